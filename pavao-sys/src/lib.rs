@@ -190,6 +190,8 @@ pub type smbc_get_auth_data_with_context_fn = Option<
         pwlen: c_int,
     ),
 >;
+pub type smbc_debug_callback_fn =
+    option::Option<extern "C" fn(private_ptr: *mut c_void, level: c_int, msg: *const c_char)>;
 pub type smbc_list_print_job_fn = option::Option<extern "C" fn(i: *mut print_job_info)>;
 pub type smbc_check_server_fn =
     option::Option<extern "C" fn(c: *mut SMBCCTX, srv: *mut SMBCSRV) -> c_int>;
@@ -439,7 +441,13 @@ impl default::Default for _smbc_options {
 
 #[link(name = "smbclient")]
 extern "C" {
+    pub fn smbc_getDebug(c: *mut SMBCCTX) -> c_int;
     pub fn smbc_setDebug(c: *mut SMBCCTX, debug: c_int);
+    pub fn smbc_setLogCallback(
+        c: *mut SMBCCTX,
+        private_ptr: *mut c_void,
+        _fn: smbc_debug_callback_fn,
+    );
     pub fn smbc_getNetbiosName(c: *mut SMBCCTX) -> *mut c_char;
     pub fn smbc_setNetbiosName(c: *mut SMBCCTX, netbios_name: *mut c_char);
     pub fn smbc_getWorkgroup(c: *mut SMBCCTX) -> *mut c_char;
@@ -451,6 +459,7 @@ extern "C" {
     pub fn smbc_setOptionDebugToStderr(c: *mut SMBCCTX, b: smbc_bool);
     pub fn smbc_setOptionOpenShareMode(c: *mut SMBCCTX, share_mode: smbc_share_mode);
     pub fn smbc_setOptionSmbEncryptionLevel(c: *mut SMBCCTX, level: smbc_smb_encrypt_level);
+    pub fn smbc_getOptionCaseSensitive(c: *mut SMBCCTX) -> smbc_bool;
     pub fn smbc_setOptionCaseSensitive(c: *mut SMBCCTX, b: smbc_bool);
     pub fn smbc_setOptionBrowseMaxLmbCount(c: *mut SMBCCTX, count: c_int);
     pub fn smbc_setOptionUrlEncodeReaddirEntries(c: *mut SMBCCTX, b: smbc_bool);
@@ -459,6 +468,12 @@ extern "C" {
     pub fn smbc_setOptionFallbackAfterKerberos(c: *mut SMBCCTX, b: smbc_bool);
     pub fn smbc_setOptionNoAutoAnonymousLogin(c: *mut SMBCCTX, b: smbc_bool);
     pub fn smbc_setOptionUseCCache(c: *mut SMBCCTX, b: smbc_bool);
+    pub fn smbc_setOptionProtocols(
+        c: *mut SMBCCTX,
+        min_proto: *const c_char,
+        max_proto: *const c_char,
+    ) -> smbc_bool;
+    pub fn smbc_setConfiguration(c: *mut SMBCCTX, file: *const c_char) -> c_int;
     pub fn smbc_setFunctionAuthDataWithContext(
         c: *mut SMBCCTX,
         _fn: smbc_get_auth_data_with_context_fn,
@@ -479,9 +494,18 @@ extern "C" {
     pub fn smbc_getFunctionMkdir(c: *mut SMBCCTX) -> smbc_mkdir_fn;
     pub fn smbc_getFunctionRmdir(c: *mut SMBCCTX) -> smbc_rmdir_fn;
     pub fn smbc_getFunctionChmod(c: *mut SMBCCTX) -> smbc_chmod_fn;
+    pub fn smbc_getFunctionUtimes(c: *mut SMBCCTX) -> smbc_utimes_fn;
+    pub fn smbc_getFunctionSetxattr(c: *mut SMBCCTX) -> smbc_setxattr_fn;
+    pub fn smbc_getFunctionGetxattr(c: *mut SMBCCTX) -> smbc_getxattr_fn;
     pub fn smbc_getFunctionPrintFile(c: *mut SMBCCTX) -> smbc_print_file_fn;
+    pub fn smbc_getFunctionCheckServer(c: *mut SMBCCTX) -> smbc_check_server_fn;
+    pub fn smbc_getFunctionRemoveUnusedServer(c: *mut SMBCCTX) -> smbc_remove_unused_server_fn;
+    pub fn smbc_getFunctionGetCachedServer(c: *mut SMBCCTX) -> smbc_get_cached_srv_fn;
+    pub fn smbc_getFunctionRemoveCachedServer(c: *mut SMBCCTX) -> smbc_remove_cached_srv_fn;
+    pub fn smbc_getFunctionPurgeCachedServers(c: *mut SMBCCTX) -> smbc_purge_cached_fn;
     pub fn smbc_new_context() -> *mut SMBCCTX;
     pub fn smbc_free_context(context: *mut SMBCCTX, shutdown_ctx: c_int) -> c_int;
     pub fn smbc_init_context(context: *mut SMBCCTX) -> *mut SMBCCTX;
     pub fn smbc_version() -> *const c_char;
+    pub fn smbc_thread_posix();
 }
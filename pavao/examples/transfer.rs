@@ -1,9 +1,5 @@
-use std::fs::File;
-use std::io;
-use std::path::Path;
-
 use argh::FromArgs;
-use pavao::{SmbClient, SmbCredentials, SmbOpenOptions, SmbOptions};
+use pavao::{SmbClient, SmbCredentials, SmbOptions, SmbOverwritePolicy, SmbTransferOptions};
 
 #[derive(FromArgs)]
 #[argh(description = "
@@ -46,16 +42,17 @@ fn main() {
         SmbOptions::default().one_share_per_server(true),
     )
     .unwrap();
-    // Open file to read
-    let mut reader = File::open(Path::new(args.input.as_str())).unwrap();
-    // Open file to write
-    let mut writer = client
-        .open_with(
+    // upload file
+    let report = client
+        .upload(
+            args.input,
             args.output,
-            SmbOpenOptions::default().create(true).write(true),
+            &SmbTransferOptions::default()
+                .overwrite(SmbOverwritePolicy::Always)
+                .progress(|p| println!("{}: {}/{} bytes", p.path, p.transferred, p.total)),
         )
         .unwrap();
-    assert!(io::copy(&mut reader, &mut writer).is_ok());
+    println!("uploaded {} bytes", report.bytes);
 }
 
 /// Read a secret from tty with customisable prompt
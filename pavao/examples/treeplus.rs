@@ -1,7 +1,5 @@
-use std::path::PathBuf;
-
 use argh::FromArgs;
-use pavao::{SmbClient, SmbCredentials, SmbDirentInfo, SmbDirentType, SmbOptions};
+use pavao::{SmbClient, SmbCredentials, SmbDirentInfo, SmbOptions};
 
 #[derive(FromArgs)]
 #[argh(description = "
@@ -40,7 +38,7 @@ fn main() {
         SmbOptions::default().one_share_per_server(true),
     )
     .unwrap();
-    treeplus(&client, "/DCIM", 0);
+    treeplus(&client, "/DCIM");
 }
 
 /// Read a secret from tty with customisable prompt
@@ -48,24 +46,13 @@ fn read_secret_from_tty(prompt: &str) -> std::io::Result<String> {
     rpassword::prompt_password(prompt)
 }
 
-fn treeplus(client: &SmbClient, uri: &str, depth: usize) {
-    let vec = client.list_dirplus(uri).unwrap();
-    for entityplus in vec.into_iter() {
-        let entityplus_uri = entityplus_uri(&entityplus, uri);
-        print_entry_plus(&entityplus, depth);
-        // if is dir, iter directory
-        if entityplus.get_type() == SmbDirentType::Dir {
-            treeplus(client, entityplus_uri.as_str(), depth + 1)
-        }
+fn treeplus(client: &SmbClient, uri: &str) {
+    for entry in client.walk(uri).sort_by_name() {
+        let entry = entry.unwrap();
+        print_entry_plus(entry.info(), entry.depth() - 1);
     }
 }
 
-fn entityplus_uri(entity: &SmbDirentInfo, path: &str) -> String {
-    let mut p = PathBuf::from(path);
-    p.push(PathBuf::from(entity.name()));
-    p.as_path().to_string_lossy().to_string()
-}
-
 fn print_entry_plus(entityplus: &SmbDirentInfo, depth: usize) {
     println!(
         "{}{:32}\t{}\t{:x}\t{:?}",
@@ -17,12 +17,56 @@ pub enum SmbError {
     BadFileDescriptor,
     #[error("server returned with a bad value")]
     BadValue,
+    #[error("bad option: {0}")]
+    BadOption(String),
+    #[error("bad pattern: {0}")]
+    BadPattern(String),
+    #[error("connection error: {0}")]
+    Connection(SmbConnectionError),
     #[error("IO Error: {0}")]
     Io(IoError),
     #[error("bad path: {0}")]
     NulInPath(NulError),
     #[error("mutex error")]
     Mutex,
+    #[error("failed to remove {} entries", .0.len())]
+    PartialRemoval(Vec<(String, SmbError)>),
+    #[error("conflicting changes on {} files", .0.len())]
+    SyncConflict(Vec<String>),
+}
+
+/// Reason why a client couldn't connect to the server, returned by [`crate::SmbClient::connect`]
+#[derive(Debug, Error)]
+pub enum SmbConnectionError {
+    #[error("failed to resolve {0}")]
+    Dns(String),
+    #[error("failed to connect to {0}: {1}")]
+    Tcp(String, IoError),
+    #[error("authentication refused by {0}")]
+    Auth(String),
+    #[error("share {0} not found")]
+    ShareNotFound(String),
+    #[error("{0} can't meet the security policy of the client")]
+    Policy(String),
+}
+
+impl PartialEq for SmbConnectionError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Dns(e), Self::Dns(e2)) => e == e2,
+            (Self::Tcp(e, io), Self::Tcp(e2, io2)) => e == e2 && io.kind() == io2.kind(),
+            (Self::Auth(e), Self::Auth(e2)) => e == e2,
+            (Self::ShareNotFound(e), Self::ShareNotFound(e2)) => e == e2,
+            (Self::Policy(e), Self::Policy(e2)) => e == e2,
+            (_, _) => false,
+        }
+    }
+}
+
+impl From<SmbConnectionError> for SmbError {
+    fn from(e: SmbConnectionError) -> Self {
+        Self::Connection(e)
+    }
 }
 
 impl PartialEq for SmbError {
@@ -30,8 +74,14 @@ impl PartialEq for SmbError {
         match (self, other) {
             (Self::BadFileDescriptor, Self::BadFileDescriptor) => true,
             (Self::BadValue, Self::BadValue) => true,
+            (Self::BadOption(e), Self::BadOption(e2)) => e == e2,
+            (Self::BadPattern(e), Self::BadPattern(e2)) => e == e2,
+            (Self::Connection(e), Self::Connection(e2)) => e == e2,
             (Self::Io(io), Self::Io(io2)) => io.kind() == io2.kind(),
             (Self::NulInPath(e), Self::NulInPath(e2)) => e == e2,
+            (Self::Mutex, Self::Mutex) => true,
+            (Self::PartialRemoval(e), Self::PartialRemoval(e2)) => e == e2,
+            (Self::SyncConflict(e), Self::SyncConflict(e2)) => e == e2,
             (_, _) => false,
         }
     }
@@ -56,10 +56,19 @@ mod test;
 pub(crate) mod utils;
 
 // -- exports
-pub use error::{SmbError, SmbResult};
+pub use error::{SmbConnectionError, SmbError, SmbResult};
+#[cfg(feature = "tokio")]
+pub use smb::{AsyncSmbClient, AsyncSmbDirStream, AsyncSmbFile};
 pub use smb::{
-    SmbClient, SmbCredentials, SmbDirent, SmbDirentInfo, SmbDirentType, SmbEncryptionLevel,
-    SmbFile, SmbMode, SmbModeClass, SmbOpenOptions, SmbOptions, SmbShareMode, SmbStat, SmbStatVfs,
+    SmbAtomicFile, SmbAtomicOptions, SmbClient, SmbClientPool, SmbConflictPolicy, SmbCopyOptions,
+    SmbCopyReport, SmbCredentials, SmbDeletePolicy, SmbDirent, SmbDirentInfo, SmbDirentType,
+    SmbEncryptionLevel, SmbFile, SmbHashRecord, SmbKeepalive, SmbMode, SmbModeClass,
+    SmbOpenOptions, SmbOptions, SmbOverwritePolicy, SmbParallelHash, SmbParallelTransfer,
+    SmbParallelWalk, SmbParallelWalkIter, SmbPoolMetrics, SmbPooledClient, SmbProtocol,
+    SmbServerInfo, SmbShare, SmbShareInfo, SmbShareMode, SmbShareType, SmbSigning, SmbStat,
+    SmbStatVfs, SmbSyncAction, SmbSyncActionKind, SmbSyncDirection, SmbSyncOptions, SmbSyncPlan,
+    SmbSyncReason, SmbSyncReport, SmbTransferOptions, SmbTransferProgress, SmbTransferReport,
+    SmbWalkDir, SmbWalkEntry, SmbWalkErrorPolicy, SmbWalkIter, SmbWorkgroupInfo,
 };
 
 // -- mock
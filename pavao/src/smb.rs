@@ -2,11 +2,25 @@
 //!
 //! module which exposes the smb types and client
 
+#[cfg(feature = "tokio")]
+mod async_client;
+mod atomic;
 mod auth_service;
 mod client;
+mod connect;
+mod copy;
+mod dir;
+mod glob;
+mod hash;
+mod pattern;
+mod session;
+mod sync;
+mod transfer;
 mod types;
 
 // -- priv
+#[cfg(feature = "tokio")]
+pub use async_client::{AsyncSmbClient, AsyncSmbDirStream};
 use auth_service::AuthService;
 pub use client::SmbClient;
 pub use types::*;
@@ -0,0 +1,316 @@
+//! # Async client
+//!
+//! async client running the operations of [`SmbClient`] on the tokio blocking pool
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use super::{
+    AsyncSmbFile, SmbClient, SmbCredentials, SmbDirent, SmbDirentInfo, SmbMode, SmbOpenOptions,
+    SmbOptions, SmbStat, SmbTransferOptions, SmbTransferReport,
+};
+use crate::{SmbError, SmbResult};
+
+/// Amount of entries buffered by directory streams
+const DIR_STREAM_BUFFER: usize = 64;
+
+/// Async smb client.
+///
+/// Each operation runs the blocking [`SmbClient`] on the tokio blocking pool, so it doesn't stall the runtime.
+/// Operations on the same client are still serialized by the client context; use one client per concurrent task,
+/// or a [`crate::SmbClientPool`], to run them in parallel.
+///
+/// If a future is dropped before completing, the operation still completes on the blocking pool and
+/// any file handle it opened is closed.
+#[derive(Clone)]
+pub struct AsyncSmbClient {
+    client: Arc<SmbClient>,
+}
+
+impl From<SmbClient> for AsyncSmbClient {
+    fn from(client: SmbClient) -> Self {
+        Self::from(Arc::new(client))
+    }
+}
+
+impl From<Arc<SmbClient>> for AsyncSmbClient {
+    fn from(client: Arc<SmbClient>) -> Self {
+        Self { client }
+    }
+}
+
+impl AsyncSmbClient {
+    /// Initialize a new `AsyncSmbClient` with the provided credentials to connect to the remote smb server
+    pub fn new(credentials: SmbCredentials, options: SmbOptions) -> SmbResult<Self> {
+        SmbClient::new(credentials, options).map(Self::from)
+    }
+
+    /// Get the underlying blocking client
+    pub fn client(&self) -> &Arc<SmbClient> {
+        &self.client
+    }
+
+    /// Run `f` with the blocking client on the tokio blocking pool
+    pub async fn run<T, F>(&self, f: F) -> SmbResult<T>
+    where
+        F: FnOnce(&SmbClient) -> SmbResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let client = self.client.clone();
+        tokio::task::spawn_blocking(move || f(&client))
+            .await
+            .map_err(|err| SmbError::Io(io::Error::other(err)))?
+    }
+
+    /// Get file stat at `path`
+    pub async fn stat<S>(&self, path: S) -> SmbResult<SmbStat>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        self.run(move |client| client.stat(path)).await
+    }
+
+    /// Returns whether a file or a directory exists at `path`
+    pub async fn exists<S>(&self, path: S) -> SmbResult<bool>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        self.run(move |client| client.exists(path)).await
+    }
+
+    /// Make directory at `path` with provided `mode`
+    pub async fn mkdir<S>(&self, path: S, mode: SmbMode) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        self.run(move |client| client.mkdir(path, mode)).await
+    }
+
+    /// Make directory at `path` with provided `mode`, creating all the missing parent directories
+    pub async fn create_dir_all<S>(&self, path: S, mode: SmbMode) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        self.run(move |client| client.create_dir_all(path, mode))
+            .await
+    }
+
+    /// Remove the empty directory at `path`
+    pub async fn rmdir<S>(&self, path: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        self.run(move |client| client.rmdir(path)).await
+    }
+
+    /// Remove directory at `path` and all of its content
+    pub async fn remove_dir_all<S>(&self, path: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        self.run(move |client| client.remove_dir_all(path)).await
+    }
+
+    /// Unlink file at `path`
+    pub async fn unlink<S>(&self, path: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        self.run(move |client| client.unlink(path)).await
+    }
+
+    /// Rename file at `orig_url` to `new_url`
+    pub async fn rename<S>(&self, orig_url: S, new_url: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        let orig_url = orig_url.as_ref().to_string();
+        let new_url = new_url.as_ref().to_string();
+        self.run(move |client| client.rename(orig_url, new_url))
+            .await
+    }
+
+    /// List the content of the directory at `path` as a stream
+    pub fn list_dir<S>(&self, path: S) -> AsyncSmbDirStream<SmbDirent>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        AsyncSmbDirStream::spawn(self.client.clone(), move |client| client.list_dir(path))
+    }
+
+    /// List the content of the directory at `path`, with metadata, as a stream
+    pub fn list_dirplus<S>(&self, path: S) -> AsyncSmbDirStream<SmbDirentInfo>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        AsyncSmbDirStream::spawn(self.client.clone(), move |client| client.list_dirplus(path))
+    }
+
+    /// Open a file at `path` with provided options
+    pub async fn open_with<S>(&self, path: S, options: SmbOpenOptions) -> SmbResult<AsyncSmbFile>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref().to_string();
+        let client = self.client.clone();
+        tokio::task::spawn_blocking(move || client.open_owned(path, options))
+            .await
+            .map_err(|err| SmbError::Io(io::Error::other(err)))?
+            .map(AsyncSmbFile::new)
+    }
+
+    /// Upload the local file at `local` to `remote`
+    pub async fn upload<P, S>(
+        &self,
+        local: P,
+        remote: S,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let local: PathBuf = local.as_ref().to_path_buf();
+        let remote = remote.as_ref().to_string();
+        let options = options.clone();
+        self.run(move |client| client.upload(local, remote, &options))
+            .await
+    }
+
+    /// Download the remote file at `remote` to `local`
+    pub async fn download<S, P>(
+        &self,
+        remote: S,
+        local: P,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        S: AsRef<str>,
+        P: AsRef<Path>,
+    {
+        let remote = remote.as_ref().to_string();
+        let local: PathBuf = local.as_ref().to_path_buf();
+        let options = options.clone();
+        self.run(move |client| client.download(remote, local, &options))
+            .await
+    }
+}
+
+/// Stream of the entries of a directory.
+///
+/// The directory is listed on the blocking pool; dropping the stream stops sending the remaining entries.
+pub struct AsyncSmbDirStream<T> {
+    rx: mpsc::Receiver<SmbResult<T>>,
+}
+
+impl<T> AsyncSmbDirStream<T>
+where
+    T: Send + 'static,
+{
+    fn spawn<F>(client: Arc<SmbClient>, list: F) -> Self
+    where
+        F: FnOnce(&SmbClient) -> SmbResult<Vec<T>> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(DIR_STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || match list(&client) {
+            Ok(entries) => {
+                for entry in entries {
+                    if tx.blocking_send(Ok(entry)).is_err() {
+                        trace!("directory stream dropped");
+                        break;
+                    }
+                }
+            }
+            Err(err) => {
+                let _ = tx.blocking_send(Err(err));
+            }
+        });
+        Self { rx }
+    }
+}
+
+impl<T> Stream for AsyncSmbDirStream<T> {
+    type Item = SmbResult<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::future::poll_fn;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    #[test]
+    #[serial]
+    fn should_run_operations_asynchronously() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let client = AsyncSmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        runtime().block_on(async move {
+            client
+                .create_dir_all("/cargo-test/a/b", SmbMode::from(0o755))
+                .await
+                .unwrap();
+            assert!(client.exists("/cargo-test/a/b").await.unwrap());
+            client
+                .mkdir("/cargo-test/a/c", SmbMode::from(0o755))
+                .await
+                .unwrap();
+            let mut stream = client.list_dir("/cargo-test/a");
+            let mut names = Vec::new();
+            while let Some(entry) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                names.push(entry.unwrap().name().to_string());
+            }
+            names.sort();
+            assert_eq!(names, vec![String::from("b"), String::from("c")]);
+            client.rmdir("/cargo-test/a/c").await.unwrap();
+            client.remove_dir_all("/cargo-test/a").await.unwrap();
+            assert!(!client.exists("/cargo-test/a").await.unwrap());
+        });
+    }
+
+    #[test]
+    #[serial]
+    fn should_stream_listing_errors() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let client = AsyncSmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        runtime().block_on(async move {
+            let mut stream = client.list_dirplus("/cargo-test/missing");
+            let entry = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+            assert!(matches!(entry, Some(Err(_))));
+            assert!(poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))
+                .await
+                .is_none());
+        });
+    }
+
+    /// The test container can't be started from within a runtime, so tests run their own
+    pub(crate) fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Runtime::new().unwrap()
+    }
+}
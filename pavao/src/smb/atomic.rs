@@ -0,0 +1,244 @@
+//! # Atomic
+//!
+//! atomic replacement of files on the share
+
+use std::io::Write;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{SmbAtomicFile, SmbAtomicOptions, SmbClient, SmbOpenOptions};
+use crate::{SmbError, SmbResult};
+
+/// Counter making temporary names unique within the process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl SmbClient {
+    /// Replace the content of the file at `path` with `bytes`: readers either see the previous content
+    /// or the new one, never a partially written file.
+    ///
+    /// Mind that the replacement is not atomic when `path` already exists: see [`SmbClient::atomic_writer`]
+    pub fn write_atomic<S>(&self, path: S, bytes: &[u8]) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        self.write_atomic_with(path, bytes, &SmbAtomicOptions::default())
+    }
+
+    /// Replace the content of the file at `path` with `bytes` atomically, with the provided options
+    pub fn write_atomic_with<S>(
+        &self,
+        path: S,
+        bytes: &[u8],
+        options: &SmbAtomicOptions,
+    ) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        let mut writer = self.atomic_writer(path, options)?;
+        writer.write_all(bytes)?;
+        writer.commit()
+    }
+
+    /// Open a writer which replaces the file at `path` atomically once committed.
+    ///
+    /// Content is written to a hidden temporary file in the same directory, which is then renamed over the target.
+    /// The temporary file is removed if the writer is dropped without being committed.
+    ///
+    /// # Non-atomic window
+    ///
+    /// libsmbclient can't rename over an existing file: it removes the target, then renames the temporary file.
+    /// Between the two requests the target doesn't exist, so a concurrent reader may find it missing, and if the
+    /// rename then fails the new content is left at [`SmbAtomicFile::temp_path`] only: the temporary file is kept
+    /// in that case, so get its path before committing. Readers never see a partially written file, though.
+    pub fn atomic_writer<S>(
+        &self,
+        path: S,
+        options: &SmbAtomicOptions,
+    ) -> SmbResult<SmbAtomicFile<'_>>
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref();
+        let temp = Self::temp_path(path)?;
+        trace!("writing {} atomically through {}", path, temp);
+        let file = self.open_with(
+            temp.as_str(),
+            SmbOpenOptions::default()
+                .write(true)
+                .create(true)
+                .exclusive(true)
+                .mode(options.mode),
+        )?;
+        Ok(SmbAtomicFile::new(
+            self,
+            file,
+            path.to_string(),
+            temp,
+            options.backup.clone(),
+        ))
+    }
+
+    /// Get a unique hidden temporary path in the same directory as `path`
+    pub(crate) fn temp_path(path: &str) -> SmbResult<String> {
+        let trimmed = path.trim_end_matches('/');
+        let (dir, name) = trimmed.rsplit_once('/').unwrap_or(("", trimmed));
+        if name.is_empty() {
+            return Err(SmbError::BadValue);
+        }
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        Ok(format!(
+            "{}/.{}.{}-{}-{}.tmp",
+            dir,
+            name,
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::SeqCst),
+            nanos
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Read;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test::TestCtx;
+    use crate::{mock, SmbMode};
+
+    #[test]
+    fn should_make_temp_path() {
+        let temp = SmbClient::temp_path("/cargo-test/config.toml").unwrap();
+        assert!(temp.starts_with("/cargo-test/.config.toml."));
+        assert!(temp.ends_with(".tmp"));
+        assert_ne!(
+            temp,
+            SmbClient::temp_path("/cargo-test/config.toml").unwrap()
+        );
+        assert!(SmbClient::temp_path("smb://localhost/temp/a.txt")
+            .unwrap()
+            .starts_with("smb://localhost/temp/.a.txt."));
+        assert!(SmbClient::temp_path("/").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn should_write_atomic() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        ctx.client
+            .write_atomic("/cargo-test/config.toml", b"version = 1\n")
+            .unwrap();
+        assert_eq!(
+            read(&ctx.client, "/cargo-test/config.toml"),
+            "version = 1\n"
+        );
+        ctx.client
+            .write_atomic_with(
+                "/cargo-test/config.toml",
+                b"version = 2\n",
+                &SmbAtomicOptions::default().backup(".bak"),
+            )
+            .unwrap();
+        assert_eq!(
+            read(&ctx.client, "/cargo-test/config.toml"),
+            "version = 2\n"
+        );
+        assert_eq!(
+            read(&ctx.client, "/cargo-test/config.toml.bak"),
+            "version = 1\n"
+        );
+        // the previous backup is replaced
+        ctx.client
+            .write_atomic_with(
+                "/cargo-test/config.toml",
+                b"version = 3\n",
+                &SmbAtomicOptions::default().backup(".bak"),
+            )
+            .unwrap();
+        assert_eq!(
+            read(&ctx.client, "/cargo-test/config.toml.bak"),
+            "version = 2\n"
+        );
+        assert_eq!(list(&ctx.client), vec!["config.toml", "config.toml.bak"]);
+    }
+
+    #[test]
+    #[serial]
+    fn should_remove_temp_file_when_not_committed() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        ctx.client
+            .write_atomic("/cargo-test/config.toml", b"version = 1\n")
+            .unwrap();
+        let mut writer = ctx
+            .client
+            .atomic_writer("/cargo-test/config.toml", &SmbAtomicOptions::default())
+            .unwrap();
+        writer.write_all(b"version = ").unwrap();
+        assert!(ctx.client.stat(writer.temp_path()).is_ok());
+        drop(writer);
+        assert_eq!(
+            read(&ctx.client, "/cargo-test/config.toml"),
+            "version = 1\n"
+        );
+        assert_eq!(list(&ctx.client), vec!["config.toml"]);
+        // the temporary file can't be created
+        let writer = ctx.client.atomic_writer(
+            "/cargo-test/missing/config.toml",
+            &SmbAtomicOptions::default(),
+        );
+        assert!(writer.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn should_keep_temp_file_when_rename_fails() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        // a non-empty directory can't be replaced by a file
+        ctx.client
+            .mkdir("/cargo-test/config", SmbMode::from(0o755))
+            .unwrap();
+        ctx.client
+            .write_atomic("/cargo-test/config/a.toml", b"version = 1\n")
+            .unwrap();
+        let mut writer = ctx
+            .client
+            .atomic_writer("/cargo-test/config", &SmbAtomicOptions::default())
+            .unwrap();
+        writer.write_all(b"version = 2\n").unwrap();
+        let temp = writer.temp_path().to_string();
+        assert!(writer.commit().is_err());
+        assert_eq!(read(&ctx.client, &temp), "version = 2\n");
+        assert!(ctx.client.stat("/cargo-test/config").unwrap().mode.is_dir());
+    }
+
+    fn read(client: &SmbClient, path: &str) -> String {
+        let mut content = String::new();
+        client
+            .open_with(path, SmbOpenOptions::default().read(true))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn list(client: &SmbClient) -> Vec<String> {
+        let mut names: Vec<String> = client
+            .list_dir("/cargo-test")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+}
@@ -22,8 +22,8 @@ impl AuthService {
         self.credentials.remove(uuid.as_ref());
     }
 
-    pub fn get<S: AsRef<str>>(&self, uuid: S) -> &SmbCredentials {
-        self.credentials.get(uuid.as_ref()).unwrap()
+    pub fn get<S: AsRef<str>>(&self, uuid: S) -> Option<&SmbCredentials> {
+        self.credentials.get(uuid.as_ref())
     }
 }
 
@@ -36,14 +36,13 @@ mod test {
     fn should_use_auth_service() {
         let mut auth_service = AuthService::default();
         auth_service.insert("test", SmbCredentials::default());
-        let _ = auth_service.get("test");
+        assert!(auth_service.get("test").is_some());
         auth_service.remove("test");
     }
 
     #[test]
-    #[should_panic]
-    fn should_panic_when_accessing_unknown_credentials() {
+    fn should_not_find_unknown_credentials() {
         let auth_service = AuthService::default();
-        auth_service.get("test");
+        assert!(auth_service.get("test").is_none());
     }
 }
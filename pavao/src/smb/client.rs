@@ -2,17 +2,21 @@
 //!
 //! module which exposes the Smb Client
 
+use std::io::Write;
 use std::ops::Deref;
-use std::sync::Mutex;
-use std::time::Duration;
+use std::panic;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Once, PoisonError};
+use std::time::{Duration, SystemTime};
 use std::{mem, sync::MutexGuard};
 
-use libc::{self, c_char, c_int};
+use libc::{self, c_char, c_int, c_void};
 use pavao_sys::{SMBCCTX, *};
 
 use super::{
-    AuthService, SmbCredentials, SmbDirentInfo, SmbFile, SmbMode, SmbOpenOptions, SmbOptions,
-    SmbStat, SmbStatVfs,
+    AuthService, FileClient, SmbCredentials, SmbDirentInfo, SmbFile, SmbMode, SmbOpenOptions,
+    SmbOptions, SmbSecurityPolicy, SmbServerInfo, SmbShare, SmbShareInfo, SmbStat, SmbStatVfs,
+    SmbWalkDir, SmbWorkgroupInfo,
 };
 use crate::{utils, SmbDirent, SmbError, SmbResult};
 
@@ -22,7 +26,10 @@ pub(crate) struct SmbContext {
 
 impl SmbContext {
     pub fn new() -> SmbResult<SmbContext> {
-        let _guard = SMBC_MUTEX.lock().unwrap();
+        // libsmbclient keeps global state (talloc stackframes, loadparm, debug), which is only
+        // thread-safe once the thread functions are set up, before any context is created
+        SMBC_THREAD_INIT.call_once(|| unsafe { smbc_thread_posix() });
+        let _guard = smbc_lock();
         let inner = unsafe { utils::result_from_ptr_mut(smbc_new_context())? };
         unsafe {
             utils::result_from_ptr_mut(smbc_init_context(inner))?;
@@ -31,6 +38,12 @@ impl SmbContext {
     }
 }
 
+// SAFETY: `smbc_thread_posix` is called before the first context is created, so the global state of
+// libsmbclient is protected by its own locks and contexts may be used on any thread; a context isn't bound
+// to the thread which created it, and it's only used through the mutex of the `SmbClient` owning it,
+// so it's never used by two threads at the same time.
+unsafe impl Send for SmbContext {}
+
 impl Deref for SmbContext {
     type Target = *mut SMBCCTX;
 
@@ -41,20 +54,89 @@ impl Deref for SmbContext {
 
 impl Drop for SmbContext {
     fn drop(&mut self) {
-        let _guard = SMBC_MUTEX.lock().unwrap();
+        let _guard = smbc_lock();
         unsafe {
             smbc_free_context(self.inner, 1_i32);
         }
     }
 }
 
+/// Extended attribute holding the DOS attributes of a file
+const DOS_ATTR_MODE_XATTR: &str = "system.dos_attr.mode";
+
 lazy_static! {
     static ref AUTH_SERVICE: Mutex<AuthService> = Mutex::new(AuthService::default());
     static ref SMBC_MUTEX: Mutex<()> = Mutex::new(());
+    static ref SECURITY_POLICY: Mutex<Option<SmbSecurityPolicy>> = Mutex::new(None);
+}
+
+/// Makes libsmbclient thread-safe before the first context is created
+static SMBC_THREAD_INIT: Once = Once::new();
+
+/// Lock the global libsmbclient mutex; it guards no data, so a poisoned lock is still usable
+fn smbc_lock() -> MutexGuard<'static, ()> {
+    SMBC_MUTEX.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Lock the auth service; credentials are only inserted and removed, so a poisoned lock is still usable
+fn auth_service() -> MutexGuard<'static, AuthService> {
+    AUTH_SERVICE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Get the security policy set for the process by the first client which set one
+pub(crate) fn security_policy() -> Option<SmbSecurityPolicy> {
+    *SECURITY_POLICY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Set the security policy of the process, unless a different one has already been set.
+/// Returns whether the policy has been set by this call.
+///
+/// libsmbclient keeps the security options in its global configuration, so a client can't have a policy of its own.
+fn claim_security_policy(options: &SmbOptions) -> SmbResult<bool> {
+    let Some(policy) = options.security_policy() else {
+        return Ok(false);
+    };
+    let mut current = SECURITY_POLICY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    match *current {
+        Some(current) if current != policy => {
+            error!(
+                "security policy {:?} conflicts with the policy of the process {:?}",
+                policy, current
+            );
+            Err(SmbError::BadOption(format!(
+                "security policy {:?} conflicts with the policy already set for the process {:?}",
+                policy, current
+            )))
+        }
+        Some(_) => Ok(false),
+        None => {
+            *current = Some(policy);
+            Ok(true)
+        }
+    }
+}
+
+/// Release the security policy claimed by a client which failed to initialize
+fn release_security_policy() {
+    debug!("releasing the security policy of the process");
+    *SECURITY_POLICY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = None;
 }
 
 /// Smb protocol client
+///
+/// Paths are relative to the share the client has been created for, but any path can also be an absolute
+/// `smb://server/share/path` uri, so a single client can address many shares and servers.
+///
+/// The client is `Send` and `Sync`: operations lock the underlying context, so they are serialized
+/// when the client is shared between threads.
 pub struct SmbClient {
+    server: String,
     uri: String,
     ctx: Mutex<SmbContext>,
 }
@@ -62,7 +144,20 @@ pub struct SmbClient {
 impl SmbClient {
     /// Initialize a new `SmbClient` with the provided credentials to connect to the remote smb server
     pub fn new(credentials: SmbCredentials, options: SmbOptions) -> SmbResult<Self> {
+        options.validate()?;
+        let claimed = claim_security_policy(&options)?;
+        Self::init(credentials, options).inspect_err(|_| {
+            // a client which failed to initialize doesn't bind the process to its policy
+            if claimed {
+                release_security_policy();
+            }
+        })
+    }
+
+    fn init(credentials: SmbCredentials, options: SmbOptions) -> SmbResult<Self> {
+        let server = credentials.server.clone();
         let uri = Self::build_uri(credentials.server.as_str(), credentials.share.as_str());
+        let connect = options.connect;
 
         trace!("creating context...");
         let ctx = SmbContext::new()?;
@@ -71,25 +166,47 @@ impl SmbClient {
         trace!("configuring client options");
         unsafe {
             smbc_setFunctionAuthDataWithContext(*ctx, Some(Self::auth_wrapper));
-            Self::setup_options(*ctx, options);
+            Self::setup_options(*ctx, options)?;
         }
 
         trace!("context initialized");
-        AUTH_SERVICE
-            .lock()
-            .unwrap()
-            .insert(Self::auth_service_uuid(*ctx), credentials);
+        auth_service().insert(Self::auth_service_uuid(*ctx), credentials);
 
-        Ok(SmbClient {
+        let client = SmbClient {
+            server,
             uri,
             ctx: Mutex::new(ctx),
-        })
+        };
+        if connect {
+            client.connect()?;
+        }
+        Ok(client)
+    }
+
+    /// Replace the credentials used to authenticate on the server; the server and the share of the client don't change.
+    ///
+    /// Established sessions stay authenticated with the previous credentials; if `purge` is set, the cached
+    /// sessions are dropped, so the next operation authenticates again. Sessions used by open files are kept
+    /// until the files are closed, in which case `EBUSY` is returned.
+    ///
+    /// The credentials are updated even when `EBUSY` is returned: only the sessions of the open files still use the
+    /// previous ones, and [`SmbClient::purge_cached_servers`] can be called again once the files are closed.
+    pub fn update_credentials(&self, credentials: SmbCredentials, purge: bool) -> SmbResult<()> {
+        trace!("updating credentials");
+        {
+            let ctx = self.ctx()?;
+            auth_service().insert(Self::auth_service_uuid(**ctx), credentials);
+        }
+        if purge {
+            self.purge_cached_servers()?;
+        }
+        Ok(())
     }
 
     /// Get netbios name from server
     pub fn get_netbios_name(&self) -> SmbResult<String> {
         trace!("getting netbios name");
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         unsafe {
             let ptr = utils::result_from_ptr_mut(smbc_getNetbiosName(**ctx))?;
             utils::char_ptr_to_string(ptr).map_err(|_| SmbError::BadValue)
@@ -102,7 +219,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("setting netbios name to {}", name.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let name = utils::str_to_cstring(name)?;
         unsafe { smbc_setNetbiosName(**ctx, name.into_raw()) }
         Ok(())
@@ -111,7 +228,7 @@ impl SmbClient {
     /// Get workgroup name from server
     pub fn get_workgroup(&self) -> SmbResult<String> {
         trace!("getting workgroup");
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         unsafe {
             let ptr = utils::result_from_ptr_mut(smbc_getWorkgroup(**ctx))?;
             utils::char_ptr_to_string(ptr).map_err(|_| SmbError::BadValue)
@@ -124,7 +241,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("configuring workgroup to {}", name.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let name = utils::str_to_cstring(name)?;
         unsafe { smbc_setWorkgroup(**ctx, name.into_raw()) }
         Ok(())
@@ -133,7 +250,7 @@ impl SmbClient {
     /// Get get_user name from server
     pub fn get_user(&self) -> SmbResult<String> {
         trace!("getting current username");
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         unsafe {
             let ptr = utils::result_from_ptr_mut(smbc_getUser(**ctx))?;
             utils::char_ptr_to_string(ptr).map_err(|_| SmbError::BadValue)
@@ -146,7 +263,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("configuring current username as {}", name.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let name = utils::str_to_cstring(name)?;
         unsafe { smbc_setUser(**ctx, name.into_raw()) }
         Ok(())
@@ -155,18 +272,43 @@ impl SmbClient {
     /// Get timeout from server
     pub fn get_timeout(&self) -> SmbResult<Duration> {
         trace!("getting timeout");
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         unsafe { Ok(Duration::from_millis(smbc_getTimeout(**ctx) as u64)) }
     }
 
     /// Set timeout to server
     pub fn set_timeout(&self, timeout: Duration) -> SmbResult<()> {
         trace!("setting timeout to {}ms", timeout.as_millis());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         unsafe { smbc_setTimeout(**ctx, timeout.as_millis() as c_int) }
         Ok(())
     }
 
+    /// Returns whether the client has been configured to be case sensitive
+    pub(crate) fn is_case_sensitive(&self) -> SmbResult<bool> {
+        let ctx = self.ctx()?;
+        Ok(unsafe { smbc_getOptionCaseSensitive(**ctx) != 0 })
+    }
+
+    /// Get libsmbclient debug level
+    pub fn get_debug_level(&self) -> SmbResult<u8> {
+        trace!("getting debug level");
+        let ctx = self.ctx()?;
+        unsafe { Ok(smbc_getDebug(**ctx).clamp(0, u8::MAX as c_int) as u8) }
+    }
+
+    /// Set libsmbclient debug level.
+    ///
+    /// Debug messages are forwarded to the logger with the `samba` target; levels above 3 are very verbose
+    /// and 10 dumps protocol diagnostics.
+    /// Mind that libsmbclient keeps the debug level globally, so it applies to all clients in the process.
+    pub fn set_debug_level(&self, level: u8) -> SmbResult<()> {
+        trace!("setting debug level to {}", level);
+        let ctx = self.ctx()?;
+        unsafe { smbc_setDebug(**ctx, level as c_int) }
+        Ok(())
+    }
+
     /// Get smbc version
     pub fn get_version(&self) -> SmbResult<String> {
         trace!("getting smb version");
@@ -182,7 +324,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("unlinking entry at {}", path.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let path = utils::str_to_cstring(self.uri(path))?;
         let unlink_fn = self.get_fn(**ctx, smbc_getFunctionUnlink)?;
         utils::to_result_with_ioerror((), unlink_fn(**ctx, path.as_ptr()))
@@ -194,7 +336,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("renaming {} to {}", orig_url.as_ref(), new_url.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let orig_url = utils::str_to_cstring(self.uri(orig_url))?;
         let new_url = utils::str_to_cstring(self.uri(new_url))?;
         let rename_fn = self.get_fn(**ctx, smbc_getFunctionRename)?;
@@ -204,51 +346,115 @@ impl SmbClient {
         )
     }
 
+    /// Rename file at `orig_url` to `new_url` on the `dest` client.
+    ///
+    /// libsmbclient only renames files within the same share, so renaming across shares or servers
+    /// fails with `EXDEV`.
+    pub fn rename_to<S>(&self, orig_url: S, dest: &SmbClient, new_url: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        if std::ptr::eq(self, dest) {
+            return self.rename(orig_url, new_url);
+        }
+        trace!(
+            "renaming {} to {} on another client",
+            orig_url.as_ref(),
+            new_url.as_ref()
+        );
+        // always lock in the same order to prevent deadlocks
+        let (ctx, dest_ctx) = if (self as *const SmbClient) < (dest as *const SmbClient) {
+            let ctx = self.ctx()?;
+            (ctx, dest.ctx()?)
+        } else {
+            let dest_ctx = dest.ctx()?;
+            (self.ctx()?, dest_ctx)
+        };
+        let orig_url = utils::str_to_cstring(self.uri(orig_url))?;
+        let new_url = utils::str_to_cstring(dest.uri(new_url))?;
+        let rename_fn = self.get_fn(**ctx, smbc_getFunctionRename)?;
+        utils::to_result_with_ioerror(
+            (),
+            rename_fn(**ctx, orig_url.as_ptr(), **dest_ctx, new_url.as_ptr()),
+        )
+    }
+
+    /// Get a handle to the share `name` on the server the client is connected to.
+    ///
+    /// The handle shares the client context and connection cache.
+    pub fn share<S>(&self, name: S) -> SmbShare<'_>
+    where
+        S: AsRef<str>,
+    {
+        SmbShare::new(self, Self::build_uri(&self.server, name.as_ref()))
+    }
+
+    /// Walk the directory tree at `path` recursively.
+    ///
+    /// Returns a builder to configure the walk, which can be iterated
+    pub fn walk<S>(&self, path: S) -> SmbWalkDir<'_>
+    where
+        S: AsRef<str>,
+    {
+        SmbWalkDir::new(self, path.as_ref().to_string())
+    }
+
     /// List content of directory at `path`
     pub fn list_dir<S>(&self, path: S) -> SmbResult<Vec<SmbDirent>>
     where
         S: AsRef<str>,
     {
         trace!("listing files at {}", path.as_ref());
-        let ctx = self.ctx.lock().unwrap();
-        let path = utils::str_to_cstring(self.uri(path))?;
-        let opendir_fn = self.get_fn(**ctx, smbc_getFunctionOpendir)?;
-        let fd = opendir_fn(**ctx, path.as_ptr());
-        if fd.is_null() {
-            error!("failed to open directory: returned a bad file descriptor");
-            return Err(SmbError::BadFileDescriptor);
-        }
-        let closedir_fn = self.get_fn(**ctx, smbc_getFunctionClosedir)?;
-        let mut entries = Vec::new();
-        let readdir_fn = self.get_fn(**ctx, smbc_getFunctionReaddir)?;
-        loop {
-            let dirent = readdir_fn(**ctx, fd);
-            if dirent.is_null() {
-                break;
-            }
-            unsafe {
-                match SmbDirent::try_from(*dirent) {
-                    Ok(dirent)
-                        if dirent.name() != "."
-                            && dirent.name() != ".."
-                            && !dirent.name().is_empty() =>
-                    {
-                        trace!("found dirent: {:?}", dirent);
-                        entries.push(dirent);
-                    }
-                    Ok(_) => {
-                        trace!("ignoring '..', '.' directories");
-                    }
-                    Err(e) => {
-                        error!("failed to decode directory entity {:?}: {}", dirent, e);
-                    }
-                }
-            }
-        }
-        trace!("decoded {} dirents", entries.len());
-        // Close directory
-        let _ = closedir_fn(**ctx, fd);
-        Ok(entries)
+        let ctx = self.ctx()?;
+        self.read_dir(**ctx, self.uri(path))
+    }
+
+    /// List workgroups found on the network.
+    ///
+    /// The amount of master browsers queried is limited by `browser_max_lmb_count` in [`SmbOptions`]
+    pub fn list_workgroups(&self) -> SmbResult<Vec<SmbWorkgroupInfo>> {
+        trace!("listing workgroups");
+        let ctx = self.ctx()?;
+        let entries = self.read_dir(**ctx, String::from("smb://"))?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|dirent| SmbWorkgroupInfo::try_from(dirent).ok())
+            .collect())
+    }
+
+    /// List servers in `workgroup`
+    pub fn list_servers<S>(&self, workgroup: S) -> SmbResult<Vec<SmbServerInfo>>
+    where
+        S: AsRef<str>,
+    {
+        trace!("listing servers in workgroup {}", workgroup.as_ref());
+        let ctx = self.ctx()?;
+        let entries = self.read_dir(**ctx, format!("smb://{}", workgroup.as_ref()))?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|dirent| SmbServerInfo::try_from(dirent).ok())
+            .collect())
+    }
+
+    /// List shares on the server the client is connected to
+    pub fn list_shares(&self) -> SmbResult<Vec<SmbShareInfo>> {
+        trace!("listing shares on {}", self.server);
+        let ctx = self.ctx()?;
+        self.read_shares(**ctx, self.server.clone())
+    }
+
+    /// List shares on `server`, which may be either a host or a `smb://` uri
+    pub fn list_server_shares<S>(&self, server: S) -> SmbResult<Vec<SmbShareInfo>>
+    where
+        S: AsRef<str>,
+    {
+        trace!("listing shares on {}", server.as_ref());
+        let ctx = self.ctx()?;
+        let uri = match server.as_ref().starts_with("smb://") {
+            true => server.as_ref().to_string(),
+            false => format!("smb://{}", server.as_ref()),
+        };
+        self.read_shares(**ctx, uri)
     }
 
     /// List content of directory with metadata at 'path'
@@ -257,13 +463,14 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("listing files with metadata at {}", path.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let path = utils::str_to_cstring(self.uri(path))?;
         let opendir_fn = self.get_fn(**ctx, smbc_getFunctionOpendir)?;
         let fd = opendir_fn(**ctx, path.as_ptr());
         if fd.is_null() {
-            error!("failed to open directory: returned a bad file descriptor");
-            return Err(SmbError::BadFileDescriptor);
+            let err = utils::last_os_error();
+            error!("failed to open directory: {}", err);
+            return Err(err);
         }
         let closedir_fn = self.get_fn(**ctx, smbc_getFunctionClosedir)?;
         let mut entries = Vec::new();
@@ -307,7 +514,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("making directory at {} with mode {:?}", p.as_ref(), mode);
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let p = utils::str_to_cstring(self.uri(p))?;
         let mkdir_fn = self.get_fn(**ctx, smbc_getFunctionMkdir)?;
         utils::to_result_with_ioerror((), mkdir_fn(**ctx, p.as_ptr(), mode.into()))
@@ -319,7 +526,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("removing directory at {}", p.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let p = utils::str_to_cstring(self.uri(p))?;
         let rmdir_fn = self.get_fn(**ctx, smbc_getFunctionRmdir)?;
         utils::to_result_with_ioerror((), rmdir_fn(**ctx, p.as_ptr()))
@@ -331,7 +538,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("Stating filesystem at {}", p.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let p = utils::str_to_cstring(self.uri(p))?;
         unsafe {
             let mut st: libc::statvfs = mem::zeroed();
@@ -351,7 +558,7 @@ impl SmbClient {
         S: AsRef<str>,
     {
         trace!("Stating file at {}", p.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let p = utils::str_to_cstring(self.uri(p))?;
         unsafe {
             let mut st: libc::stat = mem::zeroed();
@@ -365,25 +572,108 @@ impl SmbClient {
         }
     }
 
+    /// Returns whether an entry exists at `p`
+    pub fn exists<S>(&self, p: S) -> SmbResult<bool>
+    where
+        S: AsRef<str>,
+    {
+        match self.stat(p) {
+            Ok(_) => Ok(true),
+            Err(SmbError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
     /// Change file mode for file at `p`
     pub fn chmod<S>(&self, p: S, mode: SmbMode) -> SmbResult<()>
     where
         S: AsRef<str>,
     {
         trace!("changing mode for {} with {:?}", p.as_ref(), mode);
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let p = utils::str_to_cstring(self.uri(p))?;
         let chmod_fn = self.get_fn(**ctx, smbc_getFunctionChmod)?;
         utils::to_result_with_ioerror((), chmod_fn(**ctx, p.as_ptr(), mode.into()))
     }
 
+    /// Set access and modification times for file at `p`
+    pub fn utimes<S>(&self, p: S, accessed: SystemTime, modified: SystemTime) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        trace!(
+            "setting times for {} to {:?}, {:?}",
+            p.as_ref(),
+            accessed,
+            modified
+        );
+        let ctx = self.ctx()?;
+        let p = utils::str_to_cstring(self.uri(p))?;
+        let utimes_fn = self.get_fn(**ctx, smbc_getFunctionUtimes)?;
+        let mut times = [
+            utils::system_time_to_timeval(accessed),
+            utils::system_time_to_timeval(modified),
+        ];
+        utils::to_result_with_ioerror((), utimes_fn(**ctx, p.as_ptr(), times.as_mut_ptr()))
+    }
+
+    /// Get DOS attributes (`FILE_ATTRIBUTE_*`) of file at `p`
+    pub fn get_dos_attributes<S>(&self, p: S) -> SmbResult<u16>
+    where
+        S: AsRef<str>,
+    {
+        trace!("getting DOS attributes for {}", p.as_ref());
+        let ctx = self.ctx()?;
+        let p = utils::str_to_cstring(self.uri(p))?;
+        let name = utils::str_to_cstring(DOS_ATTR_MODE_XATTR)?;
+        let getxattr_fn = self.get_fn(**ctx, smbc_getFunctionGetxattr)?;
+        let mut value = [0u8; 64];
+        let len = getxattr_fn(
+            **ctx,
+            p.as_ptr(),
+            name.as_ptr(),
+            value.as_mut_ptr() as *const c_void,
+            value.len() - 1,
+        );
+        utils::to_result_with_ioerror((), len)?;
+        let value = utils::char_ptr_to_string(value.as_ptr() as *const c_char)?;
+        u16::from_str_radix(value.trim().trim_start_matches("0x"), 16).map_err(|_| {
+            error!("bad DOS attributes value: {}", value);
+            SmbError::BadValue
+        })
+    }
+
+    /// Set DOS attributes (`FILE_ATTRIBUTE_*`) of file at `p`
+    pub fn set_dos_attributes<S>(&self, p: S, attrs: u16) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        trace!("setting DOS attributes for {} to {:#x}", p.as_ref(), attrs);
+        let ctx = self.ctx()?;
+        let p = utils::str_to_cstring(self.uri(p))?;
+        let name = utils::str_to_cstring(DOS_ATTR_MODE_XATTR)?;
+        let value = format!("{:#x}", attrs);
+        let setxattr_fn = self.get_fn(**ctx, smbc_getFunctionSetxattr)?;
+        utils::to_result_with_ioerror(
+            (),
+            setxattr_fn(
+                **ctx,
+                p.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                0,
+            ),
+        )
+    }
+
     /// Print file at `p` using the `print_queue`
     pub fn print<S>(&self, p: S, print_queue: S) -> SmbResult<()>
     where
         S: AsRef<str>,
     {
         trace!("printing {} to {} queue", p.as_ref(), print_queue.as_ref());
-        let ctx = self.ctx.lock().unwrap();
+        let ctx = self.ctx()?;
         let p = utils::str_to_cstring(self.uri(p))?;
         let print_queue = utils::str_to_cstring(self.uri(print_queue))?;
         let print_fn = self.get_fn(**ctx, smbc_getFunctionPrintFile)?;
@@ -393,7 +683,7 @@ impl SmbClient {
     // -- internal private
 
     /// Build connection uri
-    fn build_uri(server: &str, share: &str) -> String {
+    pub(crate) fn build_uri(server: &str, share: &str) -> String {
         format!(
             "{}{}{}",
             server,
@@ -405,12 +695,72 @@ impl SmbClient {
         )
     }
 
-    /// Get file uri
-    fn uri<S>(&self, p: S) -> String
+    /// Get the uri of the server
+    pub(crate) fn server(&self) -> &str {
+        self.server.as_str()
+    }
+
+    /// Get file uri; absolute `smb://` uris are returned as they are
+    pub(crate) fn uri<S>(&self, p: S) -> String
     where
         S: AsRef<str>,
     {
-        format!("{}{}", self.uri, p.as_ref())
+        match p.as_ref().starts_with("smb://") {
+            true => p.as_ref().to_string(),
+            false => format!("{}{}", self.uri, p.as_ref()),
+        }
+    }
+
+    /// Read directory entries at `uri`
+    fn read_dir(&self, ctx: *mut SMBCCTX, uri: String) -> SmbResult<Vec<SmbDirent>> {
+        let uri = utils::str_to_cstring(uri)?;
+        let opendir_fn = self.get_fn(ctx, smbc_getFunctionOpendir)?;
+        let fd = opendir_fn(ctx, uri.as_ptr());
+        if fd.is_null() {
+            let err = utils::last_os_error();
+            error!("failed to open directory: {}", err);
+            return Err(err);
+        }
+        let closedir_fn = self.get_fn(ctx, smbc_getFunctionClosedir)?;
+        let mut entries = Vec::new();
+        let readdir_fn = self.get_fn(ctx, smbc_getFunctionReaddir)?;
+        loop {
+            let dirent = readdir_fn(ctx, fd);
+            if dirent.is_null() {
+                break;
+            }
+            unsafe {
+                match SmbDirent::try_from(*dirent) {
+                    Ok(dirent)
+                        if dirent.name() != "."
+                            && dirent.name() != ".."
+                            && !dirent.name().is_empty() =>
+                    {
+                        trace!("found dirent: {:?}", dirent);
+                        entries.push(dirent);
+                    }
+                    Ok(_) => {
+                        trace!("ignoring '..', '.' directories");
+                    }
+                    Err(e) => {
+                        error!("failed to decode directory entity {:?}: {}", dirent, e);
+                    }
+                }
+            }
+        }
+        trace!("decoded {} dirents", entries.len());
+        // Close directory
+        let _ = closedir_fn(ctx, fd);
+        Ok(entries)
+    }
+
+    /// Read shares exposed by server at `uri`
+    fn read_shares(&self, ctx: *mut SMBCCTX, uri: String) -> SmbResult<Vec<SmbShareInfo>> {
+        let entries = self.read_dir(ctx, uri)?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|dirent| SmbShareInfo::try_from(dirent).ok())
+            .collect())
     }
 
     /// Callback getter
@@ -424,10 +774,12 @@ impl SmbClient {
     }
 
     /// Setup options in the context
-    unsafe fn setup_options(ctx: *mut SMBCCTX, options: SmbOptions) {
+    unsafe fn setup_options(ctx: *mut SMBCCTX, options: SmbOptions) -> SmbResult<()> {
         smbc_setOptionBrowseMaxLmbCount(ctx, options.browser_max_lmb_count);
         smbc_setOptionCaseSensitive(ctx, options.case_sensitive as i32);
         smbc_setOptionDebugToStderr(ctx, 0);
+        #[cfg(not(feature = "debug"))]
+        smbc_setLogCallback(ctx, std::ptr::null_mut(), Some(Self::log_wrapper));
         smbc_setOptionFallbackAfterKerberos(ctx, options.fallback_after_kerberos as i32);
         smbc_setOptionNoAutoAnonymousLogin(ctx, options.no_auto_anonymous_login as i32);
         smbc_setOptionOneSharePerServer(ctx, options.one_share_per_server as i32);
@@ -440,6 +792,93 @@ impl SmbClient {
         smbc_setOptionDebugToStderr(ctx, 1 as i32);
         #[cfg(feature = "debug")]
         smbc_setDebug(ctx, 10);
+        if let Some(path) = options.configuration_file.as_deref() {
+            Self::load_configuration_file(ctx, path)?;
+        }
+        Self::setup_protocols(ctx, &options)?;
+        Self::setup_configuration(ctx, options.smb_conf_params())
+    }
+
+    /// Setup the protocol range allowed for negotiation.
+    ///
+    /// libsmbclient stores the range in its global configuration, so options without a range keep
+    /// the one set by previous clients. See [`claim_security_policy`]
+    unsafe fn setup_protocols(ctx: *mut SMBCCTX, options: &SmbOptions) -> SmbResult<()> {
+        if options.min_protocol.is_none() && options.max_protocol.is_none() {
+            return Ok(());
+        }
+        let min_protocol = options
+            .min_protocol
+            .map(|p| utils::str_to_cstring(p.as_str()))
+            .transpose()?;
+        let max_protocol = options
+            .max_protocol
+            .map(|p| utils::str_to_cstring(p.as_str()))
+            .transpose()?;
+        trace!(
+            "setting protocol range to {:?}..{:?}",
+            options.min_protocol,
+            options.max_protocol
+        );
+        let _guard = smbc_lock();
+        if smbc_setOptionProtocols(
+            ctx,
+            min_protocol
+                .as_ref()
+                .map(|p| p.as_ptr())
+                .unwrap_or(std::ptr::null()),
+            max_protocol
+                .as_ref()
+                .map(|p| p.as_ptr())
+                .unwrap_or(std::ptr::null()),
+        ) == 0
+        {
+            error!("libsmbclient refused protocol range");
+            return Err(SmbError::BadOption(format!(
+                "protocol range {:?}..{:?} refused by libsmbclient",
+                options.min_protocol, options.max_protocol
+            )));
+        }
+        Ok(())
+    }
+
+    /// Load smb.conf `params` into the libsmbclient configuration.
+    ///
+    /// Since libsmbclient only reads the configuration from a file, the parameters are written
+    /// to a temporary file, only readable by the user and with an unpredictable name, which is removed once loaded.
+    unsafe fn setup_configuration(
+        ctx: *mut SMBCCTX,
+        params: Vec<(String, String)>,
+    ) -> SmbResult<()> {
+        if params.is_empty() {
+            return Ok(());
+        }
+        let mut conf = String::from("[global]\n");
+        for (key, value) in params.iter() {
+            trace!("setting configuration parameter {} = {}", key, value);
+            conf.push_str(&format!("\t{} = {}\n", key, value));
+        }
+        let mut file = tempfile::Builder::new()
+            .prefix("pavao-")
+            .suffix(".conf")
+            .tempfile()?;
+        file.write_all(conf.as_bytes())?;
+        file.flush()?;
+        // the file is removed when dropped
+        Self::load_configuration_file(ctx, file.path())
+    }
+
+    /// Load smb.conf file at `path` into the libsmbclient configuration
+    unsafe fn load_configuration_file(ctx: *mut SMBCCTX, path: &Path) -> SmbResult<()> {
+        trace!("loading configuration from {}", path.display());
+        let path = utils::str_to_cstring(path.to_string_lossy())?;
+        let _guard = smbc_lock();
+        if smbc_setConfiguration(ctx, path.as_ptr()) < 0 {
+            error!("failed to load configuration: {}", utils::last_os_error());
+            Err(utils::last_os_error())
+        } else {
+            Ok(())
+        }
     }
 
     /// Auth wrapper passed to `SMBCCTX` to authenticate requests to SMB servers.
@@ -454,28 +893,67 @@ impl SmbClient {
         pw: *mut c_char,
         pwlen: c_int,
     ) {
-        unsafe {
+        let result = panic::catch_unwind(|| unsafe {
             let srv = utils::cstr(srv);
             let shr = utils::cstr(shr);
             trace!("authenticating on {}\\{}", &srv, &shr);
-            let creds = AUTH_SERVICE
-                .lock()
-                .unwrap()
-                .get(Self::auth_service_uuid(ctx))
-                .clone();
+            let creds = auth_service().get(Self::auth_service_uuid(ctx)).cloned();
+            if creds.is_none() {
+                error!("no credentials found for context {:?}", ctx);
+            }
+            creds
+        });
+        // without credentials, empty ones are reported, so authentication fails
+        let creds = match result {
+            Ok(creds) => creds.unwrap_or_default(),
+            Err(_) => {
+                error!("authentication callback panicked");
+                SmbCredentials::default()
+            }
+        };
+        unsafe {
             utils::write_to_cstr(wg as *mut u8, wglen as usize, &creds.workgroup);
             utils::write_to_cstr(un as *mut u8, unlen as usize, &creds.username);
             utils::write_to_cstr(pw as *mut u8, pwlen as usize, &creds.password);
         }
     }
 
+    /// Log callback passed to libsmbclient to forward debug messages to the logger
+    #[cfg_attr(feature = "debug", allow(dead_code))]
+    extern "C" fn log_wrapper(_private_ptr: *mut c_void, level: c_int, msg: *const c_char) {
+        // a panicking logger must not unwind into libsmbclient
+        let _ = panic::catch_unwind(|| {
+            let msg = unsafe { utils::cstr(msg) };
+            let msg = msg.trim_end();
+            if !msg.is_empty() {
+                log!(target: "samba", Self::log_level(level), "{}", msg);
+            }
+        });
+    }
+
+    /// Map samba debug level to log level
+    fn log_level(level: c_int) -> log::Level {
+        match level {
+            i32::MIN..=0 => log::Level::Error,
+            1 => log::Level::Warn,
+            2 => log::Level::Info,
+            3..=5 => log::Level::Debug,
+            _ => log::Level::Trace,
+        }
+    }
+
+    /// Get the credentials of the client owning `ctx`
+    pub(crate) fn stored_credentials(ctx: *mut SMBCCTX) -> Option<SmbCredentials> {
+        auth_service().get(Self::auth_service_uuid(ctx)).cloned()
+    }
+
     fn auth_service_uuid(ctx: *mut SMBCCTX) -> String {
         format!("{:?}", ctx)
     }
 
     /// Get underlying context
-    pub(crate) fn ctx(&self) -> MutexGuard<'_, SmbContext> {
-        self.ctx.lock().unwrap()
+    pub(crate) fn ctx(&self) -> SmbResult<MutexGuard<'_, SmbContext>> {
+        self.ctx.lock().map_err(|_| SmbError::Mutex)
     }
 }
 
@@ -486,8 +964,25 @@ impl<'a> SmbClient {
         path: P,
         options: SmbOpenOptions,
     ) -> SmbResult<SmbFile<'a>> {
-        trace!("opening {} with {:?}", path.as_ref(), options);
-        let ctx = self.ctx.lock().unwrap();
+        self.open_fd(path.as_ref(), &options)
+            .map(|fd| SmbFile::new(FileClient::Borrowed(self), fd, &options))
+    }
+
+    /// Open a file at `P` with provided options, returning a handle which owns a reference to the client.
+    ///
+    /// The handle is `'static`, so it can be stored in structs or moved to other threads.
+    pub fn open_owned<P: AsRef<str>>(
+        self: &Arc<Self>,
+        path: P,
+        options: SmbOpenOptions,
+    ) -> SmbResult<SmbFile<'static>> {
+        self.open_fd(path.as_ref(), &options)
+            .map(|fd| SmbFile::new(FileClient::Owned(self.clone()), fd, &options))
+    }
+
+    fn open_fd(&self, path: &str, options: &SmbOpenOptions) -> SmbResult<*mut SMBCFILE> {
+        trace!("opening {} with {:?}", path, options);
+        let ctx = self.ctx()?;
         let open_fn = self.get_fn(**ctx, smbc_getFunctionOpen)?;
         let path = utils::str_to_cstring(self.uri(path))?;
         let fd = utils::result_from_ptr_mut(open_fn(
@@ -501,7 +996,7 @@ impl<'a> SmbClient {
             Err(SmbError::BadFileDescriptor)
         } else {
             trace!("opened file with file descriptor {:?}", fd);
-            Ok(SmbFile::new(self, fd))
+            Ok(fd)
         }
     }
 }
@@ -510,18 +1005,15 @@ impl<'a> SmbClient {
 impl Drop for SmbClient {
     fn drop(&mut self) {
         trace!("removing uri from auth service");
-        let ctx = self.ctx.lock().unwrap();
-        AUTH_SERVICE
-            .lock()
-            .unwrap()
-            .remove(Self::auth_service_uuid(**ctx));
+        let ctx = self.ctx.lock().unwrap_or_else(PoisonError::into_inner);
+        auth_service().remove(Self::auth_service_uuid(**ctx));
         trace!("smbclient context freed");
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::io::{Cursor, Read};
+    use std::io::{self, Cursor, Read};
     use std::time::UNIX_EPOCH;
 
     use pretty_assertions::{assert_eq, assert_ne};
@@ -529,7 +1021,7 @@ mod test {
 
     use super::*;
     use crate::test::TestCtx;
-    use crate::{mock, SmbDirentType};
+    use crate::{mock, SmbDirentType, SmbProtocol, SmbShareType, SmbSigning};
 
     #[test]
     #[serial]
@@ -599,6 +1091,27 @@ mod test {
         assert_eq!(ctx.client.get_timeout().unwrap(), Duration::from_secs(3));
     }
 
+    #[test]
+    #[serial]
+    fn should_set_debug_level() {
+        mock::logger();
+        let ctx = init_ctx();
+        assert!(ctx.client.set_debug_level(3).is_ok());
+        assert_eq!(ctx.client.get_debug_level().unwrap(), 3);
+        assert!(ctx.client.list_dir("/cargo-test").is_ok());
+        assert!(ctx.client.set_debug_level(0).is_ok());
+    }
+
+    #[test]
+    fn should_map_samba_debug_level_to_log_level() {
+        assert_eq!(SmbClient::log_level(0), log::Level::Error);
+        assert_eq!(SmbClient::log_level(1), log::Level::Warn);
+        assert_eq!(SmbClient::log_level(2), log::Level::Info);
+        assert_eq!(SmbClient::log_level(3), log::Level::Debug);
+        assert_eq!(SmbClient::log_level(5), log::Level::Debug);
+        assert_eq!(SmbClient::log_level(10), log::Level::Trace);
+    }
+
     #[test]
     #[serial]
     fn should_get_version() {
@@ -651,6 +1164,40 @@ mod test {
         assert_eq!(jfk.get_type(), SmbDirentType::Dir);
     }
 
+    #[test]
+    #[serial]
+    fn should_not_list_missing_dir() {
+        mock::logger();
+        let ctx = init_ctx();
+        let err = ctx.client.list_dir("/cargo-test/nope").unwrap_err();
+        assert!(matches!(err, SmbError::Io(err) if err.kind() == io::ErrorKind::NotFound));
+        let err = ctx.client.list_dirplus("/cargo-test/nope").unwrap_err();
+        assert!(matches!(err, SmbError::Io(err) if err.kind() == io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    #[serial]
+    fn should_list_shares() {
+        mock::logger();
+        let ctx = init_ctx();
+        let shares = ctx.client.list_shares().unwrap();
+        let temp = shares.iter().find(|s| s.name() == "temp").unwrap();
+        assert_eq!(temp.get_type(), SmbShareType::File);
+        assert_eq!(temp.is_hidden(), false);
+        let ipc = shares.iter().find(|s| s.name() == "IPC$").unwrap();
+        assert_eq!(ipc.get_type(), SmbShareType::Ipc);
+        assert_eq!(ipc.is_hidden(), true);
+    }
+
+    #[test]
+    #[serial]
+    fn should_list_server_shares() {
+        mock::logger();
+        let ctx = init_ctx();
+        let shares = ctx.client.list_server_shares(&ctx.client.server).unwrap();
+        assert!(shares.iter().any(|s| s.name() == "temp"));
+    }
+
     #[test]
     #[serial]
     fn should_list_dirplus() {
@@ -725,6 +1272,16 @@ mod test {
         assert_eq!(file.size, 14);
     }
 
+    #[test]
+    #[serial]
+    fn should_check_whether_file_exists() {
+        mock::logger();
+        let ctx = init_ctx();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        assert_eq!(ctx.client.exists("/cargo-test/test").unwrap(), true);
+        assert_eq!(ctx.client.exists("/cargo-test/nope").unwrap(), false);
+    }
+
     #[test]
     #[serial]
     fn should_chmod() {
@@ -735,6 +1292,37 @@ mod test {
         let _ = ctx.client.chmod("/cargo-test/test", SmbMode::from(0o755)); // NOTE: may not be supported by the server
     }
 
+    #[test]
+    #[serial]
+    fn should_utimes() {
+        mock::logger();
+        let ctx = init_ctx();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        let t = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        assert!(ctx.client.utimes("/cargo-test/test", t, t).is_ok());
+        assert_eq!(ctx.client.stat("/cargo-test/test").unwrap().modified, t);
+    }
+
+    #[test]
+    #[serial]
+    fn should_get_and_set_dos_attributes() {
+        mock::logger();
+        let ctx = init_ctx();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        assert!(ctx.client.get_dos_attributes("/cargo-test/test").is_ok());
+        // NOTE: may not be supported by the server
+        if ctx
+            .client
+            .set_dos_attributes("/cargo-test/test", 0x01)
+            .is_ok()
+        {
+            assert_eq!(
+                ctx.client.get_dos_attributes("/cargo-test/test").unwrap() & 0x01,
+                0x01
+            );
+        }
+    }
+
     #[test]
     #[serial]
     fn should_build_uri() {
@@ -744,6 +1332,58 @@ mod test {
         assert!(ctx.client.uri("/test").as_str().ends_with("/temp/test"));
     }
 
+    #[test]
+    #[serial]
+    fn should_build_absolute_uri() {
+        mock::logger();
+        let ctx = init_ctx();
+
+        assert_eq!(
+            ctx.client.uri("smb://localhost/other/test").as_str(),
+            "smb://localhost/other/test"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_stat_absolute_uri() {
+        mock::logger();
+        let ctx = init_ctx();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        let uri = ctx.client.uri("/cargo-test/test");
+        assert_eq!(ctx.client.stat(uri).unwrap().size, 14);
+    }
+
+    #[test]
+    #[serial]
+    fn should_rename_to_another_client() {
+        mock::logger();
+        let ctx = init_ctx();
+        let other = SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        assert!(ctx
+            .client
+            .rename_to("/cargo-test/test", &other, "/cargo-test/new")
+            .is_ok());
+        assert_eq!(ctx.client.exists("/cargo-test/test").unwrap(), false);
+        let mut output = String::default();
+        other
+            .open_with("/cargo-test/new", SmbOpenOptions::default().read(true))
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output.as_str(), "Hello, World!\n");
+        // renaming across shares is not supported
+        let other_share =
+            SmbClient::new(ctx.credentials().share("/other"), SmbOptions::default()).unwrap();
+        let err = ctx
+            .client
+            .rename_to("/cargo-test/new", &other_share, "/new")
+            .unwrap_err();
+        assert!(matches!(err, SmbError::Io(err) if err.raw_os_error() == Some(libc::EXDEV)));
+        assert_eq!(ctx.client.exists("/cargo-test/new").unwrap(), true);
+    }
+
     #[test]
     #[serial]
     fn should_read_file() {
@@ -776,7 +1416,8 @@ mod test {
             .unwrap();
         let mut reader = Cursor::new("test string\n".as_bytes());
         assert_eq!(std::io::copy(&mut reader, &mut writer).unwrap(), 12);
-        drop(writer);
+        assert!(writer.close().is_ok());
+        assert_eq!(ctx.client.stat("/cargo-test/test").unwrap().size, 12);
     }
 
     #[test]
@@ -807,6 +1448,222 @@ mod test {
         assert_eq!(output.as_str(), "Hello, World!\nBonjour\n");
     }
 
+    #[test]
+    #[serial]
+    fn should_update_credentials() {
+        mock::logger();
+        let ctx = init_ctx();
+        let client = SmbClient::new(
+            ctx.credentials(),
+            SmbOptions::default().no_auto_anonymous_login(true),
+        )
+        .unwrap();
+        assert!(client.connect().is_ok());
+        // established sessions are kept unless purged
+        client
+            .update_credentials(ctx.credentials().password("wrong"), false)
+            .unwrap();
+        assert!(client.stat("/cargo-test").is_ok());
+        client
+            .update_credentials(ctx.credentials().password("wrong"), true)
+            .unwrap();
+        assert!(client.connect().is_err());
+        client.update_credentials(ctx.credentials(), true).unwrap();
+        assert!(client.stat("/cargo-test").is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn should_update_credentials_with_open_files() {
+        mock::logger();
+        let ctx = init_ctx();
+        let client = SmbClient::new(
+            ctx.credentials(),
+            SmbOptions::default().no_auto_anonymous_login(true),
+        )
+        .unwrap();
+        create_file_at(&client, "/cargo-test/test", "Hello, World!\n");
+        let file = client
+            .open_with("/cargo-test/test", SmbOpenOptions::default().read(true))
+            .unwrap();
+        // the session of the open file is kept, but the credentials are updated anyway
+        let err = client
+            .update_credentials(ctx.credentials().password("wrong"), true)
+            .unwrap_err();
+        assert!(matches!(err, SmbError::Io(err) if err.raw_os_error() == Some(libc::EBUSY)));
+        drop(file);
+        client.purge_cached_servers().unwrap();
+        assert!(client.connect().is_err());
+        client.update_credentials(ctx.credentials(), true).unwrap();
+        assert!(client.stat("/cargo-test").is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn should_connect_with_security_policy() {
+        mock::logger();
+        let ctx = init_ctx();
+        let options = SmbOptions::default()
+            .min_protocol(SmbProtocol::Smb2_02)
+            .max_protocol(SmbProtocol::Smb3_11)
+            .signing(SmbSigning::Required)
+            .ntlmv2_only(true);
+        let client = SmbClient::new(
+            ctx.credentials(),
+            options
+                .clone()
+                .case_sensitive(true)
+                .one_share_per_server(true),
+        )
+        .unwrap();
+        assert!(client.list_dir("/cargo-test").is_ok());
+        // the policy applies to the whole process
+        assert!(SmbClient::new(ctx.credentials(), options).is_ok());
+        assert!(SmbClient::new(ctx.credentials(), SmbOptions::default()).is_ok());
+        assert!(matches!(
+            SmbClient::new(
+                ctx.credentials(),
+                SmbOptions::default().signing(SmbSigning::Off)
+            ),
+            Err(SmbError::BadOption(_))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn should_release_security_policy_of_failed_client() {
+        mock::logger();
+        let ctx = init_ctx();
+        let previous = SECURITY_POLICY.lock().unwrap().take();
+        assert!(SmbClient::new(
+            ctx.credentials().password("wrong"),
+            SmbOptions::default()
+                .no_auto_anonymous_login(true)
+                .signing(SmbSigning::Required)
+                .connect(true),
+        )
+        .is_err());
+        assert_eq!(security_policy(), None);
+        assert!(SmbClient::new(
+            ctx.credentials(),
+            SmbOptions::default().signing(SmbSigning::Off)
+        )
+        .is_ok());
+        *SECURITY_POLICY.lock().unwrap() = previous;
+    }
+
+    #[test]
+    #[serial]
+    fn should_connect_with_configuration() {
+        mock::logger();
+        let ctx = init_ctx();
+        let client = SmbClient::new(
+            ctx.credentials(),
+            SmbOptions::default()
+                .case_sensitive(true)
+                .one_share_per_server(true)
+                .configuration("name resolve order", "host bcast")
+                .configuration("socket options", "TCP_NODELAY"),
+        )
+        .unwrap();
+        assert!(client.list_dir("/cargo-test").is_ok());
+    }
+
+    #[test]
+    fn should_not_create_client_with_unknown_configuration() {
+        assert!(SmbClient::new(
+            SmbCredentials::default(),
+            SmbOptions::default().configuration("foo bar", "baz"),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn should_not_create_client_with_bad_protocol_range() {
+        assert!(SmbClient::new(
+            SmbCredentials::default(),
+            SmbOptions::default()
+                .min_protocol(SmbProtocol::Smb3_11)
+                .max_protocol(SmbProtocol::Smb2_02),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn should_be_send_and_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+        assert_send::<SmbClient>();
+        assert_sync::<SmbClient>();
+        assert_send::<Arc<SmbClient>>();
+        assert_send::<SmbFile<'static>>();
+        assert_sync::<SmbFile<'static>>();
+    }
+
+    #[test]
+    #[serial]
+    fn should_share_client_between_threads() {
+        mock::logger();
+        let ctx = init_ctx();
+        create_file_at(&ctx.client, "/cargo-test/test.txt", "Hello, World!\n");
+        let client = Arc::new(SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap());
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let client = client.clone();
+                std::thread::spawn(move || client.stat("/cargo-test/test.txt").unwrap().size)
+            })
+            .collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), 14);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn should_run_operations_on_several_clients_concurrently() {
+        mock::logger();
+        let ctx = init_ctx();
+        create_file_at(&ctx.client, "/cargo-test/test.txt", "Hello, World!\n");
+        let workers: Vec<_> = (0..2)
+            .map(|i| {
+                let client = SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+                std::thread::spawn(move || {
+                    let dir = format!("/cargo-test/worker-{}", i);
+                    for _ in 0..16 {
+                        client.mkdir(&dir, SmbMode::from(0o755)).unwrap();
+                        assert_eq!(client.stat("/cargo-test/test.txt").unwrap().size, 14);
+                        client.rmdir(&dir).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn should_move_owned_file_to_another_thread() {
+        mock::logger();
+        let ctx = init_ctx();
+        create_file_at(&ctx.client, "/cargo-test/test.txt", "Hello, World!\n");
+        let client = Arc::new(SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap());
+        let mut file = client
+            .open_owned("/cargo-test/test.txt", SmbOpenOptions::default().read(true))
+            .unwrap();
+        // the file keeps the client alive
+        drop(client);
+        let content = std::thread::spawn(move || {
+            let mut content = String::new();
+            file.read_to_string(&mut content).unwrap();
+            content
+        })
+        .join()
+        .unwrap();
+        assert_eq!(content.as_str(), "Hello, World!\n");
+    }
+
     fn init_ctx() -> TestCtx {
         TestCtx::default()
     }
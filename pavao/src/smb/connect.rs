@@ -0,0 +1,315 @@
+//! # Connect
+//!
+//! eager connection to the server, telling apart why it failed
+
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use pavao_sys::{smbc_getFunctionClosedir, smbc_getFunctionOpendir};
+
+use super::client::security_policy;
+use super::SmbClient;
+use crate::{utils, SmbConnectionError, SmbError, SmbResult};
+
+/// Ports tried when the server uri doesn't specify one
+const DEFAULT_PORTS: [u16; 2] = [445, 139];
+/// Connect timeout used when the client has no timeout set
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+impl SmbClient {
+    /// Connect to the server, authenticate and open the root of the share.
+    ///
+    /// Clients connect lazily, so unreachable hosts, bad passwords and wrong shares are otherwise only reported
+    /// by the first operation. On failure, a [`SmbError::Connection`] tells why the client couldn't connect.
+    ///
+    /// The server name is resolved with the system resolver; names it can't resolve, such as NetBIOS names,
+    /// are left to libsmbclient and reported as a DNS failure if it can't connect either.
+    pub fn connect(&self) -> SmbResult<()> {
+        let (host, port) = Self::server_address(self.server());
+        let share = self.share_name();
+        trace!("connecting to {} (share {:?})", host, share);
+        let addrs: Vec<SocketAddr> = match (host.as_str(), 0).to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(err) => {
+                debug!("failed to resolve {}: {}", host, err);
+                Vec::new()
+            }
+        };
+        let resolved = !addrs.is_empty();
+        if resolved {
+            let ports = port
+                .map(|port| vec![port])
+                .unwrap_or(DEFAULT_PORTS.to_vec());
+            let timeout = match self.get_timeout()? {
+                Duration::ZERO => DEFAULT_CONNECT_TIMEOUT,
+                timeout => timeout,
+            };
+            Self::probe_tcp(&host, &addrs, &ports, timeout)?;
+        }
+        self.open_root().map_err(|err| {
+            let err = Self::connection_error(err, &host, &share, resolved);
+            error!("failed to connect to {}: {}", host, err);
+            err
+        })?;
+        debug!("connected to {}", host);
+        Ok(())
+    }
+
+    /// Check whether the server accepts TCP connections on any of `ports`
+    fn probe_tcp(
+        host: &str,
+        addrs: &[SocketAddr],
+        ports: &[u16],
+        timeout: Duration,
+    ) -> SmbResult<()> {
+        let mut last_err = None;
+        for port in ports {
+            for addr in addrs {
+                let addr = SocketAddr::new(addr.ip(), *port);
+                match TcpStream::connect_timeout(&addr, timeout) {
+                    Ok(_) => {
+                        trace!("{} accepts connections", addr);
+                        return Ok(());
+                    }
+                    Err(err) => {
+                        debug!("failed to connect to {}: {}", addr, err);
+                        last_err = Some(err);
+                    }
+                }
+            }
+        }
+        let err = last_err.unwrap_or_else(|| std::io::Error::other("no address to connect to"));
+        error!("failed to connect to {}: {}", host, err);
+        Err(SmbConnectionError::Tcp(host.to_string(), err).into())
+    }
+
+    /// Open and close the root directory of the share, which authenticates on the server
+    pub(crate) fn open_root(&self) -> SmbResult<()> {
+        let ctx = self.ctx()?;
+        let uri = utils::str_to_cstring(self.uri(""))?;
+        let opendir_fn = self.get_fn(**ctx, smbc_getFunctionOpendir)?;
+        let fd = opendir_fn(**ctx, uri.as_ptr());
+        if fd.is_null() {
+            return Err(utils::last_os_error());
+        }
+        let closedir_fn = self.get_fn(**ctx, smbc_getFunctionClosedir)?;
+        let _ = closedir_fn(**ctx, fd);
+        Ok(())
+    }
+
+    /// Tell apart why the share root couldn't be opened
+    fn connection_error(err: SmbError, host: &str, share: &str, resolved: bool) -> SmbError {
+        Self::connection_error_with_policy(err, host, share, resolved, security_policy().is_some())
+    }
+
+    /// Tell apart why the share root couldn't be opened; with a security policy set, failures to negotiate
+    /// are reported as the server not meeting it
+    fn connection_error_with_policy(
+        err: SmbError,
+        host: &str,
+        share: &str,
+        resolved: bool,
+        policy: bool,
+    ) -> SmbError {
+        let SmbError::Io(io) = err else {
+            return err;
+        };
+        match io.raw_os_error() {
+            Some(libc::EACCES | libc::EPERM) => SmbConnectionError::Auth(host.to_string()).into(),
+            _ if !resolved => SmbConnectionError::Dns(host.to_string()).into(),
+            Some(libc::ENOTSUP | libc::EPROTONOSUPPORT | libc::EPROTO | libc::ECONNABORTED)
+                if policy =>
+            {
+                SmbConnectionError::Policy(host.to_string()).into()
+            }
+            Some(libc::ENOENT | libc::ENODEV) if !share.is_empty() => {
+                SmbConnectionError::ShareNotFound(share.to_string()).into()
+            }
+            Some(
+                libc::ECONNREFUSED
+                | libc::ECONNRESET
+                | libc::ETIMEDOUT
+                | libc::EHOSTUNREACH
+                | libc::ENETUNREACH,
+            ) => SmbConnectionError::Tcp(host.to_string(), io).into(),
+            _ => SmbError::Io(io),
+        }
+    }
+
+    /// Get the name of the share the client has been created for
+    pub(crate) fn share_name(&self) -> String {
+        self.uri("")
+            .strip_prefix(self.server())
+            .unwrap_or_default()
+            .trim_matches('/')
+            .to_string()
+    }
+
+    /// Get host and port from a `smb://[user@]host[:port]` server uri
+    pub(crate) fn server_address(server: &str) -> (String, Option<u16>) {
+        let authority = server.strip_prefix("smb://").unwrap_or(server);
+        let authority = authority.split('/').next().unwrap_or_default();
+        let authority = authority
+            .rsplit_once('@')
+            .map(|(_, authority)| authority)
+            .unwrap_or(authority);
+        if let Some(address) = authority.strip_prefix('[') {
+            let (host, port) = address.split_once(']').unwrap_or((address, ""));
+            return (
+                host.to_string(),
+                port.strip_prefix(':').and_then(|port| port.parse().ok()),
+            );
+        }
+        match authority.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host.to_string(), Some(port)),
+                Err(_) => (authority.to_string(), None),
+            },
+            None => (authority.to_string(), None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+    use crate::{SmbCredentials, SmbOptions};
+
+    #[test]
+    fn should_get_server_address() {
+        assert_eq!(
+            SmbClient::server_address("smb://localhost"),
+            (String::from("localhost"), None)
+        );
+        assert_eq!(
+            SmbClient::server_address("smb://localhost:3445/temp"),
+            (String::from("localhost"), Some(3445))
+        );
+        assert_eq!(
+            SmbClient::server_address("smb://WORKGROUP;user@server:139"),
+            (String::from("server"), Some(139))
+        );
+        assert_eq!(
+            SmbClient::server_address("smb://[::1]:445"),
+            (String::from("::1"), Some(445))
+        );
+        assert_eq!(
+            SmbClient::server_address("smb://[::1]"),
+            (String::from("::1"), None)
+        );
+    }
+
+    #[test]
+    fn should_tell_policy_errors_apart() {
+        let negotiation_error = || SmbError::Io(std::io::Error::from_raw_os_error(libc::ENOTSUP));
+        assert_eq!(
+            SmbClient::connection_error_with_policy(
+                negotiation_error(),
+                "localhost",
+                "temp",
+                true,
+                true
+            ),
+            SmbError::Connection(SmbConnectionError::Policy(String::from("localhost")))
+        );
+        assert_eq!(
+            SmbClient::connection_error_with_policy(
+                negotiation_error(),
+                "localhost",
+                "temp",
+                true,
+                false
+            ),
+            negotiation_error()
+        );
+        assert_eq!(
+            SmbClient::connection_error_with_policy(
+                SmbError::Io(std::io::Error::from_raw_os_error(libc::EACCES)),
+                "localhost",
+                "temp",
+                true,
+                true
+            ),
+            SmbError::Connection(SmbConnectionError::Auth(String::from("localhost")))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_connect() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        assert!(ctx.client.connect().is_ok());
+        assert!(SmbClient::new(ctx.credentials(), SmbOptions::default().connect(true)).is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn should_fail_authentication() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let err = SmbClient::new(
+            ctx.credentials().password("wrong"),
+            SmbOptions::default()
+                .no_auto_anonymous_login(true)
+                .connect(true),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err,
+            SmbError::Connection(SmbConnectionError::Auth(String::from("localhost")))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_fail_with_missing_share() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let client =
+            SmbClient::new(ctx.credentials().share("/missing"), SmbOptions::default()).unwrap();
+        assert_eq!(
+            client.connect().err().unwrap(),
+            SmbError::Connection(SmbConnectionError::ShareNotFound(String::from("missing")))
+        );
+    }
+
+    #[test]
+    fn should_fail_resolving_server() {
+        mock::logger();
+        let client = SmbClient::new(
+            SmbCredentials::default()
+                .server("smb://pavao.invalid")
+                .share("/temp"),
+            SmbOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            client.connect().err().unwrap(),
+            SmbError::Connection(SmbConnectionError::Dns(String::from("pavao.invalid")))
+        );
+    }
+
+    #[test]
+    fn should_fail_connecting_to_server() {
+        mock::logger();
+        let client = SmbClient::new(
+            SmbCredentials::default()
+                .server("smb://127.0.0.1:1")
+                .share("/temp"),
+            SmbOptions::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            client.connect().err().unwrap(),
+            SmbError::Connection(SmbConnectionError::Tcp(host, _)) if host == "127.0.0.1"
+        ));
+    }
+}
@@ -0,0 +1,491 @@
+//! # Copy
+//!
+//! copy and move of files and directory trees between smb clients
+
+use std::io::{self, Read, Write};
+
+use super::{SmbClient, SmbCopyOptions, SmbCopyReport, SmbOpenOptions, SmbStat};
+use crate::{utils, SmbError, SmbResult};
+
+impl SmbClient {
+    /// Copy the file or the directory tree at `src` to `dst` on the `dest` client.
+    ///
+    /// `dest` can be another client, connected to a different share or server, or the client itself.
+    /// Files are streamed through a single buffer, so memory usage doesn't depend on file sizes.
+    ///
+    /// Copying a directory into itself, or into one of its subdirectories, fails with `InvalidInput`; paths are
+    /// compared regardless of case unless both clients are case sensitive.
+    pub fn copy_to<S>(
+        &self,
+        src: S,
+        dest: &SmbClient,
+        dst: S,
+        options: &SmbCopyOptions,
+    ) -> SmbResult<SmbCopyReport>
+    where
+        S: AsRef<str>,
+    {
+        trace!("copying {} to {}", src.as_ref(), dst.as_ref());
+        let case_sensitive = self.is_case_sensitive()? && dest.is_case_sensitive()?;
+        if Self::is_within(
+            &dest.uri(dst.as_ref()),
+            &self.uri(src.as_ref()),
+            case_sensitive,
+        ) {
+            error!("cannot copy {} into itself", src.as_ref());
+            return Err(SmbError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot copy {} into itself", src.as_ref()),
+            )));
+        }
+        let mut report = SmbCopyReport::default();
+        let mut buffer = vec![0; options.buffer_size];
+        self.copy_entry(
+            src.as_ref(),
+            dest,
+            dst.as_ref(),
+            options,
+            &mut buffer,
+            &mut report,
+        )?;
+        debug!(
+            "copied {} files, {} directories and {} bytes",
+            report.files, report.directories, report.bytes
+        );
+        Ok(report)
+    }
+
+    /// Returns whether the uri `path` is `parent` or is under it, comparing whole path components
+    fn is_within(path: &str, parent: &str, case_sensitive: bool) -> bool {
+        let components = |uri: &str| -> Vec<String> {
+            uri.split('/')
+                .filter(|c| !c.is_empty())
+                .map(|c| match case_sensitive {
+                    true => c.to_string(),
+                    false => c.to_lowercase(),
+                })
+                .collect()
+        };
+        components(path).starts_with(&components(parent))
+    }
+
+    /// Move the file or the directory tree at `src` to `dst` on the `dest` client.
+    ///
+    /// A rename is tried first; if the rename is cross-share or cross-server, the entry is copied
+    /// and then removed from `src`.
+    pub fn move_to<S>(
+        &self,
+        src: S,
+        dest: &SmbClient,
+        dst: S,
+        options: &SmbCopyOptions,
+    ) -> SmbResult<SmbCopyReport>
+    where
+        S: AsRef<str>,
+    {
+        trace!("moving {} to {}", src.as_ref(), dst.as_ref());
+        if !options.overwrite && dest.exists(dst.as_ref())? {
+            return Err(Self::already_exists(dst.as_ref()));
+        }
+        match self.rename_to(src.as_ref(), dest, dst.as_ref()) {
+            Ok(()) => {
+                return Ok(SmbCopyReport {
+                    renamed: true,
+                    ..Default::default()
+                })
+            }
+            Err(SmbError::Io(err)) if err.raw_os_error() == Some(libc::EXDEV) => {
+                debug!("cannot rename {}: falling back to copy", src.as_ref());
+            }
+            Err(err) => return Err(err),
+        }
+        let report = self.copy_to(src.as_ref(), dest, dst.as_ref(), options)?;
+        self.remove_entry(src.as_ref())?;
+        Ok(report)
+    }
+
+    /// Copy entry at `src`, which can be either a file or a directory
+    fn copy_entry(
+        &self,
+        src: &str,
+        dest: &SmbClient,
+        dst: &str,
+        options: &SmbCopyOptions,
+        buffer: &mut [u8],
+        report: &mut SmbCopyReport,
+    ) -> SmbResult<()> {
+        let stat = self.stat(src)?;
+        if stat.mode.is_dir() {
+            self.copy_dir(src, &stat, dest, dst, options, buffer, report)
+        } else {
+            self.copy_file(src, &stat, dest, dst, options, buffer, report)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn copy_dir(
+        &self,
+        src: &str,
+        stat: &SmbStat,
+        dest: &SmbClient,
+        dst: &str,
+        options: &SmbCopyOptions,
+        buffer: &mut [u8],
+        report: &mut SmbCopyReport,
+    ) -> SmbResult<()> {
+        trace!("copying directory {} to {}", src, dst);
+        // list before creating `dst`, which may be under `src`
+        let entries = self.list_dir(src)?;
+        match dest.mkdir(dst, stat.mode) {
+            Ok(()) => report.directories += 1,
+            Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists => {
+                trace!("directory {} already exists", dst);
+            }
+            Err(err) => return Err(err),
+        }
+        for entry in entries {
+            self.copy_entry(
+                &utils::join_path(src, entry.name()),
+                dest,
+                &utils::join_path(dst, entry.name()),
+                options,
+                buffer,
+                report,
+            )?;
+        }
+        self.preserve_metadata(src, stat, dest, dst, options);
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn copy_file(
+        &self,
+        src: &str,
+        stat: &SmbStat,
+        dest: &SmbClient,
+        dst: &str,
+        options: &SmbCopyOptions,
+        buffer: &mut [u8],
+        report: &mut SmbCopyReport,
+    ) -> SmbResult<()> {
+        trace!("copying file {} to {}", src, dst);
+        let mut reader = self.open_with(src, SmbOpenOptions::default().read(true))?;
+        // without overwrite, the destination is created exclusively, so a file created meanwhile is kept
+        let mut writer = match dest.open_with(
+            dst,
+            SmbOpenOptions::default()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .exclusive(!options.overwrite)
+                .mode(stat.mode.into()),
+        ) {
+            Ok(writer) => writer,
+            Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Self::already_exists(dst));
+            }
+            Err(err) => return Err(err),
+        };
+        let mut bytes = 0;
+        loop {
+            let n = reader.read(buffer)?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..n])?;
+            bytes += n as u64;
+        }
+        writer.close()?;
+        drop(reader);
+        if options.verify_size {
+            let size = dest.stat(dst)?.size;
+            if size != stat.size || bytes != stat.size {
+                error!(
+                    "size mismatch copying {}: source has {} bytes, copied {}, destination has {}",
+                    src, stat.size, bytes, size
+                );
+                return Err(SmbError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "size mismatch for {}: expected {} bytes, found {}",
+                        dst, stat.size, size
+                    ),
+                )));
+            }
+        }
+        self.preserve_metadata(src, stat, dest, dst, options);
+        report.files += 1;
+        report.bytes += bytes;
+        Ok(())
+    }
+
+    /// Copy times and DOS attributes of `src` to `dst`. Failures are only logged.
+    fn preserve_metadata(
+        &self,
+        src: &str,
+        stat: &SmbStat,
+        dest: &SmbClient,
+        dst: &str,
+        options: &SmbCopyOptions,
+    ) {
+        if options.preserve_times {
+            if let Err(err) = dest.utimes(dst, stat.accessed, stat.modified) {
+                warn!("failed to preserve times for {}: {}", dst, err);
+            }
+        }
+        if options.preserve_attributes {
+            if let Err(err) = self
+                .get_dos_attributes(src)
+                .and_then(|attrs| dest.set_dos_attributes(dst, attrs))
+            {
+                warn!("failed to preserve DOS attributes for {}: {}", dst, err);
+            }
+        }
+    }
+
+    /// Remove entry at `p`, recursively if it's a directory
+    fn remove_entry(&self, p: &str) -> SmbResult<()> {
+        if self.stat(p)?.mode.is_dir() {
+            self.remove_dir_all(p)
+        } else {
+            self.unlink(p)
+        }
+    }
+
+    pub(crate) fn already_exists(p: &str) -> SmbError {
+        SmbError::Io(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", p),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test::TestCtx;
+    use crate::{mock, SmbMode, SmbOptions};
+
+    #[test]
+    #[serial]
+    fn should_copy_file_to_same_client() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        let report = ctx
+            .client
+            .copy_to(
+                "/cargo-test/test",
+                &ctx.client,
+                "/cargo-test/copy",
+                &SmbCopyOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(report.bytes, 14);
+        assert_eq!(
+            read_file_at(&ctx.client, "/cargo-test/copy"),
+            "Hello, World!\n"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_copy_tree_to_another_client() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let other = SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        ctx.client
+            .mkdir("/cargo-test/src", SmbMode::from(0o755))
+            .unwrap();
+        ctx.client
+            .mkdir("/cargo-test/src/sub", SmbMode::from(0o755))
+            .unwrap();
+        create_file_at(&ctx.client, "/cargo-test/src/a", "Hello, World!\n");
+        create_file_at(&ctx.client, "/cargo-test/src/sub/b", "Bonjour\n");
+        let report = ctx
+            .client
+            .copy_to(
+                "/cargo-test/src",
+                &other,
+                "/cargo-test/dst",
+                &SmbCopyOptions::default().buffer_size(4),
+            )
+            .unwrap();
+        assert_eq!(report.files, 2);
+        assert_eq!(report.directories, 2);
+        assert_eq!(report.bytes, 22);
+        assert_eq!(read_file_at(&other, "/cargo-test/dst/a"), "Hello, World!\n");
+        assert_eq!(read_file_at(&other, "/cargo-test/dst/sub/b"), "Bonjour\n");
+        assert_eq!(
+            other.stat("/cargo-test/dst/a").unwrap().modified,
+            ctx.client.stat("/cargo-test/src/a").unwrap().modified
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_not_copy_dir_into_itself() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        ctx.client
+            .mkdir("/cargo-test/src", SmbMode::from(0o755))
+            .unwrap();
+        create_file_at(&ctx.client, "/cargo-test/src/a", "Hello, World!\n");
+        for dst in ["/cargo-test/src", "/cargo-test/src/", "/cargo-test/src/sub"] {
+            let err = ctx
+                .client
+                .copy_to(
+                    "/cargo-test/src",
+                    &ctx.client,
+                    dst,
+                    &SmbCopyOptions::default(),
+                )
+                .unwrap_err();
+            assert!(matches!(err, SmbError::Io(err) if err.kind() == io::ErrorKind::InvalidInput));
+        }
+        let other = SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        assert!(ctx
+            .client
+            .copy_to(
+                "/cargo-test/src",
+                &other,
+                "/cargo-test/src/sub",
+                &SmbCopyOptions::default()
+            )
+            .is_err());
+        assert_eq!(ctx.client.exists("/cargo-test/src/sub").unwrap(), false);
+        // case-insensitive clients don't tell apart paths which differ in case
+        assert!(other
+            .copy_to(
+                "/cargo-test/src",
+                &other,
+                "/cargo-test/SRC/sub",
+                &SmbCopyOptions::default()
+            )
+            .is_err());
+        // a sibling sharing the prefix is fine
+        assert!(ctx
+            .client
+            .copy_to(
+                "/cargo-test/src",
+                &ctx.client,
+                "/cargo-test/src2",
+                &SmbCopyOptions::default()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn should_tell_whether_path_is_within() {
+        assert!(SmbClient::is_within("smb://a/s/src", "smb://a/s/src", true));
+        assert!(SmbClient::is_within(
+            "smb://a/s/src/",
+            "smb://a/s/src",
+            true
+        ));
+        assert!(SmbClient::is_within(
+            "smb://a/s/src/sub",
+            "smb://a/s/src/",
+            true
+        ));
+        assert!(!SmbClient::is_within(
+            "smb://a/s/src2",
+            "smb://a/s/src",
+            true
+        ));
+        assert!(!SmbClient::is_within(
+            "smb://a/s/SRC/sub",
+            "smb://a/s/src",
+            true
+        ));
+        assert!(SmbClient::is_within(
+            "smb://a/s/SRC/sub",
+            "smb://a/s/src",
+            false
+        ));
+        assert!(!SmbClient::is_within("smb://a/s", "smb://a/s/src", false));
+    }
+
+    #[test]
+    #[serial]
+    fn should_not_overwrite_on_copy() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        create_file_at(&ctx.client, "/cargo-test/copy", "Bonjour\n");
+        let err = ctx
+            .client
+            .copy_to(
+                "/cargo-test/test",
+                &ctx.client,
+                "/cargo-test/copy",
+                &SmbCopyOptions::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, SmbError::Io(err) if err.kind() == io::ErrorKind::AlreadyExists));
+        assert_eq!(read_file_at(&ctx.client, "/cargo-test/copy"), "Bonjour\n");
+        assert!(ctx
+            .client
+            .copy_to(
+                "/cargo-test/test",
+                &ctx.client,
+                "/cargo-test/copy",
+                &SmbCopyOptions::default().overwrite(true),
+            )
+            .is_ok());
+        assert_eq!(
+            read_file_at(&ctx.client, "/cargo-test/copy"),
+            "Hello, World!\n"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_move_to_another_client() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let other = SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        create_file_at(&ctx.client, "/cargo-test/test", "Hello, World!\n");
+        assert!(ctx
+            .client
+            .move_to(
+                "/cargo-test/test",
+                &other,
+                "/cargo-test/moved",
+                &SmbCopyOptions::default(),
+            )
+            .is_ok());
+        assert_eq!(ctx.client.exists("/cargo-test/test").unwrap(), false);
+        assert_eq!(read_file_at(&other, "/cargo-test/moved"), "Hello, World!\n");
+    }
+
+    fn create_file_at(client: &SmbClient, uri: &str, content: &str) {
+        let mut reader = Cursor::new(content.as_bytes());
+        let mut writer = client
+            .open_with(
+                uri,
+                SmbOpenOptions::default()
+                    .create(true)
+                    .write(true)
+                    .truncate(true),
+            )
+            .unwrap();
+        assert!(io::copy(&mut reader, &mut writer).is_ok());
+    }
+
+    fn read_file_at(client: &SmbClient, uri: &str) -> String {
+        let mut reader = client
+            .open_with(uri, SmbOpenOptions::default().read(true))
+            .unwrap();
+        let mut output = String::default();
+        reader.read_to_string(&mut output).unwrap();
+        output
+    }
+}
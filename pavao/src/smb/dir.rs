@@ -0,0 +1,285 @@
+//! # Dir
+//!
+//! recursive directory operations
+
+use std::io;
+
+use super::{SmbClient, SmbDirentInfo, SmbDirentType, SmbMode};
+use crate::{utils, SmbError, SmbResult};
+
+/// DOS Attribute mask for READONLY
+const FILE_ATTRIBUTE_READONLY: u16 = 0x0001;
+/// DOS Attribute for a file without other attributes
+const FILE_ATTRIBUTE_NORMAL: u16 = 0x0080;
+
+impl SmbClient {
+    /// Make directory at `p` with provided `mode`, creating all the missing parent directories.
+    ///
+    /// Directories which already exist, or which are created concurrently by someone else, are not an error.
+    pub fn create_dir_all<S>(&self, p: S, mode: SmbMode) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        trace!("making directory at {} and its parents", p.as_ref());
+        if self.is_dir(p.as_ref()) {
+            return Ok(());
+        }
+        let (root, path) = Self::split_share_root(p.as_ref());
+        let mut dir = root.to_string();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            dir = utils::join_path(&dir, component);
+            match self.mkdir(&dir, mode) {
+                Ok(()) => trace!("created directory {}", dir),
+                Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                // the directory may have been created meanwhile, or we're not allowed to create an existing one
+                Err(_) if self.is_dir(&dir) => {}
+                Err(err) => {
+                    error!("failed to create directory {}: {}", dir, err);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove directory at `p` and all of its content.
+    ///
+    /// Read-only entries are removed by clearing their read-only attribute.
+    /// Removal goes on when an entry can't be removed; in that case [`SmbError::PartialRemoval`] is returned
+    /// with the entries which couldn't be removed (their parent directories are kept as well).
+    /// If the directory at `p` itself can't be listed, e.g. because it doesn't exist, the error is returned as it is.
+    pub fn remove_dir_all<S>(&self, p: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        trace!("removing directory at {} and its content", p.as_ref());
+        let entries = self.list_dirplus(p.as_ref())?;
+        let mut failures = Vec::new();
+        self.remove_listed_dir(p.as_ref(), entries, &mut failures);
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            error!("failed to remove {} entries", failures.len());
+            Err(SmbError::PartialRemoval(failures))
+        }
+    }
+
+    /// Remove directory at `p` recursively, collecting failures.
+    ///
+    /// Returns whether the directory has been removed
+    fn remove_dir_content(&self, p: &str, failures: &mut Vec<(String, SmbError)>) -> bool {
+        match self.list_dirplus(p) {
+            Ok(entries) => self.remove_listed_dir(p, entries, failures),
+            Err(err) => {
+                failures.push((p.to_string(), err));
+                false
+            }
+        }
+    }
+
+    /// Remove directory at `p`, whose content is `entries`, recursively, collecting failures.
+    ///
+    /// Returns whether the directory has been removed
+    fn remove_listed_dir(
+        &self,
+        p: &str,
+        entries: Vec<SmbDirentInfo>,
+        failures: &mut Vec<(String, SmbError)>,
+    ) -> bool {
+        let mut empty = true;
+        for entry in entries {
+            let path = utils::join_path(p, entry.name());
+            if entry.get_type() == SmbDirentType::Dir {
+                empty &= self.remove_dir_content(&path, failures);
+            } else if let Err(err) = self.remove_with(&path, entry.attrs, |p| self.unlink(p)) {
+                failures.push((path, err));
+                empty = false;
+            }
+        }
+        if !empty {
+            return false;
+        }
+        let attrs = self.get_dos_attributes(p).unwrap_or_default();
+        match self.remove_with(p, attrs, |p| self.rmdir(p)) {
+            Ok(()) => true,
+            Err(err) => {
+                failures.push((p.to_string(), err));
+                false
+            }
+        }
+    }
+
+    /// Remove entry at `p` with `remove_fn`; if the entry is read-only, the attribute is cleared first
+    fn remove_with(
+        &self,
+        p: &str,
+        attrs: u16,
+        remove_fn: impl Fn(&str) -> SmbResult<()>,
+    ) -> SmbResult<()> {
+        if attrs & FILE_ATTRIBUTE_READONLY != 0 {
+            trace!("clearing read-only attribute of {}", p);
+            let attrs = match attrs & !FILE_ATTRIBUTE_READONLY {
+                0 => FILE_ATTRIBUTE_NORMAL,
+                attrs => attrs,
+            };
+            self.set_dos_attributes(p, attrs)?;
+        }
+        remove_fn(p)
+    }
+
+    /// Returns whether `p` is an existing directory
+    fn is_dir(&self, p: &str) -> bool {
+        self.stat(p).map(|s| s.mode.is_dir()).unwrap_or(false)
+    }
+
+    /// Split an absolute `smb://server/share` uri from the path in the share.
+    /// Relative paths have an empty root
+    pub(crate) fn split_share_root(p: &str) -> (&str, &str) {
+        match p.strip_prefix("smb://") {
+            Some(rest) => {
+                let root_len = rest
+                    .match_indices('/')
+                    .nth(1)
+                    .map(|(idx, _)| idx)
+                    .unwrap_or(rest.len());
+                p.split_at("smb://".len() + root_len)
+            }
+            None => ("", p),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test::TestCtx;
+    use crate::{mock, SmbOpenOptions, SmbOptions, SmbShareMode};
+
+    #[test]
+    fn should_split_share_root() {
+        assert_eq!(
+            SmbClient::split_share_root("smb://localhost/temp/a/b"),
+            ("smb://localhost/temp", "/a/b")
+        );
+        assert_eq!(
+            SmbClient::split_share_root("smb://localhost/temp"),
+            ("smb://localhost/temp", "")
+        );
+        assert_eq!(SmbClient::split_share_root("/a/b"), ("", "/a/b"));
+    }
+
+    #[test]
+    #[serial]
+    fn should_create_dir_all() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        assert!(ctx
+            .client
+            .create_dir_all("/cargo-test/a/b/c", SmbMode::from(0o755))
+            .is_ok());
+        assert!(ctx.client.stat("/cargo-test/a/b/c").unwrap().mode.is_dir());
+        // already exists
+        assert!(ctx
+            .client
+            .create_dir_all("/cargo-test/a/b/c", SmbMode::from(0o755))
+            .is_ok());
+        assert!(ctx
+            .client
+            .create_dir_all("/cargo-test/a/d", SmbMode::from(0o755))
+            .is_ok());
+        let uri = ctx.client.uri("/cargo-test/e/f");
+        assert!(ctx.client.create_dir_all(uri, SmbMode::from(0o755)).is_ok());
+        assert!(ctx.client.stat("/cargo-test/e/f").unwrap().mode.is_dir());
+    }
+
+    #[test]
+    #[serial]
+    fn should_not_create_dir_all_over_file() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        create_file_at(&ctx.client, "/cargo-test/file");
+        assert!(ctx
+            .client
+            .create_dir_all("/cargo-test/file/a", SmbMode::from(0o755))
+            .is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn should_remove_dir_all() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        ctx.client
+            .create_dir_all("/cargo-test/a/b/c", SmbMode::from(0o755))
+            .unwrap();
+        create_file_at(&ctx.client, "/cargo-test/a/file");
+        create_file_at(&ctx.client, "/cargo-test/a/b/c/file");
+        create_file_at(&ctx.client, "/cargo-test/a/b/readonly");
+        let _ = ctx
+            .client
+            .set_dos_attributes("/cargo-test/a/b/readonly", FILE_ATTRIBUTE_READONLY);
+        assert!(ctx.client.remove_dir_all("/cargo-test/a").is_ok());
+        assert_eq!(ctx.client.exists("/cargo-test/a").unwrap(), false);
+    }
+
+    #[test]
+    #[serial]
+    fn should_not_remove_missing_dir() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        match ctx.client.remove_dir_all("/cargo-test/nope") {
+            Err(SmbError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn should_report_entries_not_removed() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        ctx.client
+            .create_dir_all("/cargo-test/a/b", SmbMode::from(0o755))
+            .unwrap();
+        create_file_at(&ctx.client, "/cargo-test/a/file");
+        create_file_at(&ctx.client, "/cargo-test/a/b/locked");
+        // a file open without sharing can't be removed
+        let locker = SmbClient::new(
+            ctx.credentials(),
+            SmbOptions::default().open_share_mode(SmbShareMode::DenyAll),
+        )
+        .unwrap();
+        let file = locker
+            .open_with(
+                "/cargo-test/a/b/locked",
+                SmbOpenOptions::default().read(true),
+            )
+            .unwrap();
+        match ctx.client.remove_dir_all("/cargo-test/a") {
+            Err(SmbError::PartialRemoval(failures)) => {
+                assert_eq!(failures.len(), 1);
+                assert_eq!(failures[0].0.as_str(), "/cargo-test/a/b/locked");
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(file);
+        // what could be removed is gone; the parent directories of the failed entry are kept
+        assert_eq!(ctx.client.exists("/cargo-test/a/file").unwrap(), false);
+        assert_eq!(ctx.client.exists("/cargo-test/a/b/locked").unwrap(), true);
+        assert!(ctx.client.remove_dir_all("/cargo-test/a").is_ok());
+    }
+
+    fn create_file_at(client: &SmbClient, uri: &str) {
+        let mut reader = Cursor::new("Hello, World!\n".as_bytes());
+        let mut writer = client
+            .open_with(uri, SmbOpenOptions::default().create(true).write(true))
+            .unwrap();
+        assert!(io::copy(&mut reader, &mut writer).is_ok());
+    }
+}
@@ -0,0 +1,315 @@
+//! # Glob
+//!
+//! expansion of shell-style glob patterns against the share
+
+use std::io;
+
+use globset::{GlobBuilder, GlobMatcher};
+
+use super::{SmbClient, SmbWalkEntry};
+use crate::{SmbError, SmbResult};
+
+impl SmbClient {
+    /// Expand the shell-style `pattern` against the share, returning the matching entries sorted by path.
+    ///
+    /// `*`, `?`, `[...]` and `{a,b}` match within a path component, while a `**` component matches any
+    /// amount of directories. Only directories which can still match the pattern are listed.
+    /// Matching is case insensitive unless the client has been configured to be case sensitive.
+    pub fn glob<S>(&self, pattern: S) -> SmbResult<Vec<SmbWalkEntry>>
+    where
+        S: AsRef<str>,
+    {
+        trace!("expanding glob {}", pattern.as_ref());
+        let pattern = GlobPattern::new(pattern.as_ref(), self.is_case_sensitive()?)?;
+        let states = pattern.start();
+        let mut matches = Vec::new();
+        let relative_path = pattern.base.trim_start_matches('/');
+        let depth = relative_path.split('/').filter(|c| !c.is_empty()).count() + 1;
+        let base = format!("{}{}", pattern.root, pattern.base);
+        match self.glob_dir(&pattern, &base, relative_path, depth, &states, &mut matches) {
+            Ok(()) => {}
+            // the literal part of the pattern doesn't exist
+            Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        matches.sort_by(|a, b| a.path().cmp(b.path()));
+        debug!("glob matched {} entries", matches.len());
+        Ok(matches)
+    }
+
+    /// Match the entries of the directory at `path`, which is at `states` of the pattern
+    fn glob_dir(
+        &self,
+        pattern: &GlobPattern,
+        path: &str,
+        relative_path: &str,
+        depth: usize,
+        states: &[usize],
+        matches: &mut Vec<SmbWalkEntry>,
+    ) -> SmbResult<()> {
+        for info in self.list_dirplus(path)? {
+            let states = pattern.step(states, info.name());
+            if states.is_empty() {
+                continue;
+            }
+            let entry = SmbWalkEntry::new(path, relative_path, depth, info);
+            if entry.is_dir() && pattern.is_alive(&states) {
+                self.glob_dir(
+                    pattern,
+                    entry.path(),
+                    entry.relative_path(),
+                    depth + 1,
+                    &states,
+                    matches,
+                )?;
+            }
+            if pattern.is_match(&states) {
+                matches.push(entry);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Component of a glob pattern
+enum Component {
+    /// `**`: any amount of directories
+    AnyDirs,
+    Name(GlobMatcher),
+}
+
+/// Glob pattern split in path components.
+///
+/// Matching runs as an NFA, where each state is the index of the next component to match;
+/// the pattern matches once the index reaches the end of the components.
+struct GlobPattern {
+    /// `smb://server/share` root of absolute uris
+    root: String,
+    /// directory where the pattern stops being literal
+    base: String,
+    components: Vec<Component>,
+}
+
+impl GlobPattern {
+    fn new(pattern: &str, case_sensitive: bool) -> SmbResult<Self> {
+        let (root, path) = SmbClient::split_share_root(pattern);
+        let mut parts: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        if parts.is_empty() {
+            return Err(SmbError::BadPattern(format!("{}: empty pattern", pattern)));
+        }
+        // leading literal directories are not listed
+        let literal = parts[..parts.len() - 1]
+            .iter()
+            .take_while(|c| !Self::is_glob(c))
+            .count();
+        let base = format!("/{}", parts[..literal].join("/"));
+        let components = parts
+            .drain(literal..)
+            .map(|c| match c {
+                "**" => Ok(Component::AnyDirs),
+                c => GlobBuilder::new(c)
+                    .literal_separator(true)
+                    .case_insensitive(!case_sensitive)
+                    .build()
+                    .map(|g| Component::Name(g.compile_matcher()))
+                    .map_err(|e| SmbError::BadPattern(format!("{}: {}", pattern, e))),
+            })
+            .collect::<SmbResult<Vec<Component>>>()?;
+        Ok(Self {
+            root: root.to_string(),
+            base,
+            components,
+        })
+    }
+
+    /// States at the base directory
+    fn start(&self) -> Vec<usize> {
+        self.closure(vec![0])
+    }
+
+    /// States after matching an entry named `name`; empty if the entry can't match
+    fn step(&self, states: &[usize], name: &str) -> Vec<usize> {
+        let mut next = Vec::new();
+        for &state in states {
+            match self.components.get(state) {
+                Some(Component::AnyDirs) => next.push(state),
+                Some(Component::Name(matcher)) if matcher.is_match(name) => next.push(state + 1),
+                _ => {}
+            }
+        }
+        self.closure(next)
+    }
+
+    /// Add the states reachable skipping `**`, which also matches no directory
+    fn closure(&self, mut states: Vec<usize>) -> Vec<usize> {
+        let mut i = 0;
+        while i < states.len() {
+            let state = states[i];
+            if matches!(self.components.get(state), Some(Component::AnyDirs))
+                && !states.contains(&(state + 1))
+            {
+                states.push(state + 1);
+            }
+            i += 1;
+        }
+        states.sort_unstable();
+        states.dedup();
+        states
+    }
+
+    /// Returns whether the entry at `states` matches the whole pattern
+    fn is_match(&self, states: &[usize]) -> bool {
+        states.contains(&self.components.len())
+    }
+
+    /// Returns whether the content of the entry at `states` can match the pattern
+    fn is_alive(&self, states: &[usize]) -> bool {
+        states.iter().any(|s| *s < self.components.len())
+    }
+
+    /// Returns whether the path component contains glob syntax
+    fn is_glob(component: &str) -> bool {
+        component.contains(['*', '?', '[', '{', '\\'])
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test::TestCtx;
+    use crate::{mock, SmbMode, SmbOpenOptions, SmbOptions};
+
+    #[test]
+    fn should_split_literal_base() {
+        let pattern = GlobPattern::new("/incoming/2026-*/**/*.csv", true).unwrap();
+        assert_eq!(pattern.root.as_str(), "");
+        assert_eq!(pattern.base.as_str(), "/incoming");
+        assert_eq!(pattern.components.len(), 3);
+        let pattern = GlobPattern::new("smb://localhost/temp/a/b/c.txt", true).unwrap();
+        assert_eq!(pattern.root.as_str(), "smb://localhost/temp");
+        assert_eq!(pattern.base.as_str(), "/a/b");
+        assert_eq!(pattern.components.len(), 1);
+        let pattern = GlobPattern::new("*.txt", true).unwrap();
+        assert_eq!(pattern.base.as_str(), "/");
+        assert!(GlobPattern::new("/", true).is_err());
+        assert!(GlobPattern::new("/a[b", true).is_err());
+    }
+
+    #[test]
+    fn should_match_components() {
+        let pattern = GlobPattern::new("/incoming/2026-*/**/*.csv", true).unwrap();
+        let states = pattern.start();
+        assert_eq!(states, vec![0]);
+        assert!(pattern.step(&states, "2025-01").is_empty());
+        let states = pattern.step(&states, "2026-01");
+        assert_eq!(states, vec![1, 2]);
+        assert!(pattern.is_alive(&states));
+        assert!(!pattern.is_match(&states));
+        let file = pattern.step(&states, "data.csv");
+        assert!(pattern.is_match(&file));
+        let states = pattern.step(&states, "deep");
+        assert_eq!(states, vec![1, 2]);
+        assert!(pattern.is_match(&pattern.step(&states, "data.csv")));
+        assert!(!pattern.is_match(&pattern.step(&states, "data.txt")));
+    }
+
+    #[test]
+    fn should_match_trailing_any_dirs() {
+        let pattern = GlobPattern::new("/a/**", true).unwrap();
+        let states = pattern.start();
+        assert_eq!(states, vec![0, 1]);
+        let states = pattern.step(&states, "b");
+        assert!(pattern.is_match(&states));
+        assert!(pattern.is_alive(&states));
+    }
+
+    #[test]
+    fn should_match_case_insensitive() {
+        let pattern = GlobPattern::new("/*.CSV", false).unwrap();
+        assert!(pattern.is_match(&pattern.step(&pattern.start(), "data.csv")));
+        let pattern = GlobPattern::new("/*.CSV", true).unwrap();
+        assert!(pattern.step(&pattern.start(), "data.csv").is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn should_glob() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let matches: Vec<String> = ctx
+            .client
+            .glob("/cargo-test/incoming/2026-*/**/*.csv")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path().to_string())
+            .collect();
+        assert_eq!(
+            matches,
+            vec![
+                String::from("/cargo-test/incoming/2026-01/a.csv"),
+                String::from("/cargo-test/incoming/2026-01/deep/b.csv"),
+                String::from("/cargo-test/incoming/2026-02/d.csv"),
+            ]
+        );
+        assert!(ctx
+            .client
+            .glob("/cargo-test/outgoing/*")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn should_glob_case_insensitive() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let client = SmbClient::new(
+            ctx.credentials(),
+            SmbOptions::default().case_sensitive(false),
+        )
+        .unwrap();
+        assert_eq!(
+            client.glob("/cargo-test/incoming/*/*.CSV").unwrap().len(),
+            3
+        );
+        // the client of the test context is case sensitive
+        assert!(ctx
+            .client
+            .glob("/cargo-test/incoming/*/*.CSV")
+            .unwrap()
+            .is_empty());
+    }
+
+    fn make_tree(client: &SmbClient) {
+        for dir in [
+            "/cargo-test/incoming",
+            "/cargo-test/incoming/2025-12",
+            "/cargo-test/incoming/2026-01",
+            "/cargo-test/incoming/2026-01/deep",
+            "/cargo-test/incoming/2026-02",
+        ] {
+            client.mkdir(dir, SmbMode::from(0o755)).unwrap();
+        }
+        for file in [
+            "/cargo-test/incoming/2025-12/z.csv",
+            "/cargo-test/incoming/2026-01/a.csv",
+            "/cargo-test/incoming/2026-01/deep/b.csv",
+            "/cargo-test/incoming/2026-01/deep/c.txt",
+            "/cargo-test/incoming/2026-02/d.csv",
+        ] {
+            let mut reader = Cursor::new("Hello, World!\n".as_bytes());
+            let mut writer = client
+                .open_with(file, SmbOpenOptions::default().create(true).write(true))
+                .unwrap();
+            assert!(io::copy(&mut reader, &mut writer).is_ok());
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! # Hash
+//!
+//! streaming hash of the content of remote files
+
+use std::io::{self, Read};
+
+use digest::{Digest, Output};
+
+use super::{SmbClient, SmbHashRecord, SmbOpenOptions};
+use crate::SmbResult;
+
+impl SmbClient {
+    /// Hash the content of the file at `path` with the digest `D`.
+    ///
+    /// The file is streamed through a buffer of `buffer_size` bytes, so memory usage doesn't depend on the file size.
+    pub fn hash<D, S>(&self, path: S, buffer_size: usize) -> SmbResult<SmbHashRecord>
+    where
+        D: Digest,
+        S: AsRef<str>,
+    {
+        trace!("hashing {}", path.as_ref());
+        let mut buffer = vec![0; buffer_size.max(1)];
+        let mut reader = self.open_with(path.as_ref(), SmbOpenOptions::default().read(true))?;
+        let (size, hash) = Self::hash_reader::<D>(&mut reader, &mut buffer)?;
+        Ok(SmbHashRecord {
+            path: path.as_ref().to_string(),
+            size,
+            hash: hash.to_vec(),
+        })
+    }
+
+    /// Hash the content of `reader` with the digest `D`; returns the amount of bytes read and the digest
+    pub(crate) fn hash_reader<D>(
+        reader: &mut impl Read,
+        buffer: &mut [u8],
+    ) -> io::Result<(u64, Output<D>)>
+    where
+        D: Digest,
+    {
+        let mut hasher = D::new();
+        let size = Self::read_with(reader, buffer, |data| {
+            hasher.update(data);
+            Ok(())
+        })?;
+        Ok((size, hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    /// SHA-256 of "Hello, World!\n"
+    const HELLO_SHA256: &str = "c98c24b677eff44860afea6f493bbaec5bb1c4cbb209c6fc2bbb47f66ff2ad31";
+
+    #[test]
+    fn should_hash_reader() {
+        let mut buffer = vec![0; 4];
+        let (size, hash) = SmbClient::hash_reader::<Sha256>(
+            &mut Cursor::new("Hello, World!\n".as_bytes()),
+            &mut buffer,
+        )
+        .unwrap();
+        assert_eq!(size, 14);
+        assert_eq!(hash.to_vec(), Sha256::digest("Hello, World!\n").to_vec());
+    }
+
+    #[test]
+    #[serial]
+    fn should_hash_remote_file() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let mut writer = ctx
+            .client
+            .open_with(
+                "/cargo-test/hello.txt",
+                SmbOpenOptions::default().create(true).write(true),
+            )
+            .unwrap();
+        assert!(io::copy(&mut Cursor::new("Hello, World!\n".as_bytes()), &mut writer).is_ok());
+        drop(writer);
+        let record = ctx
+            .client
+            .hash::<Sha256, _>("/cargo-test/hello.txt", 4)
+            .unwrap();
+        assert_eq!(record.path.as_str(), "/cargo-test/hello.txt");
+        assert_eq!(record.size, 14);
+        assert_eq!(record.to_hex().as_str(), HELLO_SHA256);
+        assert!(ctx
+            .client
+            .hash::<Sha256, _>("/cargo-test/missing.txt", 4)
+            .is_err());
+    }
+}
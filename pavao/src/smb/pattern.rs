@@ -0,0 +1,193 @@
+//! # Pattern
+//!
+//! gitignore-style glob patterns matched against paths relative to a root directory
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::{SmbError, SmbResult};
+
+/// A set of gitignore-style patterns.
+///
+/// - patterns without a `/` match the name of the entry at any depth
+/// - patterns with a leading or a middle `/` are anchored to the root
+/// - patterns with a trailing `/` only match directories
+/// - patterns with a leading `!` are negated: the entries they match don't match the set,
+///   unless a later pattern matches them again; use `\!` for a leading literal `!`
+/// - an entry also matches when one of its parent directories matches, whatever the patterns for the entry
+#[derive(Debug, Clone)]
+pub(crate) struct PatternSet {
+    globs: GlobSet,
+    rules: Vec<Rule>,
+}
+
+/// How the glob at the same index of the set applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    negated: bool,
+    dir_only: bool,
+}
+
+impl PatternSet {
+    /// Compile the provided patterns
+    pub fn new<S>(patterns: &[S], case_sensitive: bool) -> SmbResult<Self>
+    where
+        S: AsRef<str>,
+    {
+        let mut globs = GlobSetBuilder::new();
+        let mut rules = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let (negated, pattern_glob) = match pattern.strip_prefix('!') {
+                Some(negated) => (true, negated),
+                None => (false, pattern),
+            };
+            if pattern_glob.is_empty() {
+                return Err(SmbError::BadPattern(format!("{}: empty pattern", pattern)));
+            }
+            let (glob, dir_only) = Self::translate(pattern_glob);
+            let glob = GlobBuilder::new(&glob)
+                .literal_separator(true)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .map_err(|e| SmbError::BadPattern(format!("{}: {}", pattern, e)))?;
+            globs.add(glob);
+            rules.push(Rule { negated, dir_only });
+        }
+        Ok(Self {
+            globs: globs
+                .build()
+                .map_err(|e| SmbError::BadPattern(e.to_string()))?,
+            rules,
+        })
+    }
+
+    /// Returns whether the set contains no pattern
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns whether `path`, relative to the root and without a leading `/`, matches any pattern
+    pub fn is_match(&self, path: &str, is_dir: bool) -> bool {
+        // parent directories
+        if path
+            .match_indices('/')
+            .any(|(idx, _)| self.matches_entry(&path[..idx], true))
+        {
+            return true;
+        }
+        self.matches_entry(path, is_dir)
+    }
+
+    /// Returns whether the last pattern matching `path` isn't negated
+    fn matches_entry(&self, path: &str, is_dir: bool) -> bool {
+        self.globs
+            .matches(path)
+            .into_iter()
+            .filter(|idx| is_dir || !self.rules[*idx].dir_only)
+            .max()
+            .map(|idx| !self.rules[idx].negated)
+            .unwrap_or(false)
+    }
+
+    /// Translate a gitignore-style pattern into a glob; returns whether the pattern only matches directories
+    fn translate(pattern: &str) -> (String, bool) {
+        let dir_only = pattern.len() > 1 && pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let glob = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{}", pattern),
+        };
+        (glob, dir_only)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn should_translate_patterns() {
+        assert_eq!(
+            PatternSet::translate("*.txt"),
+            (String::from("**/*.txt"), false)
+        );
+        assert_eq!(
+            PatternSet::translate("/*.txt"),
+            (String::from("*.txt"), false)
+        );
+        assert_eq!(
+            PatternSet::translate("docs/*.md"),
+            (String::from("docs/*.md"), false)
+        );
+        assert_eq!(
+            PatternSet::translate("target/"),
+            (String::from("**/target"), true)
+        );
+    }
+
+    #[test]
+    fn should_match_names_at_any_depth() {
+        let set = PatternSet::new(&["*.txt"], true).unwrap();
+        assert_eq!(set.is_empty(), false);
+        assert!(set.is_match("a.txt", false));
+        assert!(set.is_match("foo/bar/a.txt", false));
+        assert!(!set.is_match("a.md", false));
+        assert!(!set.is_match("A.TXT", false));
+    }
+
+    #[test]
+    fn should_match_anchored_patterns() {
+        let set = PatternSet::new(&["/*.txt", "docs/*.md"], true).unwrap();
+        assert!(set.is_match("a.txt", false));
+        assert!(!set.is_match("foo/a.txt", false));
+        assert!(set.is_match("docs/README.md", false));
+        assert!(!set.is_match("foo/docs/README.md", false));
+    }
+
+    #[test]
+    fn should_match_directories_and_their_content() {
+        let set = PatternSet::new(&["target/"], true).unwrap();
+        assert!(set.is_match("target", true));
+        assert!(!set.is_match("target", false));
+        assert!(set.is_match("foo/target/debug/a.o", false));
+    }
+
+    #[test]
+    fn should_match_case_insensitive() {
+        let set = PatternSet::new(&["*.txt"], false).unwrap();
+        assert!(set.is_match("A.TXT", false));
+    }
+
+    #[test]
+    fn should_match_negated_patterns() {
+        let set = PatternSet::new(&["*.txt", "!keep.txt"], true).unwrap();
+        assert!(set.is_match("a.txt", false));
+        assert!(!set.is_match("keep.txt", false));
+        assert!(!set.is_match("foo/keep.txt", false));
+        // the last matching pattern wins
+        let set = PatternSet::new(&["!keep.txt", "*.txt"], true).unwrap();
+        assert!(set.is_match("keep.txt", false));
+        // entries in a matching directory can't be negated
+        let set = PatternSet::new(&["/docs/", "!docs/a.md"], true).unwrap();
+        assert!(set.is_match("docs", true));
+        assert!(set.is_match("docs/a.md", false));
+        // a leading literal `!`
+        let set = PatternSet::new(&["\\!a.txt"], true).unwrap();
+        assert!(set.is_match("!a.txt", false));
+        assert!(!set.is_match("a.txt", false));
+    }
+
+    #[test]
+    fn should_fail_on_bad_pattern() {
+        assert!(PatternSet::new(&["a[b"], true).is_err());
+        assert!(matches!(
+            PatternSet::new(&["!"], true),
+            Err(SmbError::BadPattern(_))
+        ));
+        assert!(PatternSet::new::<&str>(&[], true).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+//! # Session
+//!
+//! health checks and management of the connections cached by the client context
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use pavao_sys::{
+    smbc_getFunctionCheckServer, smbc_getFunctionGetCachedServer,
+    smbc_getFunctionPurgeCachedServers, smbc_getFunctionRemoveUnusedServer, SMBCCTX, SMBCSRV,
+};
+
+use super::{SmbClient, SmbKeepalive};
+use crate::{utils, SmbError, SmbResult};
+
+/// Share libsmbclient connects to when the client isn't bound to a share
+const IPC_SHARE: &str = "IPC$";
+
+impl SmbClient {
+    /// Check whether the session with the server is alive.
+    ///
+    /// libsmbclient sends an echo request to the server at most once per timeout of the client
+    /// (see [`SmbClient::set_timeout`]); in between, only the state of the connection is checked, so a session
+    /// torn down by the server may be reported as alive until the next echo.
+    /// If there's no session yet, the client connects to the share. A dead session is dropped from the cache,
+    /// so the next operation connects again, and `ConnectionReset` is returned.
+    pub fn ping(&self) -> SmbResult<()> {
+        trace!("pinging {}", self.server());
+        let ctx = self.ctx()?;
+        let srv = self.cached_server(**ctx)?;
+        if srv.is_null() {
+            drop(ctx);
+            debug!("no session with {}: connecting", self.server());
+            return self.open_root();
+        }
+        let check_server_fn = self.get_fn(**ctx, smbc_getFunctionCheckServer)?;
+        if check_server_fn(**ctx, srv) == 0 {
+            trace!("session with {} is alive", self.server());
+            return Ok(());
+        }
+        error!("session with {} is dead: dropping it", self.server());
+        let remove_unused_server_fn = self.get_fn(**ctx, smbc_getFunctionRemoveUnusedServer)?;
+        remove_unused_server_fn(**ctx, srv);
+        Err(SmbError::Io(io::Error::from(
+            io::ErrorKind::ConnectionReset,
+        )))
+    }
+
+    /// Drop the cached connection to the share of the client, if any; returns whether there was one.
+    ///
+    /// The connection is kept, and `EBUSY` is returned, while files opened through it are still open.
+    pub fn remove_cached_server(&self) -> SmbResult<bool> {
+        trace!("removing cached connection to {}", self.server());
+        let ctx = self.ctx()?;
+        let srv = self.cached_server(**ctx)?;
+        if srv.is_null() {
+            return Ok(false);
+        }
+        let remove_unused_server_fn = self.get_fn(**ctx, smbc_getFunctionRemoveUnusedServer)?;
+        if remove_unused_server_fn(**ctx, srv) != 0 {
+            error!("connection to {} is still in use", self.server());
+            return Err(SmbError::Io(io::Error::from_raw_os_error(libc::EBUSY)));
+        }
+        debug!("removed cached connection to {}", self.server());
+        Ok(true)
+    }
+
+    /// Drop all the connections cached by the client, to any server.
+    ///
+    /// Connections still used by open files are kept, in which case `EBUSY` is returned.
+    pub fn purge_cached_servers(&self) -> SmbResult<()> {
+        trace!("purging cached connections");
+        let ctx = self.ctx()?;
+        let purge_cached_fn = self.get_fn(**ctx, smbc_getFunctionPurgeCachedServers)?;
+        if purge_cached_fn(**ctx) != 0 {
+            error!("some cached connections are still in use");
+            return Err(SmbError::Io(io::Error::from_raw_os_error(libc::EBUSY)));
+        }
+        Ok(())
+    }
+
+    /// Drop all the cached connections and connect again to the share of the client.
+    ///
+    /// See [`SmbClient::purge_cached_servers`] and [`SmbClient::connect`]
+    pub fn reconnect(&self) -> SmbResult<()> {
+        debug!("reconnecting to {}", self.server());
+        self.purge_cached_servers()?;
+        self.connect()
+    }
+
+    /// Ping the server every `interval` on a background thread, so idle sessions aren't torn down by the server.
+    ///
+    /// When the session is dead, the client connects again. The keepalive stops when the returned handle is dropped,
+    /// or once the client is dropped.
+    pub fn keepalive(self: &Arc<Self>, interval: Duration) -> SmbKeepalive {
+        SmbKeepalive::spawn(Arc::downgrade(self), interval)
+    }
+
+    /// Get the cached connection to the share of the client; null if there's none
+    fn cached_server(&self, ctx: *mut SMBCCTX) -> SmbResult<*mut SMBCSRV> {
+        let Some(credentials) = Self::stored_credentials(ctx) else {
+            return Ok(std::ptr::null_mut());
+        };
+        let share = match self.share_name() {
+            share if share.is_empty() => IPC_SHARE.to_string(),
+            share => share,
+        };
+        let share = utils::str_to_cstring(share)?;
+        let workgroup = utils::str_to_cstring(credentials.workgroup)?;
+        let username = utils::str_to_cstring(credentials.username)?;
+        let get_cached_srv_fn = self.get_fn(ctx, smbc_getFunctionGetCachedServer)?;
+        // servers may be cached either with or without their port
+        let (host, port) = Self::server_address(self.server());
+        let mut servers = vec![host.clone()];
+        if let Some(port) = port {
+            servers.insert(0, format!("{}:{}", host, port));
+        }
+        for server in servers {
+            let server = utils::str_to_cstring(server)?;
+            let srv = get_cached_srv_fn(
+                ctx,
+                server.as_ptr(),
+                share.as_ptr(),
+                workgroup.as_ptr(),
+                username.as_ptr(),
+            );
+            if !srv.is_null() {
+                return Ok(srv);
+            }
+        }
+        Ok(std::ptr::null_mut())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::time::Instant;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+    use crate::SmbOptions;
+
+    #[test]
+    #[serial]
+    fn should_ping_server() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        assert!(ctx.client.ping().is_ok());
+        assert!(ctx.client.ping().is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn should_remove_cached_server() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        ctx.client.connect().unwrap();
+        assert_eq!(ctx.client.remove_cached_server().unwrap(), true);
+        assert_eq!(ctx.client.remove_cached_server().unwrap(), false);
+        // connects again
+        assert!(ctx.client.stat("/cargo-test").is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn should_reconnect() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        assert!(ctx.client.purge_cached_servers().is_ok());
+        assert!(ctx.client.reconnect().is_ok());
+        assert!(ctx.client.stat("/cargo-test").is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn should_keep_session_alive() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let client = Arc::new(SmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap());
+        client.connect().unwrap();
+        assert_eq!(client.remove_cached_server().unwrap(), true);
+        let keepalive = client.keepalive(Duration::from_millis(50));
+        // the keepalive connects again
+        let started = Instant::now();
+        while !has_cached_server(&client) {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "session not re-established"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        drop(keepalive);
+        assert!(client.stat("/cargo-test").is_ok());
+    }
+
+    fn has_cached_server(client: &SmbClient) -> bool {
+        let ctx = client.ctx().unwrap();
+        !client.cached_server(**ctx).unwrap().is_null()
+    }
+}
@@ -0,0 +1,856 @@
+//! # Sync
+//!
+//! synchronization of directory trees between the local file system and the share
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use libc::mode_t;
+
+use super::pattern::PatternSet;
+use super::{
+    SmbClient, SmbConflictPolicy, SmbDeletePolicy, SmbDirentType, SmbMode, SmbOpenOptions,
+    SmbOverwritePolicy, SmbSyncAction, SmbSyncActionKind, SmbSyncDirection, SmbSyncOptions,
+    SmbSyncPlan, SmbSyncReason, SmbSyncReport,
+};
+use crate::{utils, SmbError, SmbResult};
+
+/// Mode for the directories created by synchronizations
+const DEFAULT_DIR_MODE: mode_t = 0o755;
+
+/// Entry of a directory tree
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    is_dir: bool,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Directory tree, by path relative to its root
+type Tree = BTreeMap<String, Node>;
+
+impl SmbClient {
+    /// Compare the local tree at `local` with the remote tree at `remote` and plan how to make the
+    /// destination a mirror of the source, according to `direction`.
+    ///
+    /// Files are compared by size and modification time, or by checksum when enabled in `options`.
+    /// Unless the client is case sensitive, remote names are matched with local names regardless of their case.
+    /// Local symbolic links are skipped. Nothing is modified, so the plan can be shown as a dry run before being executed with [`SmbClient::sync_execute`].
+    pub fn sync_plan<P, S>(
+        &self,
+        local: P,
+        remote: S,
+        direction: SmbSyncDirection,
+        options: &SmbSyncOptions,
+    ) -> SmbResult<SmbSyncPlan>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let (local, remote) = (local.as_ref(), remote.as_ref());
+        trace!(
+            "planning {:?} sync between {} and {}",
+            direction,
+            local.display(),
+            remote
+        );
+        let exclude = PatternSet::new(&options.exclude, true)?;
+        let dest_exclude = match options.delete {
+            SmbDeletePolicy::ExtraneousAndExcluded => None,
+            _ => Some(&exclude),
+        };
+        let (source, dest) = match direction {
+            SmbSyncDirection::Upload => (
+                Self::local_tree(local, Some(&exclude), false)?,
+                self.remote_tree(remote, dest_exclude, true)?,
+            ),
+            SmbSyncDirection::Download => (
+                self.remote_tree(remote, Some(&exclude), false)?,
+                Self::local_tree(local, dest_exclude, true)?,
+            ),
+        };
+        let mut buffer = vec![0; options.transfer.buffer_size];
+        let case_sensitive = self.is_case_sensitive()?;
+        let actions = Self::plan_actions(
+            &source,
+            &dest,
+            &exclude,
+            options,
+            direction,
+            case_sensitive,
+            |path| {
+                let local_sum = Self::checksum(&mut File::open(local.join(path))?, &mut buffer)?;
+                let remote_sum = Self::checksum(
+                    &mut self.open_with(
+                        utils::join_path(remote, path),
+                        SmbOpenOptions::default().read(true),
+                    )?,
+                    &mut buffer,
+                )?;
+                Ok(local_sum == remote_sum)
+            },
+        )?;
+        let plan = SmbSyncPlan {
+            direction,
+            local: local.to_path_buf(),
+            remote: remote.to_string(),
+            actions,
+        };
+        debug!(
+            "sync plan: {} to create, {} to update, {} to delete, {} to skip",
+            plan.count(SmbSyncActionKind::Create),
+            plan.count(SmbSyncActionKind::Update),
+            plan.count(SmbSyncActionKind::Delete),
+            plan.count(SmbSyncActionKind::Skip)
+        );
+        Ok(plan)
+    }
+
+    /// Execute a plan obtained with [`SmbClient::sync_plan`].
+    ///
+    /// Execution stops at the first action which fails.
+    pub fn sync_execute(
+        &self,
+        plan: &SmbSyncPlan,
+        options: &SmbSyncOptions,
+    ) -> SmbResult<SmbSyncReport> {
+        trace!(
+            "executing {:?} sync between {} and {}",
+            plan.direction,
+            plan.local.display(),
+            plan.remote
+        );
+        let mut report = SmbSyncReport::default();
+        let transfer = options
+            .transfer
+            .clone()
+            .overwrite(SmbOverwritePolicy::Always)
+            .create_parents(false);
+        match plan.direction {
+            SmbSyncDirection::Upload => {
+                self.create_dir_all(&plan.remote, SmbMode::from(DEFAULT_DIR_MODE))?
+            }
+            SmbSyncDirection::Download => fs::create_dir_all(&plan.local)?,
+        }
+        for action in plan.actions.iter() {
+            let local = plan.local.join(&action.path);
+            let remote = utils::join_path(&plan.remote, &action.path);
+            match action.kind {
+                SmbSyncActionKind::Skip => {
+                    report.skipped += 1;
+                    continue;
+                }
+                SmbSyncActionKind::Delete => {
+                    trace!("deleting {}", action.path);
+                    match plan.direction {
+                        SmbSyncDirection::Upload => self.remove_remote(&remote, action.is_dir)?,
+                        SmbSyncDirection::Download => Self::remove_local(&local, action.is_dir)?,
+                    }
+                    report.deleted += 1;
+                    continue;
+                }
+                SmbSyncActionKind::Create | SmbSyncActionKind::Update => {}
+            }
+            trace!("{} {}", action.kind, action.path);
+            if action.reason == SmbSyncReason::TypeChanged {
+                match plan.direction {
+                    SmbSyncDirection::Upload => self.remove_remote(&remote, !action.is_dir)?,
+                    SmbSyncDirection::Download => Self::remove_local(&local, !action.is_dir)?,
+                }
+            }
+            match (plan.direction, action.is_dir) {
+                (SmbSyncDirection::Upload, true) => {
+                    match self.mkdir(&remote, SmbMode::from(DEFAULT_DIR_MODE)) {
+                        Ok(()) => {}
+                        Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                (SmbSyncDirection::Download, true) => match fs::create_dir(&local) {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                    Err(err) => return Err(err.into()),
+                },
+                (SmbSyncDirection::Upload, false) => {
+                    report.bytes += self.upload(&local, &remote, &transfer)?.bytes;
+                }
+                (SmbSyncDirection::Download, false) => {
+                    report.bytes += self.download(&remote, &local, &transfer)?.bytes;
+                }
+            }
+            match action.kind {
+                SmbSyncActionKind::Create => report.created += 1,
+                _ => report.updated += 1,
+            }
+        }
+        debug!(
+            "synced: {} created, {} updated, {} deleted, {} skipped, {} bytes",
+            report.created, report.updated, report.deleted, report.skipped, report.bytes
+        );
+        Ok(report)
+    }
+
+    /// Plan and execute the synchronization of the trees at `local` and `remote`.
+    ///
+    /// See [`SmbClient::sync_plan`]
+    pub fn sync<P, S>(
+        &self,
+        local: P,
+        remote: S,
+        direction: SmbSyncDirection,
+        options: &SmbSyncOptions,
+    ) -> SmbResult<SmbSyncReport>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let plan = self.sync_plan(local, remote, direction, options)?;
+        self.sync_execute(&plan, options)
+    }
+
+    /// Compare `source` and `dest` trees. `same_content` tells whether the file at path has the same checksum on both sides.
+    ///
+    /// Unless `case_sensitive`, paths are matched regardless of their case, and actions on entries found on both sides
+    /// take the local path, as the remote one is matched anyway.
+    fn plan_actions(
+        source: &Tree,
+        dest: &Tree,
+        exclude: &PatternSet,
+        options: &SmbSyncOptions,
+        direction: SmbSyncDirection,
+        case_sensitive: bool,
+        mut same_content: impl FnMut(&str) -> SmbResult<bool>,
+    ) -> SmbResult<Vec<SmbSyncAction>> {
+        let fold = |path: &str| match case_sensitive {
+            true => path.to_string(),
+            false => path.to_lowercase(),
+        };
+        let source_paths: HashMap<String, &String> = source.keys().map(|p| (fold(p), p)).collect();
+        let dest_paths: HashMap<String, &String> = dest.keys().map(|p| (fold(p), p)).collect();
+        let mut actions = Vec::new();
+        let mut conflicts = Vec::new();
+        for (src_path, src) in source.iter() {
+            let dest_path = dest_paths.get(&fold(src_path)).copied();
+            let path = match (direction, dest_path) {
+                (SmbSyncDirection::Download, Some(dest_path)) => dest_path,
+                _ => src_path,
+            };
+            let (kind, reason) = match dest_path.and_then(|p| dest.get(p)) {
+                None => (SmbSyncActionKind::Create, SmbSyncReason::Missing),
+                Some(dst) if dst.is_dir != src.is_dir => {
+                    (SmbSyncActionKind::Update, SmbSyncReason::TypeChanged)
+                }
+                Some(_) if src.is_dir => continue,
+                Some(dst) => {
+                    let reason = if src.size != dst.size {
+                        SmbSyncReason::Size
+                    } else if options.checksum {
+                        match same_content(path)? {
+                            true => SmbSyncReason::Unchanged,
+                            false => SmbSyncReason::Checksum,
+                        }
+                    } else if Self::same_mtime(src.modified, dst.modified, options.mtime_tolerance)
+                    {
+                        SmbSyncReason::Unchanged
+                    } else {
+                        SmbSyncReason::Modified
+                    };
+                    if reason == SmbSyncReason::Unchanged {
+                        (SmbSyncActionKind::Skip, reason)
+                    } else if Self::is_newer(dst.modified, src.modified, options.mtime_tolerance) {
+                        match options.conflict {
+                            SmbConflictPolicy::Overwrite => (SmbSyncActionKind::Update, reason),
+                            SmbConflictPolicy::Skip => {
+                                (SmbSyncActionKind::Skip, SmbSyncReason::Conflict)
+                            }
+                            SmbConflictPolicy::Fail => {
+                                conflicts.push(path.clone());
+                                continue;
+                            }
+                        }
+                    } else {
+                        (SmbSyncActionKind::Update, reason)
+                    }
+                }
+            };
+            actions.push(SmbSyncAction {
+                kind,
+                reason,
+                path: path.clone(),
+                is_dir: src.is_dir,
+                size: src.size,
+            });
+        }
+        if !conflicts.is_empty() {
+            error!("conflicting changes on {} files", conflicts.len());
+            return Err(SmbError::SyncConflict(conflicts));
+        }
+        if options.delete != SmbDeletePolicy::Keep {
+            // directories which are removed as a whole
+            let mut removed: HashSet<&str> = HashSet::new();
+            for (path, dst) in dest.iter() {
+                let parent = path.rsplit_once('/').map(|(parent, _)| parent);
+                if parent.map(|p| removed.contains(p)).unwrap_or(false) {
+                    if dst.is_dir {
+                        removed.insert(path);
+                    }
+                    continue;
+                }
+                match source_paths.get(&fold(path)).and_then(|p| source.get(*p)) {
+                    Some(src) if src.is_dir != dst.is_dir && dst.is_dir => {
+                        removed.insert(path);
+                    }
+                    Some(_) => {}
+                    None => {
+                        if dst.is_dir {
+                            removed.insert(path);
+                        }
+                        actions.push(SmbSyncAction {
+                            kind: SmbSyncActionKind::Delete,
+                            reason: match exclude.is_match(path, dst.is_dir) {
+                                true => SmbSyncReason::Excluded,
+                                false => SmbSyncReason::Extraneous,
+                            },
+                            path: path.clone(),
+                            is_dir: dst.is_dir,
+                            size: 0,
+                        });
+                    }
+                }
+            }
+            actions.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        Ok(actions)
+    }
+
+    /// Returns whether `a` and `b` are the same within `tolerance`
+    fn same_mtime(a: SystemTime, b: SystemTime, tolerance: Duration) -> bool {
+        let diff = a.duration_since(b).or_else(|_| b.duration_since(a));
+        diff.map(|d| d <= tolerance).unwrap_or(false)
+    }
+
+    /// Returns whether `a` is more recent than `b` beyond `tolerance`
+    fn is_newer(a: SystemTime, b: SystemTime, tolerance: Duration) -> bool {
+        a.duration_since(b).map(|d| d > tolerance).unwrap_or(false)
+    }
+
+    /// Collect the local tree at `root`, pruning excluded entries; a missing root is an empty tree if `allow_missing`
+    fn local_tree(
+        root: &Path,
+        exclude: Option<&PatternSet>,
+        allow_missing: bool,
+    ) -> SmbResult<Tree> {
+        let mut tree = Tree::new();
+        match Self::collect_local(root, "", exclude, &mut tree) {
+            Ok(()) => Ok(tree),
+            Err(SmbError::Io(err)) if allow_missing && err.kind() == io::ErrorKind::NotFound => {
+                Ok(tree)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn collect_local(
+        dir: &Path,
+        relative_path: &str,
+        exclude: Option<&PatternSet>,
+        tree: &mut Tree,
+    ) -> SmbResult<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = fs::symlink_metadata(entry.path())?;
+            let path = Self::relative_join(relative_path, &entry.file_name().to_string_lossy());
+            if metadata.is_symlink() {
+                warn!("skipping symbolic link {}", entry.path().display());
+                continue;
+            }
+            if exclude
+                .map(|e| e.is_match(&path, metadata.is_dir()))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            if metadata.is_dir() {
+                Self::collect_local(&entry.path(), &path, exclude, tree)?;
+            }
+            tree.insert(
+                path,
+                Node {
+                    is_dir: metadata.is_dir(),
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Collect the remote tree at `root`, pruning excluded entries; a missing root is an empty tree if `allow_missing`
+    fn remote_tree(
+        &self,
+        root: &str,
+        exclude: Option<&PatternSet>,
+        allow_missing: bool,
+    ) -> SmbResult<Tree> {
+        let mut tree = Tree::new();
+        match self.collect_remote(root, "", exclude, &mut tree) {
+            Ok(()) => Ok(tree),
+            Err(SmbError::Io(err)) if allow_missing && err.kind() == io::ErrorKind::NotFound => {
+                Ok(tree)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn collect_remote(
+        &self,
+        dir: &str,
+        relative_path: &str,
+        exclude: Option<&PatternSet>,
+        tree: &mut Tree,
+    ) -> SmbResult<()> {
+        for info in self.list_dirplus(dir)? {
+            let is_dir = info.get_type() == SmbDirentType::Dir;
+            let path = Self::relative_join(relative_path, info.name());
+            if exclude.map(|e| e.is_match(&path, is_dir)).unwrap_or(false) {
+                continue;
+            }
+            if is_dir {
+                self.collect_remote(&utils::join_path(dir, info.name()), &path, exclude, tree)?;
+            }
+            tree.insert(
+                path,
+                Node {
+                    is_dir,
+                    size: info.size,
+                    modified: info.mtime,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn relative_join(relative_path: &str, name: &str) -> String {
+        match relative_path.is_empty() {
+            true => name.to_string(),
+            false => format!("{}/{}", relative_path, name),
+        }
+    }
+
+    fn remove_remote(&self, path: &str, is_dir: bool) -> SmbResult<()> {
+        match is_dir {
+            true => self.remove_dir_all(path),
+            false => self.unlink(path),
+        }
+    }
+
+    fn remove_local(path: &Path, is_dir: bool) -> SmbResult<()> {
+        match is_dir {
+            true => fs::remove_dir_all(path),
+            false => fs::remove_file(path),
+        }
+        .map_err(SmbError::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::time::UNIX_EPOCH;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    fn node(is_dir: bool, size: u64, secs: u64) -> Node {
+        Node {
+            is_dir,
+            size,
+            modified: UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    fn tree(nodes: &[(&str, Node)]) -> Tree {
+        nodes
+            .iter()
+            .map(|(path, node)| (path.to_string(), *node))
+            .collect()
+    }
+
+    fn summary(actions: &[SmbSyncAction]) -> Vec<(SmbSyncActionKind, SmbSyncReason, &str)> {
+        actions
+            .iter()
+            .map(|a| (a.kind, a.reason, a.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn should_plan_actions() {
+        let source = tree(&[
+            ("a", node(true, 0, 100)),
+            ("a/new.txt", node(false, 4, 100)),
+            ("same.txt", node(false, 4, 100)),
+            ("size.txt", node(false, 8, 100)),
+            ("time.txt", node(false, 4, 200)),
+            ("type", node(false, 4, 100)),
+        ]);
+        let dest = tree(&[
+            ("a", node(true, 0, 50)),
+            ("a-b", node(true, 0, 50)),
+            ("a-b/old.txt", node(false, 4, 50)),
+            ("same.txt", node(false, 4, 101)),
+            ("size.txt", node(false, 4, 100)),
+            ("time.txt", node(false, 4, 100)),
+            ("type", node(true, 0, 50)),
+            ("type/x.txt", node(false, 4, 50)),
+        ]);
+        let exclude = PatternSet::new::<&str>(&[], true).unwrap();
+        let options = SmbSyncOptions::default();
+        let actions = SmbClient::plan_actions(
+            &source,
+            &dest,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            true,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![
+                (
+                    SmbSyncActionKind::Create,
+                    SmbSyncReason::Missing,
+                    "a/new.txt"
+                ),
+                (
+                    SmbSyncActionKind::Skip,
+                    SmbSyncReason::Unchanged,
+                    "same.txt"
+                ),
+                (SmbSyncActionKind::Update, SmbSyncReason::Size, "size.txt"),
+                (
+                    SmbSyncActionKind::Update,
+                    SmbSyncReason::Modified,
+                    "time.txt"
+                ),
+                (
+                    SmbSyncActionKind::Update,
+                    SmbSyncReason::TypeChanged,
+                    "type"
+                ),
+            ]
+        );
+        // delete extraneous entries, but not the content of deleted or replaced directories
+        let options = options.delete(SmbDeletePolicy::Extraneous);
+        let actions = SmbClient::plan_actions(
+            &source,
+            &dest,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            true,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(actions.len(), 6);
+        assert_eq!(
+            summary(&actions)[0],
+            (SmbSyncActionKind::Delete, SmbSyncReason::Extraneous, "a-b")
+        );
+        assert!(actions[0].is_dir);
+    }
+
+    #[test]
+    fn should_plan_with_checksum() {
+        let source = tree(&[
+            ("a.txt", node(false, 4, 100)),
+            ("b.txt", node(false, 4, 100)),
+        ]);
+        let dest = tree(&[
+            ("a.txt", node(false, 4, 50)),
+            ("b.txt", node(false, 4, 100)),
+        ]);
+        let exclude = PatternSet::new::<&str>(&[], true).unwrap();
+        let options = SmbSyncOptions::default().checksum(true);
+        let actions = SmbClient::plan_actions(
+            &source,
+            &dest,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            true,
+            |path| Ok(path == "a.txt"),
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![
+                (SmbSyncActionKind::Skip, SmbSyncReason::Unchanged, "a.txt"),
+                (SmbSyncActionKind::Update, SmbSyncReason::Checksum, "b.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_plan_conflicts() {
+        let source = tree(&[("a.txt", node(false, 4, 100))]);
+        let dest = tree(&[("a.txt", node(false, 5, 200))]);
+        let exclude = PatternSet::new::<&str>(&[], true).unwrap();
+        let options = SmbSyncOptions::default();
+        let actions = SmbClient::plan_actions(
+            &source,
+            &dest,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            true,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![(SmbSyncActionKind::Update, SmbSyncReason::Size, "a.txt")]
+        );
+        let options = options.conflict(SmbConflictPolicy::Skip);
+        let actions = SmbClient::plan_actions(
+            &source,
+            &dest,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            true,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![(SmbSyncActionKind::Skip, SmbSyncReason::Conflict, "a.txt")]
+        );
+        let options = options.conflict(SmbConflictPolicy::Fail);
+        assert_eq!(
+            SmbClient::plan_actions(
+                &source,
+                &dest,
+                &exclude,
+                &options,
+                SmbSyncDirection::Upload,
+                true,
+                |_| Ok(true)
+            )
+            .unwrap_err(),
+            SmbError::SyncConflict(vec![String::from("a.txt")])
+        );
+    }
+
+    #[test]
+    fn should_plan_deletion_of_excluded_entries() {
+        let source = tree(&[]);
+        let dest = tree(&[("a.tmp", node(false, 4, 100))]);
+        let exclude = PatternSet::new(&["*.tmp"], true).unwrap();
+        let options = SmbSyncOptions::default().delete(SmbDeletePolicy::ExtraneousAndExcluded);
+        let actions = SmbClient::plan_actions(
+            &source,
+            &dest,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            true,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![(SmbSyncActionKind::Delete, SmbSyncReason::Excluded, "a.tmp")]
+        );
+    }
+
+    #[test]
+    fn should_plan_actions_regardless_of_case() {
+        let local = tree(&[
+            ("Dir", node(true, 0, 100)),
+            ("Dir/File.txt", node(false, 4, 100)),
+        ]);
+        let remote = tree(&[
+            ("dir", node(true, 0, 100)),
+            ("dir/file.txt", node(false, 8, 100)),
+            ("extra.txt", node(false, 4, 100)),
+        ]);
+        let exclude = PatternSet::new::<&str>(&[], true).unwrap();
+        let options = SmbSyncOptions::default().delete(SmbDeletePolicy::Extraneous);
+        let actions = SmbClient::plan_actions(
+            &local,
+            &remote,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            false,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![
+                (
+                    SmbSyncActionKind::Update,
+                    SmbSyncReason::Size,
+                    "Dir/File.txt"
+                ),
+                (
+                    SmbSyncActionKind::Delete,
+                    SmbSyncReason::Extraneous,
+                    "extra.txt"
+                ),
+            ]
+        );
+        // actions take the local path
+        let actions = SmbClient::plan_actions(
+            &remote,
+            &local,
+            &exclude,
+            &SmbSyncOptions::default(),
+            SmbSyncDirection::Download,
+            false,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![
+                (
+                    SmbSyncActionKind::Update,
+                    SmbSyncReason::Size,
+                    "Dir/File.txt"
+                ),
+                (
+                    SmbSyncActionKind::Create,
+                    SmbSyncReason::Missing,
+                    "extra.txt"
+                ),
+            ]
+        );
+        // case sensitive clients tell them apart
+        let actions = SmbClient::plan_actions(
+            &local,
+            &remote,
+            &exclude,
+            &options,
+            SmbSyncDirection::Upload,
+            true,
+            |_| Ok(true),
+        )
+        .unwrap();
+        assert_eq!(actions.len(), 4);
+    }
+
+    #[test]
+    fn should_skip_local_symlinks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        fs::create_dir(tempdir.path().join("dir")).unwrap();
+        fs::write(tempdir.path().join("dir/a.txt"), b"Hello\n").unwrap();
+        std::os::unix::fs::symlink(tempdir.path().join("dir"), tempdir.path().join("link"))
+            .unwrap();
+        std::os::unix::fs::symlink(
+            tempdir.path().join("dir/a.txt"),
+            tempdir.path().join("b.txt"),
+        )
+        .unwrap();
+        let tree = SmbClient::local_tree(tempdir.path(), None, false).unwrap();
+        assert_eq!(
+            tree.keys().map(|k| k.as_str()).collect::<Vec<&str>>(),
+            vec!["dir", "dir/a.txt"]
+        );
+    }
+
+    #[test]
+    fn should_compare_mtime_with_tolerance() {
+        let a = UNIX_EPOCH + Duration::from_secs(100);
+        let b = UNIX_EPOCH + Duration::from_secs(102);
+        assert!(SmbClient::same_mtime(a, b, Duration::from_secs(2)));
+        assert!(SmbClient::same_mtime(b, a, Duration::from_secs(2)));
+        assert!(!SmbClient::same_mtime(a, b, Duration::from_secs(1)));
+        assert!(SmbClient::is_newer(b, a, Duration::from_secs(1)));
+        assert!(!SmbClient::is_newer(b, a, Duration::from_secs(2)));
+        assert!(!SmbClient::is_newer(a, b, Duration::ZERO));
+    }
+
+    #[test]
+    #[serial]
+    fn should_sync_local_and_remote_trees() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let tempdir = tempfile::tempdir().unwrap();
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.txt"), "Hello, World!\n").unwrap();
+        fs::write(src.join("sub/b.txt"), "Bonjour\n").unwrap();
+        fs::write(src.join("c.tmp"), "temp\n").unwrap();
+        let options = SmbSyncOptions::default()
+            .exclude("*.tmp")
+            .delete(SmbDeletePolicy::Extraneous);
+        let plan = ctx
+            .client
+            .sync_plan(
+                &src,
+                "/cargo-test/mirror",
+                SmbSyncDirection::Upload,
+                &options,
+            )
+            .unwrap();
+        assert_eq!(plan.count(SmbSyncActionKind::Create), 3);
+        let report = ctx.client.sync_execute(&plan, &options).unwrap();
+        assert_eq!(report.created, 3);
+        assert_eq!(report.bytes, 22);
+        assert!(ctx.client.stat("/cargo-test/mirror/c.tmp").is_err());
+        // nothing left to do
+        let plan = ctx
+            .client
+            .sync_plan(
+                &src,
+                "/cargo-test/mirror",
+                SmbSyncDirection::Upload,
+                &options,
+            )
+            .unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.count(SmbSyncActionKind::Skip), 2);
+        // delete extraneous files
+        fs::remove_file(src.join("sub/b.txt")).unwrap();
+        let report = ctx
+            .client
+            .sync(
+                &src,
+                "/cargo-test/mirror",
+                SmbSyncDirection::Upload,
+                &options,
+            )
+            .unwrap();
+        assert_eq!(report.deleted, 1);
+        assert!(ctx.client.stat("/cargo-test/mirror/sub/b.txt").is_err());
+
+        // download
+        let dst = tempdir.path().join("dst");
+        let report = ctx
+            .client
+            .sync(
+                &dst,
+                "/cargo-test/mirror",
+                SmbSyncDirection::Download,
+                &options,
+            )
+            .unwrap();
+        assert_eq!(report.created, 2);
+        assert_eq!(
+            fs::read_to_string(dst.join("a.txt")).unwrap(),
+            "Hello, World!\n"
+        );
+        assert!(dst.join("sub").is_dir());
+    }
+}
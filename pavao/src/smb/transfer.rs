@@ -0,0 +1,756 @@
+//! # Transfer
+//!
+//! upload and download of files and directory trees between the local file system and the share
+
+use std::fs::{self, File, Metadata, OpenOptions, Permissions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use libc::mode_t;
+
+use super::{
+    SmbClient, SmbDirentType, SmbMode, SmbOpenOptions, SmbOverwritePolicy, SmbStat,
+    SmbTransferOptions, SmbTransferReport,
+};
+use crate::{utils, SmbError, SmbResult};
+
+/// Mode for the directories created by transfers
+const DEFAULT_DIR_MODE: mode_t = 0o755;
+
+/// Counter making the names of partial downloads unique within the process
+static PART_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl SmbClient {
+    /// Upload the local file at `local` to `remote`.
+    ///
+    /// The file is streamed through a single buffer, so memory usage doesn't depend on the file size.
+    pub fn upload<P, S>(
+        &self,
+        local: P,
+        remote: S,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        trace!(
+            "uploading {} to {}",
+            local.as_ref().display(),
+            remote.as_ref()
+        );
+        let mut report = SmbTransferReport::default();
+        let mut buffer = vec![0; options.buffer_size];
+        if options.create_parents {
+            self.create_remote_parents(remote.as_ref())?;
+        }
+        self.upload_file(
+            local.as_ref(),
+            remote.as_ref(),
+            options,
+            &mut buffer,
+            &mut report,
+        )?;
+        Ok(report)
+    }
+
+    /// Upload the local directory tree at `local` to `remote`.
+    ///
+    /// Symbolic links in the tree are skipped.
+    pub fn upload_dir<P, S>(
+        &self,
+        local: P,
+        remote: S,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        trace!(
+            "uploading directory {} to {}",
+            local.as_ref().display(),
+            remote.as_ref()
+        );
+        let mut report = SmbTransferReport::default();
+        let mut buffer = vec![0; options.buffer_size];
+        if options.create_parents {
+            self.create_remote_parents(remote.as_ref())?;
+        }
+        self.upload_tree(
+            local.as_ref(),
+            remote.as_ref(),
+            options,
+            &mut buffer,
+            &mut report,
+        )?;
+        debug!(
+            "uploaded {} files, {} directories and {} bytes; skipped {} files",
+            report.files, report.directories, report.bytes, report.skipped
+        );
+        Ok(report)
+    }
+
+    /// Download the remote file at `remote` to `local`.
+    ///
+    /// The file is streamed through a single buffer, so memory usage doesn't depend on the file size.
+    /// It's downloaded to a hidden temporary file next to `local`, which replaces `local` once complete,
+    /// so a failed download leaves an existing `local` untouched.
+    pub fn download<S, P>(
+        &self,
+        remote: S,
+        local: P,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        S: AsRef<str>,
+        P: AsRef<Path>,
+    {
+        trace!(
+            "downloading {} to {}",
+            remote.as_ref(),
+            local.as_ref().display()
+        );
+        let mut report = SmbTransferReport::default();
+        let mut buffer = vec![0; options.buffer_size];
+        if options.create_parents {
+            Self::create_local_parents(local.as_ref())?;
+        }
+        self.download_file(
+            remote.as_ref(),
+            local.as_ref(),
+            options,
+            &mut buffer,
+            &mut report,
+        )?;
+        Ok(report)
+    }
+
+    /// Download the remote directory tree at `remote` to `local`
+    pub fn download_dir<S, P>(
+        &self,
+        remote: S,
+        local: P,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        S: AsRef<str>,
+        P: AsRef<Path>,
+    {
+        trace!(
+            "downloading directory {} to {}",
+            remote.as_ref(),
+            local.as_ref().display()
+        );
+        let mut report = SmbTransferReport::default();
+        let mut buffer = vec![0; options.buffer_size];
+        if options.create_parents {
+            Self::create_local_parents(local.as_ref())?;
+        }
+        self.download_tree(
+            remote.as_ref(),
+            local.as_ref(),
+            options,
+            &mut buffer,
+            &mut report,
+        )?;
+        debug!(
+            "downloaded {} files, {} directories and {} bytes; skipped {} files",
+            report.files, report.directories, report.bytes, report.skipped
+        );
+        Ok(report)
+    }
+
+    fn upload_tree(
+        &self,
+        local: &Path,
+        remote: &str,
+        options: &SmbTransferOptions,
+        buffer: &mut [u8],
+        report: &mut SmbTransferReport,
+    ) -> SmbResult<()> {
+        let metadata = fs::metadata(local)?;
+        match self.mkdir(remote, SmbMode::from(DEFAULT_DIR_MODE)) {
+            Ok(()) => report.directories += 1,
+            Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+        for entry in fs::read_dir(local)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let remote_path = utils::join_path(remote, &name.to_string_lossy());
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                warn!("skipping symbolic link {}", entry.path().display());
+            } else if file_type.is_dir() {
+                self.upload_tree(&entry.path(), &remote_path, options, buffer, report)?;
+            } else {
+                self.upload_file(&entry.path(), &remote_path, options, buffer, report)?;
+            }
+        }
+        if options.preserve_permissions {
+            self.chmod(remote, Self::local_mode(&metadata))?;
+        }
+        if options.preserve_mtime {
+            self.utimes(remote, metadata.accessed()?, metadata.modified()?)?;
+        }
+        Ok(())
+    }
+
+    fn upload_file(
+        &self,
+        local: &Path,
+        remote: &str,
+        options: &SmbTransferOptions,
+        buffer: &mut [u8],
+        report: &mut SmbTransferReport,
+    ) -> SmbResult<()> {
+        trace!("uploading file {} to {}", local.display(), remote);
+        let metadata = fs::metadata(local)?;
+        if self.skip_upload(&metadata, remote, options)? {
+            report.skipped += 1;
+            return Ok(());
+        }
+        let mut reader = File::open(local)?;
+        let mut writer = self.open_with(
+            remote,
+            SmbOpenOptions::default()
+                .write(true)
+                .create(true)
+                .truncate(true),
+        )?;
+        let path = local.to_string_lossy();
+        let bytes = Self::stream(
+            &mut reader,
+            &mut writer,
+            buffer,
+            &path,
+            metadata.len(),
+            options,
+        )?;
+        writer.close()?;
+        self.finish_upload(local, &metadata, remote, options, buffer)?;
+        report.files += 1;
+        report.bytes += bytes;
+        Ok(())
+    }
+
+    fn download_tree(
+        &self,
+        remote: &str,
+        local: &Path,
+        options: &SmbTransferOptions,
+        buffer: &mut [u8],
+        report: &mut SmbTransferReport,
+    ) -> SmbResult<()> {
+        let stat = self.stat(remote)?;
+        match fs::create_dir(local) {
+            Ok(()) => report.directories += 1,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err.into()),
+        }
+        for entry in self.list_dirplus(remote)? {
+            let remote_path = utils::join_path(remote, entry.name());
+            let local_path = local.join(entry.name());
+            if entry.get_type() == SmbDirentType::Dir {
+                self.download_tree(&remote_path, &local_path, options, buffer, report)?;
+            } else {
+                self.download_file(&remote_path, &local_path, options, buffer, report)?;
+            }
+        }
+        if options.preserve_permissions {
+            fs::set_permissions(local, Self::permissions(stat.mode))?;
+        }
+        if options.preserve_mtime {
+            File::open(local)?.set_modified(stat.modified)?;
+        }
+        Ok(())
+    }
+
+    fn download_file(
+        &self,
+        remote: &str,
+        local: &Path,
+        options: &SmbTransferOptions,
+        buffer: &mut [u8],
+        report: &mut SmbTransferReport,
+    ) -> SmbResult<()> {
+        trace!("downloading file {} to {}", remote, local.display());
+        let stat = self.stat(remote)?;
+        if Self::skip_download(&stat, local, options)? {
+            report.skipped += 1;
+            return Ok(());
+        }
+        let mut reader = self.open_with(remote, SmbOpenOptions::default().read(true))?;
+        let bytes = Self::write_local_file(local, |writer, temp| {
+            let bytes = Self::stream(&mut reader, writer, buffer, remote, stat.size, options)?;
+            self.finish_download(remote, &stat, temp, writer, options, buffer)?;
+            Ok(bytes)
+        })?;
+        report.files += 1;
+        report.bytes += bytes;
+        Ok(())
+    }
+
+    /// Returns whether the upload of the local file with `metadata` to `remote` must be skipped
+    pub(crate) fn skip_upload(
+        &self,
+        metadata: &Metadata,
+        remote: &str,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<bool> {
+        let existing = match self.stat(remote) {
+            Ok(stat) => Some(stat.modified),
+            Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        Self::must_skip(options.overwrite, remote, existing, metadata.modified()?)
+    }
+
+    /// Verify the uploaded file and copy the local metadata to it
+    pub(crate) fn finish_upload(
+        &self,
+        local: &Path,
+        metadata: &Metadata,
+        remote: &str,
+        options: &SmbTransferOptions,
+        buffer: &mut [u8],
+    ) -> SmbResult<()> {
+        if options.verify_checksum {
+            let local_sum = Self::checksum(&mut File::open(local)?, buffer)?;
+            let remote_sum = Self::checksum(
+                &mut self.open_with(remote, SmbOpenOptions::default().read(true))?,
+                buffer,
+            )?;
+            Self::verify(remote, local_sum, remote_sum)?;
+        }
+        if options.preserve_permissions {
+            self.chmod(remote, Self::local_mode(metadata))?;
+        }
+        if options.preserve_mtime {
+            self.utimes(remote, metadata.accessed()?, metadata.modified()?)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the download of the remote file with `stat` to `local` must be skipped
+    pub(crate) fn skip_download(
+        stat: &SmbStat,
+        local: &Path,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<bool> {
+        let existing = match fs::metadata(local) {
+            Ok(metadata) => Some(metadata.modified()?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        Self::must_skip(
+            options.overwrite,
+            &local.to_string_lossy(),
+            existing,
+            stat.modified,
+        )
+    }
+
+    /// Verify the downloaded file and copy the remote metadata to it
+    pub(crate) fn finish_download(
+        &self,
+        remote: &str,
+        stat: &SmbStat,
+        local: &Path,
+        writer: &mut File,
+        options: &SmbTransferOptions,
+        buffer: &mut [u8],
+    ) -> SmbResult<()> {
+        if options.verify_checksum {
+            writer.flush()?;
+            let remote_sum = Self::checksum(
+                &mut self.open_with(remote, SmbOpenOptions::default().read(true))?,
+                buffer,
+            )?;
+            let local_sum = Self::checksum(&mut File::open(local)?, buffer)?;
+            Self::verify(&local.to_string_lossy(), remote_sum, local_sum)?;
+        }
+        if options.preserve_permissions {
+            writer.set_permissions(Self::permissions(stat.mode))?;
+        }
+        if options.preserve_mtime {
+            writer.set_modified(stat.modified)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the transfer to `dest` must be skipped according to the overwrite `policy`
+    fn must_skip(
+        policy: SmbOverwritePolicy,
+        dest: &str,
+        dest_modified: Option<SystemTime>,
+        src_modified: SystemTime,
+    ) -> SmbResult<bool> {
+        match (dest_modified, policy) {
+            (None, _) | (Some(_), SmbOverwritePolicy::Always) => Ok(false),
+            (Some(_), SmbOverwritePolicy::Fail) => Err(Self::already_exists(dest)),
+            (Some(_), SmbOverwritePolicy::Skip) => {
+                debug!("{} already exists: skipping", dest);
+                Ok(true)
+            }
+            (Some(dest_modified), SmbOverwritePolicy::IfNewer) => Ok(src_modified <= dest_modified),
+        }
+    }
+
+    /// Copy `reader` into `writer`, reporting progress; returns the amount of bytes copied
+    pub(crate) fn stream(
+        reader: &mut impl Read,
+        writer: &mut impl Write,
+        buffer: &mut [u8],
+        path: &str,
+        total: u64,
+        options: &SmbTransferOptions,
+    ) -> io::Result<u64> {
+        let mut transferred = 0;
+        options.report_progress(path, transferred, total);
+        Self::read_with(reader, buffer, |data| {
+            writer.write_all(data)?;
+            transferred += data.len() as u64;
+            options.report_progress(path, transferred, total);
+            Ok(())
+        })
+    }
+
+    /// Compute the CRC-32 checksum of the content of `reader`
+    pub(crate) fn checksum(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<u32> {
+        let mut hasher = crc32fast::Hasher::new();
+        Self::read_with(reader, buffer, |data| {
+            hasher.update(data);
+            Ok(())
+        })?;
+        Ok(hasher.finalize())
+    }
+
+    /// Read `reader` to the end through `buffer`, calling `f` with each part read; returns the amount of bytes read
+    pub(crate) fn read_with<F>(
+        reader: &mut impl Read,
+        buffer: &mut [u8],
+        mut f: F,
+    ) -> io::Result<u64>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let mut size = 0;
+        loop {
+            match reader.read(buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    f(&buffer[..n])?;
+                    size += n as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(size)
+    }
+
+    fn verify(dest: &str, expected: u32, found: u32) -> SmbResult<()> {
+        if expected == found {
+            return Ok(());
+        }
+        error!(
+            "checksum mismatch for {}: expected {:08x}, found {:08x}",
+            dest, expected, found
+        );
+        Err(SmbError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "checksum mismatch for {}: expected {:08x}, found {:08x}",
+                dest, expected, found
+            ),
+        )))
+    }
+
+    pub(crate) fn create_remote_parents(&self, remote: &str) -> SmbResult<()> {
+        match remote.trim_end_matches('/').rsplit_once('/') {
+            Some((parent, _)) if !parent.is_empty() => {
+                self.create_dir_all(parent, SmbMode::from(DEFAULT_DIR_MODE))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn create_local_parents(local: &Path) -> SmbResult<()> {
+        match local.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                fs::create_dir_all(parent).map_err(SmbError::from)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the local file at `local` through a hidden temporary file in the same directory, which replaces
+    /// `local` only if `write` succeeds; otherwise it's removed.
+    ///
+    /// `write` gets the temporary file and its path.
+    pub(crate) fn write_local_file<T>(
+        local: &Path,
+        write: impl FnOnce(&mut File, &Path) -> SmbResult<T>,
+    ) -> SmbResult<T> {
+        let temp = Self::local_part_path(local)?;
+        trace!("writing {} through {}", local.display(), temp.display());
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        let result = write(&mut file, &temp).and_then(|value| {
+            drop(file);
+            fs::rename(&temp, local)?;
+            Ok(value)
+        });
+        if result.is_err() {
+            if let Err(err) = fs::remove_file(&temp) {
+                error!("failed to remove {}: {}", temp.display(), err);
+            }
+        }
+        result
+    }
+
+    /// Get a unique hidden temporary path in the same directory as `local`
+    fn local_part_path(local: &Path) -> SmbResult<PathBuf> {
+        let name = local.file_name().ok_or(SmbError::BadValue)?;
+        Ok(local.with_file_name(format!(
+            ".{}.{}-{}.part",
+            name.to_string_lossy(),
+            process::id(),
+            PART_COUNTER.fetch_add(1, Ordering::SeqCst)
+        )))
+    }
+
+    /// Get the mode of a local file; `mode_t` is `u32` on linux, but `u16` on macOS
+    #[allow(clippy::unnecessary_cast)]
+    fn local_mode(metadata: &Metadata) -> SmbMode {
+        SmbMode::from(metadata.permissions().mode() as mode_t)
+    }
+
+    /// Get the local permissions for `mode`; see [`SmbClient::local_mode`]
+    #[allow(clippy::unnecessary_cast)]
+    fn permissions(mode: SmbMode) -> Permissions {
+        Permissions::from_mode(mode_t::from(mode) as u32 & 0o7777)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    #[test]
+    fn should_tell_whether_to_skip() {
+        let old = UNIX_EPOCH + Duration::from_secs(1000);
+        let new = UNIX_EPOCH + Duration::from_secs(2000);
+        for policy in [
+            SmbOverwritePolicy::Fail,
+            SmbOverwritePolicy::Skip,
+            SmbOverwritePolicy::Always,
+            SmbOverwritePolicy::IfNewer,
+        ] {
+            assert_eq!(
+                SmbClient::must_skip(policy, "/a", None, new).unwrap(),
+                false
+            );
+        }
+        assert!(SmbClient::must_skip(SmbOverwritePolicy::Fail, "/a", Some(old), new).is_err());
+        assert_eq!(
+            SmbClient::must_skip(SmbOverwritePolicy::Skip, "/a", Some(old), new).unwrap(),
+            true
+        );
+        assert_eq!(
+            SmbClient::must_skip(SmbOverwritePolicy::Always, "/a", Some(new), old).unwrap(),
+            false
+        );
+        assert_eq!(
+            SmbClient::must_skip(SmbOverwritePolicy::IfNewer, "/a", Some(old), new).unwrap(),
+            false
+        );
+        assert_eq!(
+            SmbClient::must_skip(SmbOverwritePolicy::IfNewer, "/a", Some(new), old).unwrap(),
+            true
+        );
+    }
+
+    #[test]
+    fn should_stream_with_progress() {
+        let transferred = Arc::new(AtomicU64::new(0));
+        let calls = Arc::new(AtomicU64::new(0));
+        let (t, c) = (transferred.clone(), calls.clone());
+        let options = SmbTransferOptions::default().progress(move |p| {
+            assert_eq!(p.path, "/test");
+            assert_eq!(p.total, 14);
+            t.store(p.transferred, Ordering::SeqCst);
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut reader = io::Cursor::new("Hello, World!\n".as_bytes());
+        let mut writer = Vec::new();
+        let mut buffer = vec![0; 4];
+        assert_eq!(
+            SmbClient::stream(&mut reader, &mut writer, &mut buffer, "/test", 14, &options)
+                .unwrap(),
+            14
+        );
+        assert_eq!(writer.as_slice(), "Hello, World!\n".as_bytes());
+        assert_eq!(transferred.load(Ordering::SeqCst), 14);
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn should_compute_checksum() {
+        let mut buffer = vec![0; 4];
+        let sum = SmbClient::checksum(
+            &mut io::Cursor::new("Hello, World!\n".as_bytes()),
+            &mut buffer,
+        )
+        .unwrap();
+        assert_eq!(sum, crc32fast::hash("Hello, World!\n".as_bytes()));
+        assert!(SmbClient::verify("/a", sum, sum).is_ok());
+        assert!(SmbClient::verify("/a", sum, sum + 1).is_err());
+    }
+
+    #[test]
+    fn should_write_local_file_through_temp_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let local = tempdir.path().join("test.txt");
+        fs::write(&local, "Hello, World!\n").unwrap();
+        let result: SmbResult<()> = SmbClient::write_local_file(&local, |file, temp| {
+            assert_ne!(temp, local.as_path());
+            file.write_all(b"Bonj")?;
+            Err(SmbError::Io(io::Error::from(
+                io::ErrorKind::ConnectionReset,
+            )))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&local).unwrap(), "Hello, World!\n");
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 1);
+        SmbClient::write_local_file(&local, |file, _| {
+            file.write_all(b"Bonjour\n")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(&local).unwrap(), "Bonjour\n");
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    #[serial]
+    fn should_upload_and_download_file() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let tempdir = tempfile::tempdir().unwrap();
+        let local = tempdir.path().join("test.txt");
+        fs::write(&local, "Hello, World!\n").unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        File::options()
+            .write(true)
+            .open(&local)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let options = SmbTransferOptions::default()
+            .create_parents(true)
+            .verify_checksum(true);
+        let report = ctx
+            .client
+            .upload(&local, "/cargo-test/a/b/test.txt", &options)
+            .unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(report.bytes, 14);
+        assert_eq!(
+            ctx.client
+                .stat("/cargo-test/a/b/test.txt")
+                .unwrap()
+                .modified,
+            mtime
+        );
+        // overwrite policy
+        assert!(ctx
+            .client
+            .upload(&local, "/cargo-test/a/b/test.txt", &options)
+            .is_err());
+        let report = ctx
+            .client
+            .upload(
+                &local,
+                "/cargo-test/a/b/test.txt",
+                &options.clone().overwrite(SmbOverwritePolicy::IfNewer),
+            )
+            .unwrap();
+        assert_eq!(report.skipped, 1);
+
+        let downloaded = tempdir.path().join("c/d/test.txt");
+        let report = ctx
+            .client
+            .download("/cargo-test/a/b/test.txt", &downloaded, &options)
+            .unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(fs::read_to_string(&downloaded).unwrap(), "Hello, World!\n");
+        assert_eq!(
+            fs::metadata(&downloaded).unwrap().modified().unwrap(),
+            mtime
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_upload_and_download_dir() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let tempdir = tempfile::tempdir().unwrap();
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.txt"), "Hello, World!\n").unwrap();
+        fs::write(src.join("sub/b.txt"), "Bonjour\n").unwrap();
+        // symbolic links are not followed
+        std::os::unix::fs::symlink(src.join("sub"), src.join("link")).unwrap();
+        let options = SmbTransferOptions::default();
+        let report = ctx
+            .client
+            .upload_dir(&src, "/cargo-test/tree", &options)
+            .unwrap();
+        assert_eq!(report.files, 2);
+        assert_eq!(report.directories, 2);
+        assert_eq!(report.bytes, 22);
+
+        let dst = tempdir.path().join("dst");
+        let report = ctx
+            .client
+            .download_dir("/cargo-test/tree", &dst, &options)
+            .unwrap();
+        assert_eq!(report.files, 2);
+        assert_eq!(report.directories, 2);
+        assert_eq!(
+            fs::read_to_string(dst.join("sub/b.txt")).unwrap(),
+            "Bonjour\n"
+        );
+        // skip existing files
+        let report = ctx
+            .client
+            .download_dir(
+                "/cargo-test/tree",
+                &dst,
+                &options.overwrite(SmbOverwritePolicy::Skip),
+            )
+            .unwrap();
+        assert_eq!(report.files, 0);
+        assert_eq!(report.skipped, 2);
+    }
+}
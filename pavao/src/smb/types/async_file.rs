@@ -0,0 +1,378 @@
+//! # Async file
+//!
+//! module which exposes the async file handle returned by [`crate::AsyncSmbClient`]
+
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use tokio::task::JoinHandle;
+
+use super::SmbFile;
+use crate::{SmbError, SmbResult};
+
+/// Maximum amount of bytes read or written by a single blocking operation
+const MAX_BUF: usize = 2 * 1024 * 1024;
+
+/// Async file opened on the server.
+///
+/// Each operation runs on the tokio blocking pool. Dropping the handle closes the file on the blocking pool
+/// as well, once the running operation, if any, completes.
+///
+/// As with `tokio::fs::File`, writes run in the background: `poll_write` returns as soon as the data has been
+/// copied, and a write which fails is reported by the next operation on the file, by a flush or by [`AsyncSmbFile::close`].
+pub struct AsyncSmbFile {
+    state: State,
+}
+
+enum State {
+    Idle(Option<Inner>),
+    Busy(JoinHandle<(Operation, Inner)>),
+}
+
+struct Inner {
+    file: SmbFile<'static>,
+    /// data read ahead; `buf[pos..]` hasn't been consumed yet
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+enum Operation {
+    Read(io::Result<usize>),
+    Write(io::Result<()>),
+    Seek(io::Result<u64>),
+    Flush(io::Result<()>),
+}
+
+impl Inner {
+    fn unread(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Move the file cursor back to the position of the reader, discarding the data read ahead
+    fn discard(&mut self) -> io::Result<()> {
+        let unread = self.unread();
+        self.buf.clear();
+        self.pos = 0;
+        if unread > 0 {
+            self.file.seek(SeekFrom::Current(-(unread as i64)))?;
+        }
+        Ok(())
+    }
+}
+
+impl AsyncSmbFile {
+    pub(crate) fn new(file: SmbFile<'static>) -> Self {
+        Self {
+            state: State::Idle(Some(Inner {
+                file,
+                buf: Vec::new(),
+                pos: 0,
+            })),
+        }
+    }
+
+    /// Write the buffered data and close the file, reporting any error.
+    ///
+    /// See [`SmbFile::close`]
+    pub async fn close(mut self) -> SmbResult<()> {
+        if let State::Busy(_) = self.state {
+            // only a background write can fail without having been reported yet
+            if let Operation::Write(Err(err)) =
+                std::future::poll_fn(|cx| self.poll_busy(cx)).await?
+            {
+                return Err(SmbError::Io(err));
+            }
+        }
+        let State::Idle(inner) = &mut self.state else {
+            return Err(SmbError::Io(io::Error::other(
+                "an operation is still running",
+            )));
+        };
+        let inner = Self::take_idle(inner)?;
+        tokio::task::spawn_blocking(move || inner.file.close())
+            .await
+            .map_err(|err| SmbError::Io(io::Error::other(err)))?
+    }
+
+    /// Take the file to run an operation on it
+    fn take_idle(inner: &mut Option<Inner>) -> io::Result<Inner> {
+        inner
+            .take()
+            .ok_or_else(|| io::Error::other("file handle has been lost by a failed operation"))
+    }
+
+    /// Wait for the running operation to complete
+    fn poll_busy(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Operation>> {
+        let State::Busy(handle) = &mut self.state else {
+            return Poll::Ready(Err(io::Error::other("no operation is running")));
+        };
+        let result = ready!(Pin::new(handle).poll(cx));
+        Poll::Ready(match result {
+            Ok((operation, inner)) => {
+                self.state = State::Idle(Some(inner));
+                Ok(operation)
+            }
+            Err(err) => {
+                self.state = State::Idle(None);
+                Err(io::Error::other(err))
+            }
+        })
+    }
+}
+
+impl Drop for AsyncSmbFile {
+    fn drop(&mut self) {
+        // a running operation keeps the file, which is closed on the blocking pool once it completes
+        let State::Idle(inner) = &mut self.state else {
+            return;
+        };
+        let Some(inner) = inner.take() else {
+            return;
+        };
+        // closing flushes the buffered data, so don't block the runtime
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(move || {
+                    if let Err(err) = inner.file.close() {
+                        error!("failed to close dropped file: {}", err);
+                    }
+                });
+            }
+            Err(_) => drop(inner),
+        }
+    }
+}
+
+impl AsyncRead for AsyncSmbFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        dst: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            match &mut this.state {
+                State::Idle(inner) => {
+                    let mut file = Self::take_idle(inner)?;
+                    if file.unread() > 0 {
+                        let n = file.unread().min(dst.remaining());
+                        dst.put_slice(&file.buf[file.pos..file.pos + n]);
+                        file.pos += n;
+                        this.state = State::Idle(Some(file));
+                        return Poll::Ready(Ok(()));
+                    }
+                    let len = dst.remaining().min(MAX_BUF);
+                    this.state = State::Busy(tokio::task::spawn_blocking(move || {
+                        file.buf.resize(len, 0);
+                        file.pos = 0;
+                        let result = file.file.read(&mut file.buf);
+                        file.buf.truncate(*result.as_ref().unwrap_or(&0));
+                        (Operation::Read(result), file)
+                    }));
+                }
+                State::Busy(_) => match ready!(this.poll_busy(cx))? {
+                    Operation::Read(Ok(0)) => return Poll::Ready(Ok(())),
+                    Operation::Read(Err(err)) | Operation::Write(Err(err)) => {
+                        return Poll::Ready(Err(err))
+                    }
+                    // copy the data read, or start reading
+                    _ => {}
+                },
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncSmbFile {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        src: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            match &mut this.state {
+                State::Idle(inner) => {
+                    let mut file = Self::take_idle(inner)?;
+                    let data = src[..src.len().min(MAX_BUF)].to_vec();
+                    let len = data.len();
+                    this.state = State::Busy(tokio::task::spawn_blocking(move || {
+                        let result = file.discard().and_then(|()| file.file.write_all(&data));
+                        (Operation::Write(result), file)
+                    }));
+                    return Poll::Ready(Ok(len));
+                }
+                // wait for the previous operation; the result of a dropped read or seek is discarded
+                State::Busy(_) => {
+                    if let Operation::Write(Err(err)) = ready!(this.poll_busy(cx))? {
+                        return Poll::Ready(Err(err));
+                    }
+                }
+            }
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            match &mut this.state {
+                State::Idle(inner) => {
+                    let mut file = Self::take_idle(inner)?;
+                    this.state = State::Busy(tokio::task::spawn_blocking(move || {
+                        let result = file.file.flush();
+                        (Operation::Flush(result), file)
+                    }));
+                }
+                State::Busy(_) => match ready!(this.poll_busy(cx))? {
+                    Operation::Flush(result) => return Poll::Ready(result),
+                    Operation::Write(Err(err)) => return Poll::Ready(Err(err)),
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl AsyncSeek for AsyncSmbFile {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = &mut *self;
+        let State::Idle(inner) = &mut this.state else {
+            return Err(io::Error::other("another operation is running on the file"));
+        };
+        let mut file = Self::take_idle(inner)?;
+        this.state = State::Busy(tokio::task::spawn_blocking(move || {
+            let result = file.discard().and_then(|()| file.file.seek(position));
+            (Operation::Seek(result), file)
+        }));
+        Ok(())
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        loop {
+            if let State::Idle(_) = self.state {
+                // no seek is running: get the current position
+                self.as_mut().start_seek(SeekFrom::Current(0))?;
+            }
+            match ready!(self.poll_busy(cx))? {
+                Operation::Seek(result) => return Poll::Ready(result),
+                Operation::Write(Err(err)) => return Poll::Ready(Err(err)),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::SeekFrom;
+    use std::pin::Pin;
+    use std::task::Poll;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+    use crate::mock;
+    use crate::test::TestCtx;
+    use crate::{AsyncSmbClient, SmbOpenOptions, SmbOptions};
+
+    #[test]
+    #[serial]
+    fn should_read_write_and_seek_asynchronously() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let client = AsyncSmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let mut file = client
+                    .open_with(
+                        "/cargo-test/test.txt",
+                        SmbOpenOptions::default()
+                            .create(true)
+                            .read(true)
+                            .write(true),
+                    )
+                    .await
+                    .unwrap();
+                file.write_all(b"Hello, World!\n").await.unwrap();
+                file.flush().await.unwrap();
+                assert_eq!(file.seek(SeekFrom::Start(7)).await.unwrap(), 7);
+                let mut buf = [0; 5];
+                file.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"World");
+                assert_eq!(file.stream_position().await.unwrap(), 12);
+                file.write_all(b"?").await.unwrap();
+                file.rewind().await.unwrap();
+                let mut content = String::new();
+                file.read_to_string(&mut content).await.unwrap();
+                assert_eq!(content.as_str(), "Hello, World?\n");
+                file.close().await.unwrap();
+                assert_eq!(client.stat("/cargo-test/test.txt").await.unwrap().size, 14);
+            });
+    }
+
+    #[test]
+    #[serial]
+    fn should_write_data_of_dropped_write() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let client = AsyncSmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let mut file = client
+                    .open_with(
+                        "/cargo-test/test.txt",
+                        SmbOpenOptions::default()
+                            .create(true)
+                            .read(true)
+                            .write(true),
+                    )
+                    .await
+                    .unwrap();
+                // poll a write once, then drop its future
+                let written = std::future::poll_fn(|cx| {
+                    Poll::Ready(Pin::new(&mut file).poll_write(cx, b"Hello"))
+                })
+                .await;
+                assert!(matches!(written, Poll::Ready(Ok(5))));
+                file.write_all(b", World!\n").await.unwrap();
+                file.rewind().await.unwrap();
+                let mut content = String::new();
+                file.read_to_string(&mut content).await.unwrap();
+                assert_eq!(content.as_str(), "Hello, World!\n");
+                file.close().await.unwrap();
+            });
+    }
+
+    #[test]
+    #[serial]
+    fn should_close_dropped_file() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let client = AsyncSmbClient::new(ctx.credentials(), SmbOptions::default()).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let mut file = client
+                .open_with(
+                    "/cargo-test/test.txt",
+                    SmbOpenOptions::default().create(true).write(true),
+                )
+                .await
+                .unwrap();
+            file.write_all(b"Hello, World!\n").await.unwrap();
+        });
+        // the runtime waits for the file to be closed on the blocking pool
+        drop(runtime);
+        assert_eq!(ctx.client.stat("/cargo-test/test.txt").unwrap().size, 14);
+    }
+}
@@ -0,0 +1,177 @@
+//! # Atomic
+//!
+//! module which exposes the atomic replacement of files on the share
+
+use std::io::{self, Write};
+
+use libc::mode_t;
+
+use super::{SmbCopyOptions, SmbFile};
+use crate::{SmbClient, SmbError, SmbResult};
+
+/// Options for atomic writes
+#[derive(Debug, Clone)]
+pub struct SmbAtomicOptions {
+    pub(crate) backup: Option<String>,
+    pub(crate) mode: mode_t,
+}
+
+impl Default for SmbAtomicOptions {
+    fn default() -> Self {
+        Self {
+            backup: None,
+            mode: 0o644,
+        }
+    }
+}
+
+impl SmbAtomicOptions {
+    /// Keep a copy of the previous version of the file, named with `suffix` appended to its name.
+    ///
+    /// The copy is made before the file is replaced, so the file is never moved away to make the backup
+    pub fn backup<S>(mut self, suffix: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.backup = Some(suffix.as_ref().to_string());
+        self
+    }
+
+    /// Set POSIX mode of the written file
+    pub fn mode(mut self, mode: mode_t) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+/// Writer which replaces a file atomically, obtained with [`SmbClient::atomic_writer`].
+///
+/// Content is written to a hidden temporary file in the same directory, which is renamed over the target
+/// on [`SmbAtomicFile::commit`]. If the writer is dropped without being committed, the temporary file is removed
+/// and the target is left untouched.
+///
+/// The rename is not atomic when the target exists: see [`SmbClient::atomic_writer`]
+pub struct SmbAtomicFile<'a> {
+    client: &'a SmbClient,
+    file: Option<SmbFile<'a>>,
+    path: String,
+    temp: String,
+    backup: Option<String>,
+}
+
+impl<'a> SmbAtomicFile<'a> {
+    pub(crate) fn new(
+        client: &'a SmbClient,
+        file: SmbFile<'a>,
+        path: String,
+        temp: String,
+        backup: Option<String>,
+    ) -> Self {
+        Self {
+            client,
+            file: Some(file),
+            path,
+            temp,
+            backup,
+        }
+    }
+
+    /// Get the path of the temporary file
+    pub fn temp_path(&self) -> &str {
+        self.temp.as_str()
+    }
+
+    /// Close the temporary file and rename it over the target, copying the previous version to the backup
+    /// first if configured.
+    ///
+    /// If the backup fails, the temporary file is removed and the target is left untouched. If the rename fails,
+    /// the temporary file is kept, since the target may have been removed already: its content is then at
+    /// [`SmbAtomicFile::temp_path`]. See [`SmbClient::atomic_writer`] for the window in which the target doesn't exist.
+    pub fn commit(mut self) -> SmbResult<()> {
+        trace!("committing {} to {}", self.temp, self.path);
+        if let Some(file) = self.file.take() {
+            file.close()?;
+        }
+        if let Some(suffix) = self.backup.as_deref() {
+            let backup = format!("{}{}", self.path, suffix);
+            match self.client.copy_to(
+                self.path.as_str(),
+                self.client,
+                backup.as_str(),
+                &SmbCopyOptions::default().overwrite(true),
+            ) {
+                Ok(_) => trace!("backed up {} to {}", self.path, backup),
+                Err(SmbError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    error!("failed to back up {}: {}", self.path, err);
+                    return Err(err);
+                }
+            }
+        }
+        if let Err(err) = self.client.rename(self.temp.as_str(), self.path.as_str()) {
+            error!("failed to rename {} to {}: {}", self.temp, self.path, err);
+            warn!("new content of {} is left at {}", self.path, self.temp);
+            // the target may have been removed already, so the temporary file is the only copy of the content
+            self.temp.clear();
+            return Err(err);
+        }
+        // nothing left to clean up
+        self.temp.clear();
+        debug!("replaced {}", self.path);
+        Ok(())
+    }
+
+    /// Discard the written content, removing the temporary file
+    pub fn abort(self) {
+        trace!("aborting write of {}", self.path);
+    }
+
+    fn file(&mut self) -> io::Result<&mut SmbFile<'a>> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("file has already been closed"))
+    }
+}
+
+impl Write for SmbAtomicFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Drop for SmbAtomicFile<'_> {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            // the content is discarded anyway
+            let _ = file.close();
+        }
+        if !self.temp.is_empty() {
+            trace!("removing temporary file {}", self.temp);
+            if let Err(err) = self.client.unlink(self.temp.as_str()) {
+                error!("failed to remove temporary file {}: {}", self.temp, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn should_build_atomic_options() {
+        let options = SmbAtomicOptions::default();
+        assert_eq!(options.backup, None);
+        assert_eq!(options.mode, 0o644);
+        let options = options.backup(".bak").mode(0o600);
+        assert_eq!(options.backup.as_deref(), Some(".bak"));
+        assert_eq!(options.mode, 0o600);
+    }
+}
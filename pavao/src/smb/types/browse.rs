@@ -0,0 +1,237 @@
+//! # Browse
+//!
+//! module which exposes the types returned by network browsing
+
+use super::{SmbDirent, SmbDirentType};
+use crate::SmbError;
+
+/// Workgroup found on the network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmbWorkgroupInfo {
+    name: String,
+    comment: String,
+}
+
+impl SmbWorkgroupInfo {
+    /// Get workgroup name
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Get comment
+    pub fn comment(&self) -> &str {
+        self.comment.as_str()
+    }
+}
+
+impl TryFrom<SmbDirent> for SmbWorkgroupInfo {
+    type Error = SmbError;
+
+    fn try_from(d: SmbDirent) -> Result<Self, Self::Error> {
+        match d.get_type() {
+            SmbDirentType::Workgroup => Ok(Self {
+                name: d.name().to_string(),
+                comment: d.comment().to_string(),
+            }),
+            _ => Err(SmbError::BadValue),
+        }
+    }
+}
+
+/// Server found in a workgroup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmbServerInfo {
+    name: String,
+    comment: String,
+}
+
+impl SmbServerInfo {
+    /// Get server name
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Get comment
+    pub fn comment(&self) -> &str {
+        self.comment.as_str()
+    }
+}
+
+impl TryFrom<SmbDirent> for SmbServerInfo {
+    type Error = SmbError;
+
+    fn try_from(d: SmbDirent) -> Result<Self, Self::Error> {
+        match d.get_type() {
+            SmbDirentType::Server => Ok(Self {
+                name: d.name().to_string(),
+                comment: d.comment().to_string(),
+            }),
+            _ => Err(SmbError::BadValue),
+        }
+    }
+}
+
+/// Share exposed by a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmbShareInfo {
+    name: String,
+    comment: String,
+    type_: SmbShareType,
+}
+
+impl SmbShareInfo {
+    /// Get share name
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Get comment
+    pub fn comment(&self) -> &str {
+        self.comment.as_str()
+    }
+
+    /// Get share type
+    pub fn get_type(&self) -> SmbShareType {
+        self.type_
+    }
+
+    /// Returns whether the share is hidden (its name ends with `$`)
+    pub fn is_hidden(&self) -> bool {
+        self.name.ends_with('$')
+    }
+}
+
+impl TryFrom<SmbDirent> for SmbShareInfo {
+    type Error = SmbError;
+
+    fn try_from(d: SmbDirent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            type_: SmbShareType::try_from(d.get_type())?,
+            name: d.name().to_string(),
+            comment: d.comment().to_string(),
+        })
+    }
+}
+
+/// Type of share
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SmbShareType {
+    File,
+    Printer,
+    Comms,
+    Ipc,
+}
+
+impl From<SmbShareType> for SmbDirentType {
+    fn from(type_: SmbShareType) -> Self {
+        match type_ {
+            SmbShareType::File => SmbDirentType::FileShare,
+            SmbShareType::Printer => SmbDirentType::PrinterShare,
+            SmbShareType::Comms => SmbDirentType::CommsShare,
+            SmbShareType::Ipc => SmbDirentType::IpcShare,
+        }
+    }
+}
+
+impl TryFrom<SmbDirentType> for SmbShareType {
+    type Error = SmbError;
+
+    fn try_from(type_: SmbDirentType) -> Result<Self, Self::Error> {
+        match type_ {
+            SmbDirentType::FileShare => Ok(Self::File),
+            SmbDirentType::PrinterShare => Ok(Self::Printer),
+            SmbDirentType::CommsShare => Ok(Self::Comms),
+            SmbDirentType::IpcShare => Ok(Self::Ipc),
+            _ => Err(SmbError::BadValue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use pavao_sys::smbc_dirent;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::utils;
+
+    #[test]
+    fn should_convert_dirent_type_to_share_type() {
+        assert_eq!(
+            SmbShareType::try_from(SmbDirentType::FileShare).unwrap(),
+            SmbShareType::File
+        );
+        assert_eq!(
+            SmbShareType::try_from(SmbDirentType::PrinterShare).unwrap(),
+            SmbShareType::Printer
+        );
+        assert_eq!(
+            SmbShareType::try_from(SmbDirentType::CommsShare).unwrap(),
+            SmbShareType::Comms
+        );
+        assert_eq!(
+            SmbShareType::try_from(SmbDirentType::IpcShare).unwrap(),
+            SmbShareType::Ipc
+        );
+        assert!(SmbShareType::try_from(SmbDirentType::Dir).is_err());
+        assert!(SmbShareType::try_from(SmbDirentType::Server).is_err());
+    }
+
+    #[test]
+    fn should_convert_share_type_to_dirent_type() {
+        assert_eq!(
+            SmbDirentType::from(SmbShareType::File),
+            SmbDirentType::FileShare
+        );
+        assert_eq!(
+            SmbDirentType::from(SmbShareType::Printer),
+            SmbDirentType::PrinterShare
+        );
+        assert_eq!(
+            SmbDirentType::from(SmbShareType::Comms),
+            SmbDirentType::CommsShare
+        );
+        assert_eq!(
+            SmbDirentType::from(SmbShareType::Ipc),
+            SmbDirentType::IpcShare
+        );
+    }
+
+    #[test]
+    fn should_convert_dirent_to_browse_types() {
+        let share = SmbShareInfo::try_from(dirent(3, "temp", "temporary files")).unwrap();
+        assert_eq!(share.name(), "temp");
+        assert_eq!(share.comment(), "temporary files");
+        assert_eq!(share.get_type(), SmbShareType::File);
+        assert_eq!(share.is_hidden(), false);
+        let share = SmbShareInfo::try_from(dirent(6, "IPC$", "IPC Service")).unwrap();
+        assert_eq!(share.get_type(), SmbShareType::Ipc);
+        assert_eq!(share.is_hidden(), true);
+        assert!(SmbShareInfo::try_from(dirent(8, "file", "")).is_err());
+
+        let server = SmbServerInfo::try_from(dirent(2, "NAS", "storage")).unwrap();
+        assert_eq!(server.name(), "NAS");
+        assert_eq!(server.comment(), "storage");
+        assert!(SmbServerInfo::try_from(dirent(1, "PAVAO", "")).is_err());
+
+        let workgroup = SmbWorkgroupInfo::try_from(dirent(1, "PAVAO", "NAS")).unwrap();
+        assert_eq!(workgroup.name(), "PAVAO");
+        assert_eq!(workgroup.comment(), "NAS");
+        assert!(SmbWorkgroupInfo::try_from(dirent(2, "NAS", "")).is_err());
+    }
+
+    fn dirent(smbc_type: libc::c_uint, name: &str, comment: &str) -> SmbDirent {
+        let mut dirent = smbc_dirent {
+            smbc_type,
+            comment: utils::str_to_cstring(comment).unwrap().into_raw(),
+            commentlen: comment.len() as _,
+            namelen: name.len() as _,
+            ..Default::default()
+        };
+        for (i, c) in name.bytes().enumerate() {
+            dirent.name[i] = c as libc::c_char;
+        }
+        SmbDirent::try_from(dirent).unwrap()
+    }
+}
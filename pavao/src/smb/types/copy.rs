@@ -0,0 +1,107 @@
+//! # Copy
+//!
+//! module which exposes the options and the report of copy and move operations
+
+/// Default size of the buffer used to copy files
+const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Options for copying and moving files between clients
+#[derive(Debug, Clone)]
+pub struct SmbCopyOptions {
+    pub(crate) buffer_size: usize,
+    pub(crate) overwrite: bool,
+    pub(crate) preserve_attributes: bool,
+    pub(crate) preserve_times: bool,
+    pub(crate) verify_size: bool,
+}
+
+impl Default for SmbCopyOptions {
+    fn default() -> Self {
+        Self {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            overwrite: false,
+            preserve_attributes: true,
+            preserve_times: true,
+            verify_size: true,
+        }
+    }
+}
+
+impl SmbCopyOptions {
+    /// Set the size of the buffer used to stream files. Must be greater than 0
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Overwrite existing files at destination
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Copy DOS attributes (read-only, hidden, system, archive) where possible
+    pub fn preserve_attributes(mut self, preserve_attributes: bool) -> Self {
+        self.preserve_attributes = preserve_attributes;
+        self
+    }
+
+    /// Copy access and modification times where possible
+    pub fn preserve_times(mut self, preserve_times: bool) -> Self {
+        self.preserve_times = preserve_times;
+        self
+    }
+
+    /// Check that the size of copied files matches the source
+    pub fn verify_size(mut self, verify_size: bool) -> Self {
+        self.verify_size = verify_size;
+        self
+    }
+}
+
+/// Report of a copy or move operation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmbCopyReport {
+    /// Amount of files copied
+    pub files: u64,
+    /// Amount of directories created
+    pub directories: u64,
+    /// Amount of bytes copied
+    pub bytes: u64,
+    /// Whether the entry has been moved with a rename, without copying data
+    pub renamed: bool,
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn should_initialize_copy_options() {
+        let options = SmbCopyOptions::default();
+        assert_eq!(options.buffer_size, DEFAULT_BUFFER_SIZE);
+        assert_eq!(options.overwrite, false);
+        assert_eq!(options.preserve_attributes, true);
+        assert_eq!(options.preserve_times, true);
+        assert_eq!(options.verify_size, true);
+    }
+
+    #[test]
+    fn should_configure_copy_options() {
+        let options = SmbCopyOptions::default()
+            .buffer_size(4096)
+            .overwrite(true)
+            .preserve_attributes(false)
+            .preserve_times(false)
+            .verify_size(false);
+        assert_eq!(options.buffer_size, 4096);
+        assert_eq!(options.overwrite, true);
+        assert_eq!(options.preserve_attributes, false);
+        assert_eq!(options.preserve_times, false);
+        assert_eq!(options.verify_size, false);
+        assert_eq!(SmbCopyOptions::default().buffer_size(0).buffer_size, 1);
+    }
+}
@@ -2,49 +2,255 @@
 //!
 //! file type returned by open functions on server
 
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::{mem, ptr};
 
 use libc::{c_int, c_void, mode_t, off_t};
 use pavao_sys::{
     smbc_getFunctionClose, smbc_getFunctionLseek, smbc_getFunctionRead, smbc_getFunctionWrite,
-    SMBCFILE,
+    SMBCCTX, SMBCFILE,
 };
 
-use crate::{utils, SmbClient};
+use crate::{utils, SmbClient, SmbResult};
 
+/// Default size of the read and write buffers.
+///
+/// libsmbclient doesn't expose the max read/write size negotiated with the server, so this is the
+/// largest size every SMB2 server accepts in a single request.
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Client a file has been opened with
+pub(crate) enum FileClient<'a> {
+    Borrowed(&'a SmbClient),
+    Owned(Arc<SmbClient>),
+}
+
+impl Deref for FileClient<'_> {
+    type Target = SmbClient;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Borrowed(client) => client,
+            Self::Owned(client) => client.as_ref(),
+        }
+    }
+}
+
+/// File opened on the server.
+///
+/// Reads and writes are buffered; buffered data is written on [`Write::flush`], before seeking and before reading.
+/// Data still buffered when the file is dropped is written too, and the file is closed, but errors can't be
+/// reported then, so [`SmbFile::close`] should be called once done writing: on SMB a failed close may be
+/// the only sign that written data hasn't been committed.
+///
+/// Files opened with [`SmbClient::open_owned`] are `SmbFile<'static>`, as they own a reference to the client.
+/// Files are `Send` and `Sync`: like `std::os::unix::fs::FileExt`, [`SmbFile::read_at`] and [`SmbFile::write_at`]
+/// only need a shared reference, so a file can be read and written at different offsets from many threads.
 pub struct SmbFile<'a> {
-    smbc: &'a SmbClient,
+    smbc: FileClient<'a>,
     fd: *mut SMBCFILE,
+    buffers: Mutex<Buffers>,
+    read_capacity: usize,
+    write_capacity: usize,
+}
+
+/// Read and write buffers of a file; locked by positional reads and writes, which take a shared reference
+#[derive(Default)]
+struct Buffers {
+    /// read buffer; `read[pos..filled]` hasn't been consumed yet
+    read: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    write: Vec<u8>,
+    /// whether the file has been written
+    written: bool,
 }
 
 impl<'a> SmbFile<'a> {
-    pub(crate) fn new(smbc: &'a SmbClient, fd: *mut SMBCFILE) -> Self {
-        Self { smbc, fd }
+    pub(crate) fn new(smbc: FileClient<'a>, fd: *mut SMBCFILE, options: &SmbOpenOptions) -> Self {
+        Self {
+            smbc,
+            fd,
+            buffers: Mutex::new(Buffers::default()),
+            read_capacity: options.read_buffer_size,
+            write_capacity: options.write_buffer_size,
+        }
     }
-}
 
-impl Read for SmbFile<'_> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    /// Write the buffered data and close the file, reporting any error
+    pub fn close(mut self) -> SmbResult<()> {
+        trace!("closing file");
+        let flushed = self.flush_buf();
+        let closed = self.close_fd();
+        flushed?;
+        closed
+    }
+
+    /// Close the file descriptor, unless it has already been closed
+    fn close_fd(&mut self) -> SmbResult<()> {
+        let fd = mem::replace(&mut self.fd, ptr::null_mut());
+        if fd.is_null() {
+            return Ok(());
+        }
+        let ctx = self.smbc.ctx()?;
+        let close_fn = self.smbc.get_fn(**ctx, smbc_getFunctionClose)?;
+        utils::to_result_with_ioerror((), close_fn(**ctx, fd))
+    }
+
+    /// Lock the buffers; they're only changed by complete operations, so a poisoned lock is still usable
+    fn buffers(&self) -> MutexGuard<'_, Buffers> {
+        self.buffers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Write the content of the write buffer to the file
+    fn flush_buf(&self) -> io::Result<()> {
+        self.flush_buf_with(&mut self.buffers())
+    }
+
+    fn flush_buf_with(&self, buffers: &mut Buffers) -> io::Result<()> {
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < buffers.write.len() {
+            match self.raw_write(&buffers.write[written..]) {
+                Ok(0) => {
+                    result = Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write the buffered data",
+                    ));
+                    break;
+                }
+                Ok(n) => written += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        buffers.write.drain(..written);
+        result
+    }
+
+    /// Discard the content of the read buffer, moving the file cursor back to the position of the reader
+    fn discard_buf_with(&self, buffers: &mut Buffers) -> io::Result<()> {
+        let unread = buffers.filled - buffers.pos;
+        buffers.pos = 0;
+        buffers.filled = 0;
+        if unread > 0 {
+            self.raw_seek(-(unread as off_t), libc::SEEK_CUR)?;
+        }
+        Ok(())
+    }
+
+    /// Read from the file at `offset`, without moving the cursor.
+    ///
+    /// Seeking, reading and restoring the cursor happen under the context lock, so they are atomic
+    /// with respect to any other operation on the client.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        trace!("reading file at {}", offset);
+        self.flush_buf()?;
+        self.at(offset, |file, ctx| file.raw_read_with(ctx, buf))
+    }
+
+    /// Write to the file at `offset`, without moving the cursor.
+    ///
+    /// See [`SmbFile::read_at`]
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        trace!("writing file at {}", offset);
+        let mut buffers = self.buffers();
+        buffers.written = true;
+        self.flush_buf_with(&mut buffers)?;
+        // the read buffer may contain the region being written
+        self.discard_buf_with(&mut buffers)?;
+        self.at(offset, |file, ctx| file.raw_write_with(ctx, buf))
+    }
+
+    /// Read the exact amount of bytes required to fill `buf` from the file at `offset`, without moving the cursor
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the whole `buf` to the file at `offset`, without moving the cursor
+    pub fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `op` with the cursor at `offset`, then restore the cursor; the context is locked meanwhile
+    fn at<T, F>(&self, offset: u64, op: F) -> io::Result<T>
+    where
+        F: FnOnce(&Self, *mut SMBCCTX) -> io::Result<T>,
+    {
+        let ctx = self.smbc.ctx().map_err(io::Error::other)?;
+        let cursor = self.raw_seek_with(**ctx, 0, libc::SEEK_CUR)?;
+        self.raw_seek_with(**ctx, offset as off_t, libc::SEEK_SET)?;
+        let result = op(self, **ctx);
+        let restored = self.raw_seek_with(**ctx, cursor as off_t, libc::SEEK_SET);
+        let result = result?;
+        restored?;
+        Ok(result)
+    }
+
+    fn raw_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let ctx = self.smbc.ctx().map_err(io::Error::other)?;
+        self.raw_read_with(**ctx, buf)
+    }
+
+    fn raw_read_with(&self, ctx: *mut SMBCCTX, buf: &mut [u8]) -> io::Result<usize> {
         trace!("reading file to buf [{:?};{}]", buf.as_ptr(), buf.len());
-        let ctx = self.smbc.ctx();
-        let read_fn = self.smbc.get_fn(**ctx, smbc_getFunctionRead)?;
+        let read_fn = self.smbc.get_fn(ctx, smbc_getFunctionRead)?;
         let bytes_read = utils::to_result_with_le(read_fn(
-            **ctx,
+            ctx,
             self.fd,
             buf.as_mut_ptr() as *mut c_void,
             buf.len() as _,
         ))?;
         Ok(bytes_read as usize)
     }
-}
 
-impl Write for SmbFile<'_> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    fn raw_write(&self, buf: &[u8]) -> io::Result<usize> {
+        let ctx = self.smbc.ctx().map_err(io::Error::other)?;
+        self.raw_write_with(**ctx, buf)
+    }
+
+    fn raw_write_with(&self, ctx: *mut SMBCCTX, buf: &[u8]) -> io::Result<usize> {
         trace!("writing buf [{:?};{}] to file", buf.as_ptr(), buf.len());
-        let ctx = self.smbc.ctx();
-        let write_fn = self.smbc.get_fn(**ctx, smbc_getFunctionWrite)?;
+        let write_fn = self.smbc.get_fn(ctx, smbc_getFunctionWrite)?;
         let bytes_wrote = utils::to_result_with_le(write_fn(
-            **ctx,
+            ctx,
             self.fd,
             buf.as_ptr() as *const c_void,
             buf.len() as _,
@@ -52,34 +258,142 @@ impl Write for SmbFile<'_> {
         Ok(bytes_wrote as usize)
     }
 
+    fn raw_seek(&self, off: off_t, whence: c_int) -> io::Result<u64> {
+        let ctx = self.smbc.ctx().map_err(io::Error::other)?;
+        self.raw_seek_with(**ctx, off, whence)
+    }
+
+    fn raw_seek_with(&self, ctx: *mut SMBCCTX, off: off_t, whence: c_int) -> io::Result<u64> {
+        let lseek_fn = self.smbc.get_fn(ctx, smbc_getFunctionLseek)?;
+        let res = lseek_fn(ctx, self.fd, off, whence);
+        let res = utils::to_result_with_errno(res, libc::EINVAL)?;
+        Ok(res as u64)
+    }
+}
+
+// SAFETY: the file descriptor is only used under the lock of the context of the client, which is `Sync`,
+// and the buffers are behind a mutex.
+unsafe impl Send for SmbFile<'_> {}
+unsafe impl Sync for SmbFile<'_> {}
+
+impl Read for SmbFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.flush_buf()?;
+        // bypass the buffer for large reads
+        let buffers = self
+            .buffers
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        if buffers.pos == buffers.filled && buf.len() >= self.read_capacity {
+            return self.raw_read(buf);
+        }
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for SmbFile<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        {
+            let mut buffers = self.buffers();
+            self.flush_buf_with(&mut buffers)?;
+            if buffers.pos == buffers.filled {
+                if buffers.read.is_empty() {
+                    buffers.read = vec![0; self.read_capacity.max(1)];
+                }
+                let mut read_buf = std::mem::take(&mut buffers.read);
+                let result = self.raw_read(&mut read_buf);
+                buffers.read = read_buf;
+                buffers.filled = result?;
+                buffers.pos = 0;
+            }
+        }
+        let buffers = self
+            .buffers
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(&buffers.read[buffers.pos..buffers.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let buffers = self
+            .buffers
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        buffers.pos = (buffers.pos + amt).min(buffers.filled);
+    }
+}
+
+impl Write for SmbFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffers = self.buffers();
+        buffers.written = true;
+        self.discard_buf_with(&mut buffers)?;
+        if buffers.write.len() + buf.len() > self.write_capacity {
+            self.flush_buf_with(&mut buffers)?;
+        }
+        if buf.len() >= self.write_capacity {
+            self.raw_write(buf)
+        } else {
+            if buffers.write.capacity() == 0 {
+                buffers.write.reserve_exact(self.write_capacity);
+            }
+            buffers.write.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
     fn flush(&mut self) -> io::Result<()> {
-        trace!("flush is not supported on SmbFile");
-        Ok(())
+        let mut buffers = self.buffers();
+        trace!("flushing {} buffered bytes", buffers.write.len());
+        self.flush_buf_with(&mut buffers)
     }
 }
 
 impl Seek for SmbFile<'_> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         trace!("seeking file at {:?}", pos);
-        let ctx = self.smbc.ctx();
-        let lseek_fn = self.smbc.get_fn(**ctx, smbc_getFunctionLseek)?;
+        let mut buffers = self.buffers();
+        self.flush_buf_with(&mut buffers)?;
+        let unread = (buffers.filled - buffers.pos) as off_t;
+        buffers.pos = 0;
+        buffers.filled = 0;
         let (whence, off) = match pos {
             SeekFrom::Start(p) => (libc::SEEK_SET, p as off_t),
             SeekFrom::End(p) => (libc::SEEK_END, p as off_t),
-            SeekFrom::Current(p) => (libc::SEEK_CUR, p as off_t),
+            // the cursor of the file is ahead of the reader by the unread bytes
+            SeekFrom::Current(p) => (libc::SEEK_CUR, p as off_t - unread),
         };
-        let res = lseek_fn(**ctx, self.fd, off, whence);
-        let res = utils::to_result_with_errno(res, libc::EINVAL)?;
-        Ok(res as u64)
+        self.raw_seek(off, whence)
     }
 }
 
 impl Drop for SmbFile<'_> {
     fn drop(&mut self) {
+        if self.fd.is_null() {
+            return;
+        }
+        let mut buffers = self.buffers();
+        if cfg!(debug_assertions) && buffers.written {
+            warn!("written file dropped without being closed: close errors can't be reported");
+        }
+        if let Err(err) = self.flush_buf_with(&mut buffers) {
+            error!(
+                "failed to write {} buffered bytes on drop: {}",
+                buffers.write.len(),
+                err
+            );
+        }
+        drop(buffers);
         trace!("closing file");
-        let ctx = self.smbc.ctx();
-        if let Ok(close_fn) = self.smbc.get_fn(**ctx, smbc_getFunctionClose) {
-            close_fn(**ctx, self.fd);
+        if let Err(err) = self.close_fd() {
+            error!("failed to close file on drop: {}", err);
         }
     }
 }
@@ -95,6 +409,8 @@ pub struct SmbOpenOptions {
     write: bool,
     /// for posix file mode
     pub(crate) mode: mode_t,
+    pub(crate) read_buffer_size: usize,
+    pub(crate) write_buffer_size: usize,
 }
 
 impl Default for SmbOpenOptions {
@@ -104,6 +420,8 @@ impl Default for SmbOpenOptions {
             read: false,
             write: false,
             mode: 0o644,
+            read_buffer_size: DEFAULT_BUFFER_SIZE,
+            write_buffer_size: DEFAULT_BUFFER_SIZE,
         }
     }
 }
@@ -154,6 +472,25 @@ impl SmbOpenOptions {
         self
     }
 
+    /// Set the size of the read buffer. Reads larger than the buffer bypass it.
+    ///
+    /// Defaults to 64 KiB, the largest size every SMB2 server accepts in a single request. The size negotiated with
+    /// the server can't be queried through libsmbclient, so the default isn't tuned to it: with servers which accept
+    /// larger requests, a larger buffer may reduce the amount of round trips.
+    pub fn read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = size;
+        self
+    }
+
+    /// Set the size of the write buffer; `0` disables write buffering.
+    ///
+    /// Defaults to 64 KiB; see [`SmbOpenOptions::read_buffer_size`] for why it isn't the size negotiated with the
+    /// server.
+    pub fn write_buffer_size(mut self, size: usize) -> Self {
+        self.write_buffer_size = size;
+        self
+    }
+
     /// Naive impl, rewrite to check for incompatible flags
     pub(crate) fn to_flags(self) -> c_int {
         let base_mode = match (self.read, self.write) {
@@ -179,8 +516,11 @@ impl SmbOpenOptions {
 mod test {
 
     use pretty_assertions::assert_eq;
+    use serial_test::serial;
 
     use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
 
     #[test]
     fn should_initialize_open_options() {
@@ -188,6 +528,8 @@ mod test {
         assert_eq!(open_opts.read, false);
         assert_eq!(open_opts.write, false);
         assert_eq!(open_opts.mode, 0o644);
+        assert_eq!(open_opts.read_buffer_size, DEFAULT_BUFFER_SIZE);
+        assert_eq!(open_opts.write_buffer_size, DEFAULT_BUFFER_SIZE);
         assert_eq!(open_opts.to_flags(), 0);
     }
 
@@ -200,13 +542,149 @@ mod test {
             .exclusive(true)
             .create(true)
             .truncate(true)
-            .mode(0o755);
+            .mode(0o755)
+            .read_buffer_size(1024)
+            .write_buffer_size(0);
         assert_eq!(open_opts.read, true);
         assert_eq!(open_opts.write, true);
         assert_eq!(open_opts.mode, 0o755);
+        assert_eq!(open_opts.read_buffer_size, 1024);
+        assert_eq!(open_opts.write_buffer_size, 0);
         assert_eq!(
             open_opts.to_flags(),
             libc::O_RDWR | libc::O_TRUNC | libc::O_APPEND | libc::O_EXCL | libc::O_CREAT
         );
     }
+
+    #[test]
+    #[serial]
+    fn should_buffer_writes_until_flush() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let mut writer = ctx
+            .client
+            .open_with(
+                "/cargo-test/lines.txt",
+                SmbOpenOptions::default().create(true).write(true),
+            )
+            .unwrap();
+        for i in 0..100 {
+            writeln!(writer, "line {}", i).unwrap();
+        }
+        assert_eq!(ctx.client.stat("/cargo-test/lines.txt").unwrap().size, 0);
+        writer.flush().unwrap();
+        assert_eq!(ctx.client.stat("/cargo-test/lines.txt").unwrap().size, 790);
+        drop(writer);
+
+        let reader = ctx
+            .client
+            .open_with(
+                "/cargo-test/lines.txt",
+                SmbOpenOptions::default().read(true).read_buffer_size(16),
+            )
+            .unwrap();
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[42].as_str(), "line 42");
+    }
+
+    #[test]
+    #[serial]
+    fn should_keep_position_across_buffers() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let mut file = ctx
+            .client
+            .open_with(
+                "/cargo-test/test.txt",
+                SmbOpenOptions::default()
+                    .create(true)
+                    .read(true)
+                    .write(true),
+            )
+            .unwrap();
+        file.write_all(b"Hello, World!\n").unwrap();
+        assert_eq!(file.seek(SeekFrom::Start(0)).unwrap(), 0);
+        let mut buf = [0; 5];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"Hello");
+        // the rest of the file is buffered, but the position is the one of the reader
+        assert_eq!(file.stream_position().unwrap(), 5);
+        file.write_all(b"!").unwrap();
+        file.flush().unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content.as_str(), "Hello! World!\n");
+    }
+
+    #[test]
+    #[serial]
+    fn should_read_and_write_at_offset() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let mut file = ctx
+            .client
+            .open_with(
+                "/cargo-test/test.txt",
+                SmbOpenOptions::default()
+                    .create(true)
+                    .read(true)
+                    .write(true),
+            )
+            .unwrap();
+        file.write_all(b"Hello, World!\n").unwrap();
+        file.seek(SeekFrom::Start(2)).unwrap();
+        let mut buf = [0; 5];
+        file.read_exact_at(&mut buf, 7).unwrap();
+        assert_eq!(&buf, b"World");
+        assert_eq!(file.stream_position().unwrap(), 2);
+        file.write_all_at(b"Earth", 7).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 2);
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content.as_str(), "llo, Earth!\n");
+        assert_eq!(
+            file.read_exact_at(&mut buf, 12).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_read_at_offset_from_many_threads() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let mut file = ctx
+            .client
+            .open_with(
+                "/cargo-test/test.txt",
+                SmbOpenOptions::default()
+                    .create(true)
+                    .read(true)
+                    .write(true),
+            )
+            .unwrap();
+        file.write_all(b"Hello, World!\n").unwrap();
+        file.flush().unwrap();
+        std::thread::scope(|scope| {
+            let hello = scope.spawn(|| {
+                let mut buf = [0; 5];
+                for _ in 0..50 {
+                    file.read_exact_at(&mut buf, 0).unwrap();
+                    assert_eq!(&buf, b"Hello");
+                }
+            });
+            let world = scope.spawn(|| {
+                let mut buf = [0; 5];
+                for _ in 0..50 {
+                    file.read_exact_at(&mut buf, 7).unwrap();
+                    assert_eq!(&buf, b"World");
+                }
+            });
+            hello.join().unwrap();
+            world.join().unwrap();
+        });
+        assert_eq!(file.stream_position().unwrap(), 14);
+    }
 }
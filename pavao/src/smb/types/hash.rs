@@ -0,0 +1,46 @@
+//! # Hash
+//!
+//! module which exposes the hash of the content of remote files
+
+use std::fmt::Write;
+
+/// Hash of the content of a remote file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmbHashRecord {
+    /// Path of the file
+    pub path: String,
+    /// Amount of bytes hashed
+    pub size: u64,
+    /// Digest of the content
+    pub hash: Vec<u8>,
+}
+
+impl SmbHashRecord {
+    /// Get the digest as a lowercase hexadecimal string
+    pub fn to_hex(&self) -> String {
+        self.hash
+            .iter()
+            .fold(String::with_capacity(self.hash.len() * 2), |mut hex, b| {
+                let _ = write!(hex, "{:02x}", b);
+                hex
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn should_format_hash_as_hex() {
+        let record = SmbHashRecord {
+            path: String::from("/a.txt"),
+            size: 14,
+            hash: vec![0x00, 0x0f, 0xa0, 0xff],
+        };
+        assert_eq!(record.to_hex().as_str(), "000fa0ff");
+    }
+}
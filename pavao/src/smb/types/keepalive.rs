@@ -0,0 +1,57 @@
+//! # Keepalive
+//!
+//! module which exposes the background keepalive of a client
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Weak;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::SmbClient;
+
+/// Background keepalive of a client, obtained with [`SmbClient::keepalive`].
+///
+/// The keepalive stops when dropped.
+pub struct SmbKeepalive {
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SmbKeepalive {
+    pub(crate) fn spawn(client: Weak<SmbClient>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = thread::spawn(move || {
+            // the sender is never used: the channel is disconnected when the keepalive is dropped
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(client) = client.upgrade() else {
+                    trace!("client dropped: stopping keepalive");
+                    break;
+                };
+                if let Err(err) = client.ping() {
+                    warn!("keepalive ping failed: {}; connecting again", err);
+                    if let Err(err) = client.connect() {
+                        error!("keepalive failed to connect: {}", err);
+                    }
+                }
+            }
+        });
+        Self {
+            stop: Some(stop),
+            worker: Some(worker),
+        }
+    }
+
+    /// Stop the keepalive, waiting for the background thread to terminate
+    pub fn stop(self) {}
+}
+
+impl Drop for SmbKeepalive {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                error!("keepalive thread panicked");
+            }
+        }
+    }
+}
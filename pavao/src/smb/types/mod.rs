@@ -2,16 +2,55 @@
 //!
 //! smb types
 
+#[cfg(feature = "tokio")]
+mod async_file;
+mod atomic;
+mod browse;
+mod copy;
 mod credentials;
 mod dirent;
 mod file;
+mod hash;
+mod keepalive;
 mod mode;
 mod options;
+mod parallel_hash;
+mod parallel_transfer;
+mod parallel_walk;
+mod pool;
+mod share;
 mod stat;
+mod sync;
+mod transfer;
+mod walk;
+mod workers;
 
+#[cfg(feature = "tokio")]
+pub use async_file::AsyncSmbFile;
+pub use atomic::{SmbAtomicFile, SmbAtomicOptions};
+pub use browse::{SmbServerInfo, SmbShareInfo, SmbShareType, SmbWorkgroupInfo};
+pub use copy::{SmbCopyOptions, SmbCopyReport};
 pub use credentials::SmbCredentials;
 pub use dirent::{SmbDirent, SmbDirentType};
+pub(crate) use file::FileClient;
 pub use file::{SmbFile, SmbOpenOptions};
+pub use hash::SmbHashRecord;
+pub use keepalive::SmbKeepalive;
 pub use mode::{SmbMode, SmbModeClass};
-pub use options::{SmbEncryptionLevel, SmbOptions, SmbShareMode};
+pub(crate) use options::SmbSecurityPolicy;
+pub use options::{SmbEncryptionLevel, SmbOptions, SmbProtocol, SmbShareMode, SmbSigning};
+pub use parallel_hash::SmbParallelHash;
+pub use parallel_transfer::SmbParallelTransfer;
+pub use parallel_walk::{SmbParallelWalk, SmbParallelWalkIter};
+pub use pool::{SmbClientPool, SmbPoolMetrics, SmbPooledClient};
+pub use share::SmbShare;
 pub use stat::{SmbDirentInfo, SmbStat, SmbStatVfs};
+pub use sync::{
+    SmbConflictPolicy, SmbDeletePolicy, SmbSyncAction, SmbSyncActionKind, SmbSyncDirection,
+    SmbSyncOptions, SmbSyncPlan, SmbSyncReason, SmbSyncReport,
+};
+pub use transfer::{
+    SmbOverwritePolicy, SmbTransferOptions, SmbTransferProgress, SmbTransferReport,
+};
+pub use walk::{SmbWalkDir, SmbWalkEntry, SmbWalkErrorPolicy, SmbWalkIter};
+pub(crate) use workers::Workers;
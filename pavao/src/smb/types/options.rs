@@ -2,19 +2,103 @@
 //!
 //! module which exposes the smb client options
 
+use std::path::{Path, PathBuf};
+
 use pavao_sys::{smbc_share_mode, smbc_smb_encrypt_level};
 
+use crate::{SmbError, SmbResult};
+
+/// smb.conf parameters which are meaningful for a client, accepted by [`SmbOptions::configuration`].
+///
+/// libsmbclient doesn't expose the parameter table of loadparm, so this is a hand-kept list of the client parameters
+/// of samba 4; parameters missing from it can still be set with [`SmbOptions::configuration_file`].
+/// Names are normalized, as samba does, by removing whitespaces and lowering the case.
+const SMB_CONF_PARAMETERS: &[&str] = &[
+    "bindinterfacesonly",
+    "cachedirectory",
+    "clientipcmaxprotocol",
+    "clientipcminprotocol",
+    "clientipcsigning",
+    "clientlanmanauth",
+    "clientldapsaslwrapping",
+    "clientmaxprotocol",
+    "clientminprotocol",
+    "clientntlmv2auth",
+    "clientplaintextauth",
+    "clientprotection",
+    "clientschannel",
+    "clientsigning",
+    "clientsmb3encryptionalgorithms",
+    "clientsmb3signingalgorithms",
+    "clientsmbencrypt",
+    "clientusekerberos",
+    "clientusespnego",
+    "deadtime",
+    "debugclass",
+    "debughirestimestamp",
+    "debuglevel",
+    "debugpid",
+    "debugprefixtimestamp",
+    "debugtimestamp",
+    "debuguid",
+    "dedicatedkeytabfile",
+    "disablenetbios",
+    "doscharset",
+    "interfaces",
+    "kerberosencryptiontypes",
+    "kerberosmethod",
+    "keepalive",
+    "lockdirectory",
+    "logfile",
+    "loglevel",
+    "maxlogsize",
+    "maxxmit",
+    "namecachetimeout",
+    "nameresolveorder",
+    "nbtclientsocketaddress",
+    "netbiosaliases",
+    "netbiosname",
+    "netbiosscope",
+    "privatedir",
+    "realm",
+    "smbports",
+    "socketoptions",
+    "statedirectory",
+    "timestamplogs",
+    "unixcharset",
+    "winsserver",
+    "workgroup",
+];
+
 /// Smb connection options
+///
+/// # Security options
+///
+/// libsmbclient keeps the protocol range, the signing policy and the authentication methods in its global
+/// configuration, so [`SmbOptions::min_protocol`], [`SmbOptions::max_protocol`], [`SmbOptions::signing`] and
+/// [`SmbOptions::ntlmv2_only`] apply to every client in the process. The first client created with any of them
+/// sets the policy for the lifetime of the process: clients created later with a different policy are refused
+/// with [`SmbError::BadOption`], while clients which don't set any of them use the policy already in place.
+///
+/// Parameters set with [`SmbOptions::configuration`] or [`SmbOptions::configuration_file`] are not checked
+/// against the policy.
 #[derive(Debug, Clone)]
 pub struct SmbOptions {
     pub(crate) browser_max_lmb_count: i32,
     pub(crate) case_sensitive: bool,
+    pub(crate) configuration: Vec<(String, String)>,
+    pub(crate) configuration_file: Option<PathBuf>,
+    pub(crate) connect: bool,
     pub(crate) encryption_level: SmbEncryptionLevel,
     pub(crate) fallback_after_kerberos: bool,
     pub(crate) full_time_names: bool,
+    pub(crate) max_protocol: Option<SmbProtocol>,
+    pub(crate) min_protocol: Option<SmbProtocol>,
     pub(crate) no_auto_anonymous_login: bool,
+    pub(crate) ntlmv2_only: bool,
     pub(crate) one_share_per_server: bool,
     pub(crate) open_share_mode: SmbShareMode,
+    pub(crate) signing: Option<SmbSigning>,
     pub(crate) url_encode_readdir_entries: bool,
     pub(crate) use_ccache: bool,
     pub(crate) use_kerberos: bool,
@@ -25,12 +109,19 @@ impl Default for SmbOptions {
         Self {
             browser_max_lmb_count: 0,
             case_sensitive: false,
+            configuration: Vec::new(),
+            configuration_file: None,
+            connect: false,
             encryption_level: SmbEncryptionLevel::None,
             fallback_after_kerberos: false,
             full_time_names: false,
+            max_protocol: None,
+            min_protocol: None,
             no_auto_anonymous_login: false,
+            ntlmv2_only: false,
             one_share_per_server: false,
             open_share_mode: SmbShareMode::DenyNone,
+            signing: None,
             url_encode_readdir_entries: false,
             use_ccache: false,
             use_kerberos: false,
@@ -49,6 +140,43 @@ impl SmbOptions {
         self
     }
 
+    /// Set a smb.conf parameter, such as `name resolve order` or `socket options`.
+    ///
+    /// Parameters are applied after the configuration file. Only the most common client parameters are accepted:
+    /// others are refused with [`SmbError::BadOption`] when the client is created, since libsmbclient silently ignores
+    /// unknown parameters. Parametric options (e.g. `libsmb:client_guid`) are passed as they are. To set a parameter
+    /// which is refused, write it to a file loaded with [`SmbOptions::configuration_file`].
+    ///
+    /// Mind that libsmbclient keeps its configuration globally, so parameters apply to all clients in the process.
+    pub fn configuration<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.configuration
+            .push((key.as_ref().to_string(), value.as_ref().to_string()));
+        self
+    }
+
+    /// Load smb.conf parameters from the file at `path`, on top of the system configuration.
+    ///
+    /// Unlike [`SmbOptions::configuration`], the parameters in the file are not checked: libsmbclient ignores
+    /// the ones it doesn't know, logging them at debug level.
+    ///
+    /// Mind that libsmbclient keeps its configuration globally, so parameters apply to all clients in the process.
+    pub fn configuration_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.configuration_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Connect to the server and authenticate when the client is created, failing early if it can't.
+    ///
+    /// See [`crate::SmbClient::connect`]
+    pub fn connect(mut self, connect: bool) -> Self {
+        self.connect = connect;
+        self
+    }
+
     pub fn encryption_level(mut self, encryption_level: SmbEncryptionLevel) -> Self {
         self.encryption_level = encryption_level;
         self
@@ -64,11 +192,37 @@ impl SmbOptions {
         self
     }
 
+    /// Set the highest protocol version the client will negotiate.
+    ///
+    /// Applies to the whole process: see [security options](SmbOptions#security-options)
+    pub fn max_protocol(mut self, max_protocol: SmbProtocol) -> Self {
+        self.max_protocol = Some(max_protocol);
+        self
+    }
+
+    /// Set the lowest protocol version the client will negotiate.
+    ///
+    /// Servers which can't speak at least this version are refused when connecting, with
+    /// [`crate::SmbConnectionError::Policy`]. Applies to the whole process: see
+    /// [security options](SmbOptions#security-options)
+    pub fn min_protocol(mut self, min_protocol: SmbProtocol) -> Self {
+        self.min_protocol = Some(min_protocol);
+        self
+    }
+
     pub fn no_auto_anonymous_login(mut self, no_auto_anonymous_login: bool) -> Self {
         self.no_auto_anonymous_login = no_auto_anonymous_login;
         self
     }
 
+    /// Only authenticate with NTLMv2, refusing LANMAN, NTLMv1 and plaintext authentication.
+    ///
+    /// Applies to the whole process: see [security options](SmbOptions#security-options)
+    pub fn ntlmv2_only(mut self, ntlmv2_only: bool) -> Self {
+        self.ntlmv2_only = ntlmv2_only;
+        self
+    }
+
     pub fn one_share_per_server(mut self, one_share_per_server: bool) -> Self {
         self.one_share_per_server = one_share_per_server;
         self
@@ -79,6 +233,15 @@ impl SmbOptions {
         self
     }
 
+    /// Set the client signing policy.
+    ///
+    /// With [`SmbSigning::Required`] connections to servers which don't sign are refused. Applies to the whole
+    /// process: see [security options](SmbOptions#security-options)
+    pub fn signing(mut self, signing: SmbSigning) -> Self {
+        self.signing = Some(signing);
+        self
+    }
+
     pub fn url_encode_readdir_entries(mut self, url_encode_readdir_entries: bool) -> Self {
         self.url_encode_readdir_entries = url_encode_readdir_entries;
         self
@@ -93,6 +256,92 @@ impl SmbOptions {
         self.use_kerberos = use_kerberos;
         self
     }
+
+    /// Check whether the options are consistent
+    pub(crate) fn validate(&self) -> SmbResult<()> {
+        if let (Some(min), Some(max)) = (self.min_protocol, self.max_protocol) {
+            if min > max {
+                return Err(SmbError::BadOption(format!(
+                    "min protocol {} is greater than max protocol {}",
+                    min.as_str(),
+                    max.as_str()
+                )));
+            }
+        }
+        if let Some(path) = self.configuration_file.as_deref() {
+            if !path.is_file() {
+                return Err(SmbError::BadOption(format!(
+                    "configuration file {} doesn't exist",
+                    path.display()
+                )));
+            }
+        }
+        for (key, value) in self.configuration.iter() {
+            Self::validate_smb_conf_param(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Check whether `key` is a known smb.conf parameter and `value` can be written to smb.conf
+    fn validate_smb_conf_param(key: &str, value: &str) -> SmbResult<()> {
+        if key.contains(['\n', '\r', '=', '[', ']']) || value.contains(['\n', '\r']) {
+            return Err(SmbError::BadOption(format!(
+                "invalid configuration parameter: {} = {}",
+                key, value
+            )));
+        }
+        // parametric options are not checked by samba either
+        if key.contains(':') {
+            return Ok(());
+        }
+        let normalized: String = key
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if SMB_CONF_PARAMETERS.contains(&normalized.as_str()) {
+            Ok(())
+        } else {
+            Err(SmbError::BadOption(format!(
+                "unknown configuration parameter: {}",
+                key
+            )))
+        }
+    }
+
+    /// Get the security policy set by these options; `None` if they don't set any security option
+    pub(crate) fn security_policy(&self) -> Option<SmbSecurityPolicy> {
+        let policy = SmbSecurityPolicy {
+            max_protocol: self.max_protocol,
+            min_protocol: self.min_protocol,
+            ntlmv2_only: self.ntlmv2_only,
+            signing: self.signing,
+        };
+        (policy != SmbSecurityPolicy::default()).then_some(policy)
+    }
+
+    /// smb.conf parameters which are required to apply these options
+    pub(crate) fn smb_conf_params(&self) -> Vec<(String, String)> {
+        let mut params = self.configuration.clone();
+        if let Some(signing) = self.signing {
+            params.push(("client signing".to_string(), signing.as_str().to_string()));
+        }
+        if self.ntlmv2_only {
+            params.push(("client ntlmv2 auth".to_string(), "yes".to_string()));
+            params.push(("client lanman auth".to_string(), "no".to_string()));
+            params.push(("client plaintext auth".to_string(), "no".to_string()));
+        }
+        params
+    }
+}
+
+/// Security options, which libsmbclient applies to the whole process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SmbSecurityPolicy {
+    pub max_protocol: Option<SmbProtocol>,
+    pub min_protocol: Option<SmbProtocol>,
+    pub ntlmv2_only: bool,
+    pub signing: Option<SmbSigning>,
 }
 
 /// Share mode option
@@ -137,6 +386,53 @@ impl From<SmbEncryptionLevel> for smbc_smb_encrypt_level {
     }
 }
 
+/// SMB protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SmbProtocol {
+    /// SMB1 (NT LM 0.12)
+    Nt1,
+    Smb2_02,
+    Smb2_10,
+    Smb3_00,
+    Smb3_02,
+    Smb3_11,
+}
+
+impl SmbProtocol {
+    /// Protocol name as understood by libsmbclient
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SmbProtocol::Nt1 => "NT1",
+            SmbProtocol::Smb2_02 => "SMB2_02",
+            SmbProtocol::Smb2_10 => "SMB2_10",
+            SmbProtocol::Smb3_00 => "SMB3_00",
+            SmbProtocol::Smb3_02 => "SMB3_02",
+            SmbProtocol::Smb3_11 => "SMB3_11",
+        }
+    }
+}
+
+/// Client signing option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmbSigning {
+    Off,
+    IfRequired,
+    Desired,
+    Required,
+}
+
+impl SmbSigning {
+    /// Value of the `client signing` parameter
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SmbSigning::Off => "off",
+            SmbSigning::IfRequired => "if_required",
+            SmbSigning::Desired => "desired",
+            SmbSigning::Required => "required",
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -149,12 +445,19 @@ mod test {
         let options = SmbOptions::default();
         assert_eq!(options.browser_max_lmb_count, 0);
         assert_eq!(options.case_sensitive, false);
+        assert!(options.configuration.is_empty());
+        assert_eq!(options.configuration_file, None);
+        assert_eq!(options.connect, false);
         assert_eq!(options.encryption_level, SmbEncryptionLevel::None);
         assert_eq!(options.fallback_after_kerberos, false);
         assert_eq!(options.full_time_names, false);
+        assert_eq!(options.max_protocol, None);
+        assert_eq!(options.min_protocol, None);
         assert_eq!(options.no_auto_anonymous_login, false);
+        assert_eq!(options.ntlmv2_only, false);
         assert_eq!(options.one_share_per_server, false);
         assert_eq!(options.open_share_mode, SmbShareMode::DenyNone);
+        assert_eq!(options.signing, None);
         assert_eq!(options.url_encode_readdir_entries, false);
         assert_eq!(options.use_ccache, false);
         assert_eq!(options.use_kerberos, false);
@@ -165,23 +468,43 @@ mod test {
         let options = SmbOptions::default()
             .browser_max_lmb_count(10)
             .case_sensitive(true)
+            .configuration("name resolve order", "bcast host")
+            .configuration_file("/etc/samba/pavao.conf")
+            .connect(true)
             .encryption_level(SmbEncryptionLevel::Require)
             .fallback_after_kerberos(true)
             .full_time_names(true)
+            .max_protocol(SmbProtocol::Smb3_11)
+            .min_protocol(SmbProtocol::Smb2_10)
             .no_auto_anonymous_login(true)
+            .ntlmv2_only(true)
             .one_share_per_server(true)
             .open_share_mode(SmbShareMode::DenyAll)
+            .signing(SmbSigning::Required)
             .url_encode_readdir_entries(true)
             .use_ccache(true)
             .use_kerberos(true);
         assert_eq!(options.browser_max_lmb_count, 10);
         assert_eq!(options.case_sensitive, true);
+        assert_eq!(
+            options.configuration,
+            vec![("name resolve order".to_string(), "bcast host".to_string())]
+        );
+        assert_eq!(
+            options.configuration_file.as_deref(),
+            Some(Path::new("/etc/samba/pavao.conf"))
+        );
+        assert_eq!(options.connect, true);
         assert_eq!(options.encryption_level, SmbEncryptionLevel::Require);
         assert_eq!(options.fallback_after_kerberos, true);
         assert_eq!(options.full_time_names, true);
+        assert_eq!(options.max_protocol, Some(SmbProtocol::Smb3_11));
+        assert_eq!(options.min_protocol, Some(SmbProtocol::Smb2_10));
         assert_eq!(options.no_auto_anonymous_login, true);
+        assert_eq!(options.ntlmv2_only, true);
         assert_eq!(options.one_share_per_server, true);
         assert_eq!(options.open_share_mode, SmbShareMode::DenyAll);
+        assert_eq!(options.signing, Some(SmbSigning::Required));
         assert_eq!(options.url_encode_readdir_entries, true);
         assert_eq!(options.use_ccache, true);
         assert_eq!(options.use_kerberos, true);
@@ -203,4 +526,114 @@ mod test {
         assert_eq!(smbc_smb_encrypt_level::from(SmbEncryptionLevel::Request), 1);
         assert_eq!(smbc_smb_encrypt_level::from(SmbEncryptionLevel::Require), 2);
     }
+
+    #[test]
+    fn should_validate_protocol_range() {
+        assert!(SmbOptions::default().validate().is_ok());
+        assert!(SmbOptions::default()
+            .min_protocol(SmbProtocol::Smb2_02)
+            .max_protocol(SmbProtocol::Smb3_11)
+            .validate()
+            .is_ok());
+        assert!(SmbOptions::default()
+            .min_protocol(SmbProtocol::Smb3_00)
+            .max_protocol(SmbProtocol::Smb3_00)
+            .validate()
+            .is_ok());
+        assert!(SmbOptions::default()
+            .min_protocol(SmbProtocol::Smb3_11)
+            .max_protocol(SmbProtocol::Nt1)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn should_get_security_policy() {
+        assert_eq!(SmbOptions::default().security_policy(), None);
+        assert_eq!(
+            SmbOptions::default().case_sensitive(true).security_policy(),
+            None
+        );
+        assert_eq!(
+            SmbOptions::default()
+                .min_protocol(SmbProtocol::Smb3_00)
+                .signing(SmbSigning::Required)
+                .security_policy(),
+            Some(SmbSecurityPolicy {
+                max_protocol: None,
+                min_protocol: Some(SmbProtocol::Smb3_00),
+                ntlmv2_only: false,
+                signing: Some(SmbSigning::Required),
+            })
+        );
+    }
+
+    #[test]
+    fn should_convert_protocol_to_str() {
+        assert_eq!(SmbProtocol::Nt1.as_str(), "NT1");
+        assert_eq!(SmbProtocol::Smb2_02.as_str(), "SMB2_02");
+        assert_eq!(SmbProtocol::Smb2_10.as_str(), "SMB2_10");
+        assert_eq!(SmbProtocol::Smb3_00.as_str(), "SMB3_00");
+        assert_eq!(SmbProtocol::Smb3_02.as_str(), "SMB3_02");
+        assert_eq!(SmbProtocol::Smb3_11.as_str(), "SMB3_11");
+    }
+
+    #[test]
+    fn should_get_smb_conf_params() {
+        assert!(SmbOptions::default().smb_conf_params().is_empty());
+        let params = SmbOptions::default()
+            .configuration("client signing", "off")
+            .signing(SmbSigning::Required)
+            .ntlmv2_only(true)
+            .smb_conf_params();
+        assert_eq!(
+            params,
+            vec![
+                ("client signing".to_string(), "off".to_string()),
+                ("client signing".to_string(), "required".to_string()),
+                ("client ntlmv2 auth".to_string(), "yes".to_string()),
+                ("client lanman auth".to_string(), "no".to_string()),
+                ("client plaintext auth".to_string(), "no".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_validate_configuration() {
+        assert!(SmbOptions::default()
+            .configuration("name resolve order", "bcast host")
+            .configuration("Socket Options", "TCP_NODELAY")
+            .configuration("unix charset", "UTF-8")
+            .configuration("clientmaxprotocol", "SMB3")
+            .configuration("libsmb:client_guid", "foo")
+            .validate()
+            .is_ok());
+        assert_eq!(
+            SmbOptions::default()
+                .configuration("name resolve orderr", "bcast")
+                .validate()
+                .unwrap_err(),
+            SmbError::BadOption("unknown configuration parameter: name resolve orderr".to_string())
+        );
+        assert!(SmbOptions::default()
+            .configuration("workgroup", "pavao\n[homes]")
+            .validate()
+            .is_err());
+        assert!(SmbOptions::default()
+            .configuration("workgroup = pavao", "pavao")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn should_validate_configuration_file() {
+        assert!(SmbOptions::default()
+            .configuration_file("/this/file/does/not/exist.conf")
+            .validate()
+            .is_err());
+        assert!(SmbOptions::default()
+            .configuration_file(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+            .validate()
+            .is_ok());
+    }
 }
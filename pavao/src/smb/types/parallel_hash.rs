@@ -0,0 +1,206 @@
+//! # Parallel hash
+//!
+//! module which exposes the hash of many remote files over several connections
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use digest::Digest;
+
+use super::{SmbCredentials, SmbHashRecord, SmbOpenOptions, SmbOptions, Workers};
+use crate::{SmbClient, SmbError, SmbResult};
+
+/// Default size of the buffer used to stream files
+const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Result of the hash of a file, with the index of its path
+type IndexedRecord = (usize, SmbResult<SmbHashRecord>);
+
+/// Hash of many remote files, which are hashed concurrently over several connections.
+#[derive(Debug, Clone)]
+pub struct SmbParallelHash {
+    workers: Workers,
+    buffer_size: usize,
+}
+
+impl SmbParallelHash {
+    /// Initialize a new parallel hash which connects with the provided credentials and options
+    pub fn new(credentials: SmbCredentials, options: SmbOptions) -> Self {
+        Self {
+            workers: Workers::new(credentials, options),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Set the amount of workers, each one with its own connection. Must be greater than 0
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers.set_amount(workers);
+        self
+    }
+
+    /// Set the size of the buffer each worker streams files through. Must be greater than 0
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Hash the content of the files at `paths` with the digest `D`.
+    ///
+    /// A result is returned for each path, in the same order as `paths`: a file which can't be hashed doesn't stop
+    /// the others from being hashed. An error is returned only if a worker can't create its client, which includes
+    /// connecting to the server when [`SmbOptions::connect`] is set.
+    pub fn hash<D, I, S>(&self, paths: I) -> SmbResult<Vec<SmbResult<SmbHashRecord>>>
+    where
+        D: Digest,
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let paths: Vec<String> = paths.into_iter().map(|p| p.as_ref().to_string()).collect();
+        let workers = self.workers.amount().min(paths.len());
+        debug!("hashing {} files with {} workers", paths.len(), workers);
+        let next = AtomicUsize::new(0);
+        let aborted = AtomicBool::new(false);
+        let results: Vec<SmbResult<Vec<IndexedRecord>>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let result = self.work::<D>(&paths, &next, &aborted);
+                        if let Err(err) = result.as_ref() {
+                            error!("hash worker failed: {}", err);
+                            aborted.store(true, Ordering::SeqCst);
+                        }
+                        result
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker.join().unwrap_or_else(|_| {
+                        Err(SmbError::Io(io::Error::other("hash worker panicked")))
+                    })
+                })
+                .collect()
+        });
+        let mut records = Vec::with_capacity(paths.len());
+        for result in results {
+            records.extend(result?);
+        }
+        records.sort_by_key(|(idx, _)| *idx);
+        Ok(records.into_iter().map(|(_, record)| record).collect())
+    }
+
+    /// Claim and hash files until all of them have been hashed; returns the results with their index
+    fn work<D>(
+        &self,
+        paths: &[String],
+        next: &AtomicUsize,
+        aborted: &AtomicBool,
+    ) -> SmbResult<Vec<IndexedRecord>>
+    where
+        D: Digest,
+    {
+        let client = self.workers.connect()?;
+        let mut buffer = vec![0; self.buffer_size];
+        let mut records = Vec::new();
+        while !aborted.load(Ordering::SeqCst) {
+            let idx = next.fetch_add(1, Ordering::SeqCst);
+            let Some(path) = paths.get(idx) else {
+                break;
+            };
+            trace!("hashing {}", path);
+            let record = Self::hash_file::<D>(&client, path, &mut buffer);
+            if let Err(err) = record.as_ref() {
+                error!("failed to hash {}: {}", path, err);
+            }
+            records.push((idx, record));
+        }
+        Ok(records)
+    }
+
+    /// Hash the file at `path` through `buffer`
+    fn hash_file<D>(client: &SmbClient, path: &str, buffer: &mut [u8]) -> SmbResult<SmbHashRecord>
+    where
+        D: Digest,
+    {
+        let mut reader = client.open_with(path, SmbOpenOptions::default().read(true))?;
+        let (size, hash) = SmbClient::hash_reader::<D>(&mut reader, buffer)?;
+        Ok(SmbHashRecord {
+            path: path.to_string(),
+            size,
+            hash: hash.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    #[test]
+    fn should_build_parallel_hash() {
+        let hash = SmbParallelHash::new(SmbCredentials::default(), SmbOptions::default());
+        assert_eq!(hash.workers.amount(), 4);
+        assert_eq!(hash.buffer_size, DEFAULT_BUFFER_SIZE);
+        let hash = hash.workers(0).buffer_size(0);
+        assert_eq!(hash.workers.amount(), 1);
+        assert_eq!(hash.buffer_size, 1);
+    }
+
+    #[test]
+    #[serial]
+    fn should_hash_files_in_parallel() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let paths: Vec<String> = (0..10).map(|i| format!("/cargo-test/{}.txt", i)).collect();
+        for (i, path) in paths.iter().enumerate() {
+            let mut writer = ctx
+                .client
+                .open_with(path, SmbOpenOptions::default().create(true).write(true))
+                .unwrap();
+            assert!(io::copy(&mut Cursor::new(vec![b'a'; i]), &mut writer).is_ok());
+        }
+        let hash = SmbParallelHash::new(ctx.credentials(), SmbOptions::default())
+            .workers(3)
+            .buffer_size(4);
+        let records = hash.hash::<Sha256, _, _>(&paths).unwrap();
+        assert_eq!(records.len(), 10);
+        for (i, record) in records.iter().enumerate() {
+            let record = record.as_ref().unwrap();
+            assert_eq!(record.path, paths[i]);
+            assert_eq!(record.size, i as u64);
+            assert_eq!(record.hash, Sha256::digest(vec![b'a'; i]).to_vec());
+        }
+        // a missing file doesn't stop the others from being hashed
+        let records = hash
+            .hash::<Sha256, _, _>([
+                "/cargo-test/0.txt",
+                "/cargo-test/missing.txt",
+                "/cargo-test/1.txt",
+            ])
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0].as_ref().unwrap().path.as_str(),
+            "/cargo-test/0.txt"
+        );
+        assert!(records[1].is_err());
+        assert_eq!(records[2].as_ref().unwrap().size, 1);
+        assert!(SmbParallelHash::new(
+            ctx.credentials().password("wrong"),
+            SmbOptions::default().connect(true)
+        )
+        .hash::<Sha256, _, _>(&paths)
+        .is_err());
+    }
+}
@@ -0,0 +1,449 @@
+//! # Parallel transfer
+//!
+//! module which exposes the transfer of large files in chunks over several connections
+
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use super::{
+    SmbCredentials, SmbOpenOptions, SmbOptions, SmbTransferOptions, SmbTransferReport, Workers,
+};
+use crate::{SmbClient, SmbError, SmbResult};
+
+/// Default size of the chunks
+const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+/// Default size under which files are transferred with a single stream
+const DEFAULT_THRESHOLD: u64 = 32 * 1024 * 1024;
+
+/// Transfer of large files, which are split in chunks transferred concurrently over several connections.
+///
+/// Files smaller than the threshold are transferred with a single stream, as [`SmbClient::upload`]
+/// and [`SmbClient::download`] do.
+/// Progress is reported from the calling thread, as a single stream for the whole file.
+#[derive(Debug, Clone)]
+pub struct SmbParallelTransfer {
+    workers: Workers,
+    chunk_size: u64,
+    threshold: u64,
+}
+
+impl SmbParallelTransfer {
+    /// Initialize a new parallel transfer which connects with the provided credentials and options
+    pub fn new(credentials: SmbCredentials, options: SmbOptions) -> Self {
+        Self {
+            workers: Workers::new(credentials, options),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Set the amount of workers, each one with its own connection. Must be greater than 0
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers.set_amount(workers);
+        self
+    }
+
+    /// Set the size of the chunks the file is split in. Must be greater than 0
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Set the size under which files are transferred with a single stream
+    pub fn threshold(mut self, threshold: u64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Upload the local file at `local` to `remote`
+    pub fn upload<P, S>(
+        &self,
+        local: P,
+        remote: S,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let (local, remote) = (local.as_ref(), remote.as_ref());
+        let client = self.workers.connect()?;
+        let metadata = fs::metadata(local)?;
+        if metadata.len() < self.threshold {
+            trace!(
+                "{} is small: uploading with a single stream",
+                local.display()
+            );
+            return client.upload(local, remote, options);
+        }
+        if options.create_parents {
+            client.create_remote_parents(remote)?;
+        }
+        if client.skip_upload(&metadata, remote, options)? {
+            return Ok(SmbTransferReport {
+                skipped: 1,
+                ..Default::default()
+            });
+        }
+        debug!(
+            "uploading {} to {} in chunks of {} bytes with {} workers",
+            local.display(),
+            remote,
+            self.chunk_size,
+            self.workers.amount()
+        );
+        // chunks are written by the workers to a temporary file, which replaces the target once complete
+        let temp = SmbClient::temp_path(remote)?;
+        drop(
+            client.open_with(
+                temp.as_str(),
+                SmbOpenOptions::default()
+                    .write(true)
+                    .create(true)
+                    .exclusive(true),
+            )?,
+        );
+        let local_path = local.to_path_buf();
+        let temp_path = temp.clone();
+        let result = self
+            .run(
+                &local.to_string_lossy(),
+                metadata.len(),
+                options,
+                move |client, chunks| {
+                    let reader = File::open(&local_path)?;
+                    let writer =
+                        client.open_with(&temp_path, SmbOpenOptions::default().write(true))?;
+                    chunks.run(|offset, buffer| {
+                        reader.read_exact_at(buffer, offset)?;
+                        writer.write_all_at(buffer, offset)
+                    })?;
+                    writer.close()
+                },
+            )
+            .and_then(|()| {
+                let mut buffer = vec![0; options.buffer_size];
+                client.finish_upload(local, &metadata, &temp, options, &mut buffer)?;
+                client.rename(temp.as_str(), remote)
+            });
+        if let Err(err) = result {
+            if let Err(err) = client.unlink(temp.as_str()) {
+                error!("failed to remove {}: {}", temp, err);
+            }
+            return Err(err);
+        }
+        Ok(SmbTransferReport {
+            files: 1,
+            bytes: metadata.len(),
+            ..Default::default()
+        })
+    }
+
+    /// Download the remote file at `remote` to `local`
+    pub fn download<S, P>(
+        &self,
+        remote: S,
+        local: P,
+        options: &SmbTransferOptions,
+    ) -> SmbResult<SmbTransferReport>
+    where
+        S: AsRef<str>,
+        P: AsRef<Path>,
+    {
+        let (remote, local) = (remote.as_ref(), local.as_ref());
+        let client = self.workers.connect()?;
+        let stat = client.stat(remote)?;
+        if stat.size < self.threshold {
+            trace!("{} is small: downloading with a single stream", remote);
+            return client.download(remote, local, options);
+        }
+        if options.create_parents {
+            SmbClient::create_local_parents(local)?;
+        }
+        if SmbClient::skip_download(&stat, local, options)? {
+            return Ok(SmbTransferReport {
+                skipped: 1,
+                ..Default::default()
+            });
+        }
+        debug!(
+            "downloading {} to {} in chunks of {} bytes with {} workers",
+            remote,
+            local.display(),
+            self.chunk_size,
+            self.workers.amount()
+        );
+        // chunks are written by the workers to a temporary file, which replaces the target once complete
+        SmbClient::write_local_file(local, |file, temp| {
+            file.set_len(stat.size)?;
+            let temp_path = temp.to_path_buf();
+            let remote_path = remote.to_string();
+            self.run(remote, stat.size, options, move |client, chunks| {
+                let writer = File::options().write(true).open(&temp_path)?;
+                let reader =
+                    client.open_with(&remote_path, SmbOpenOptions::default().read(true))?;
+                chunks.run(|offset, buffer| {
+                    reader.read_exact_at(buffer, offset)?;
+                    writer.write_all_at(buffer, offset)
+                })
+            })?;
+            let mut buffer = vec![0; options.buffer_size];
+            client.finish_download(remote, &stat, temp, file, options, &mut buffer)
+        })?;
+        Ok(SmbTransferReport {
+            files: 1,
+            bytes: stat.size,
+            ..Default::default()
+        })
+    }
+
+    /// Run `transfer` on the workers, reporting progress until all chunks have been transferred
+    fn run<F>(
+        &self,
+        path: &str,
+        size: u64,
+        options: &SmbTransferOptions,
+        transfer: F,
+    ) -> SmbResult<()>
+    where
+        F: Fn(&SmbClient, &Chunks) -> SmbResult<()> + Send + Sync + 'static,
+    {
+        let transfer = Arc::new(transfer);
+        let shared = Arc::new(Shared {
+            size,
+            chunk_size: self.chunk_size,
+            buffer_size: options.buffer_size as u64,
+            next: AtomicU64::new(0),
+            aborted: AtomicBool::new(false),
+        });
+        let (tx, rx) = mpsc::channel();
+        let workers: Vec<JoinHandle<SmbResult<()>>> = (0..self.workers.amount())
+            .map(|_| {
+                let workers = self.workers.clone();
+                let transfer = transfer.clone();
+                let chunks = Chunks {
+                    shared: shared.clone(),
+                    progress: tx.clone(),
+                };
+                thread::spawn(move || {
+                    let result = workers
+                        .connect()
+                        .and_then(|client| transfer(&client, &chunks));
+                    if let Err(err) = result.as_ref() {
+                        error!("transfer worker failed: {}", err);
+                        chunks.shared.aborted.store(true, Ordering::SeqCst);
+                    }
+                    result
+                })
+            })
+            .collect();
+        drop(tx);
+        // progress is reported until all the workers have quit
+        let mut transferred = 0;
+        options.report_progress(path, transferred, size);
+        for bytes in rx {
+            transferred += bytes;
+            options.report_progress(path, transferred, size);
+        }
+        let mut result = Ok(());
+        for worker in workers {
+            let worker_result = worker.join().unwrap_or_else(|_| {
+                Err(SmbError::Io(io::Error::other("transfer worker panicked")))
+            });
+            if result.is_ok() {
+                result = worker_result;
+            }
+        }
+        result
+    }
+}
+
+/// State shared by the workers
+struct Shared {
+    size: u64,
+    chunk_size: u64,
+    buffer_size: u64,
+    /// index of the next chunk to transfer
+    next: AtomicU64,
+    aborted: AtomicBool,
+}
+
+/// Chunks of the file, which are claimed by the workers
+struct Chunks {
+    shared: Arc<Shared>,
+    progress: Sender<u64>,
+}
+
+impl Chunks {
+    /// Claim chunks until all of them have been transferred, calling `transfer` with the offset
+    /// and the buffer for each part of a chunk
+    fn run<F>(&self, mut transfer: F) -> SmbResult<()>
+    where
+        F: FnMut(u64, &mut [u8]) -> io::Result<()>,
+    {
+        let shared = &self.shared;
+        let mut buffer = vec![0; shared.buffer_size.min(shared.chunk_size) as usize];
+        while let Some((start, end)) = self.next() {
+            let mut offset = start;
+            while offset < end {
+                if shared.aborted.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+                let len = (end - offset).min(buffer.len() as u64) as usize;
+                transfer(offset, &mut buffer[..len])?;
+                offset += len as u64;
+                let _ = self.progress.send(len as u64);
+            }
+        }
+        Ok(())
+    }
+
+    /// Claim the next chunk, returning its range
+    fn next(&self) -> Option<(u64, u64)> {
+        let shared = &self.shared;
+        if shared.aborted.load(Ordering::SeqCst) {
+            return None;
+        }
+        let start = shared
+            .next
+            .fetch_add(1, Ordering::SeqCst)
+            .checked_mul(shared.chunk_size)?;
+        (start < shared.size).then(|| (start, (start + shared.chunk_size).min(shared.size)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Mutex;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    #[test]
+    fn should_split_in_chunks() {
+        let (tx, rx) = mpsc::channel();
+        let chunks = Chunks {
+            shared: Arc::new(Shared {
+                size: 10,
+                chunk_size: 4,
+                buffer_size: 3,
+                next: AtomicU64::new(0),
+                aborted: AtomicBool::new(false),
+            }),
+            progress: tx,
+        };
+        let mut parts = Vec::new();
+        assert!(chunks
+            .run(|offset, buffer| {
+                parts.push((offset, buffer.len()));
+                Ok(())
+            })
+            .is_ok());
+        assert_eq!(parts, vec![(0, 3), (3, 1), (4, 3), (7, 1), (8, 2)]);
+        drop(chunks);
+        assert_eq!(rx.iter().sum::<u64>(), 10);
+    }
+
+    #[test]
+    fn should_stop_claiming_chunks_when_aborted() {
+        let (tx, _rx) = mpsc::channel();
+        let chunks = Chunks {
+            shared: Arc::new(Shared {
+                size: 10,
+                chunk_size: 4,
+                buffer_size: 4,
+                next: AtomicU64::new(0),
+                aborted: AtomicBool::new(false),
+            }),
+            progress: tx,
+        };
+        assert_eq!(chunks.next(), Some((0, 4)));
+        chunks.shared.aborted.store(true, Ordering::SeqCst);
+        assert_eq!(chunks.next(), None);
+    }
+
+    #[test]
+    #[serial]
+    fn should_upload_and_download_in_chunks() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let tempdir = tempfile::tempdir().unwrap();
+        let local = tempdir.path().join("data.bin");
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&local, &data).unwrap();
+        let transfer = SmbParallelTransfer::new(ctx.credentials(), SmbOptions::default())
+            .workers(3)
+            .chunk_size(64 * 1024)
+            .threshold(128 * 1024);
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let p = progress.clone();
+        let options = SmbTransferOptions::default()
+            .buffer_size(16 * 1024)
+            .verify_checksum(true)
+            .progress(move |e| p.lock().unwrap().push(e.transferred));
+        let report = transfer
+            .upload(&local, "/cargo-test/data.bin", &options)
+            .unwrap();
+        assert_eq!(report.bytes, 1_000_000);
+        {
+            let progress = progress.lock().unwrap();
+            assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+            assert_eq!(progress.last().copied(), Some(1_000_000));
+        }
+        let downloaded = tempdir.path().join("downloaded.bin");
+        let report = transfer
+            .download("/cargo-test/data.bin", &downloaded, &options)
+            .unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(fs::read(&downloaded).unwrap(), data);
+        // transfers go through temporary files, which are renamed over the targets
+        let remote_names: Vec<String> = ctx
+            .client
+            .list_dir("/cargo-test")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name().to_string())
+            .collect();
+        assert_eq!(remote_names, vec![String::from("data.bin")]);
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    #[serial]
+    fn should_transfer_small_files_with_single_stream() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let tempdir = tempfile::tempdir().unwrap();
+        let local = tempdir.path().join("small.txt");
+        fs::write(&local, "Hello, World!\n").unwrap();
+        let transfer = SmbParallelTransfer::new(ctx.credentials(), SmbOptions::default());
+        let report = transfer
+            .upload(
+                &local,
+                "/cargo-test/small.txt",
+                &SmbTransferOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(report.bytes, 14);
+        let downloaded = tempdir.path().join("downloaded.txt");
+        transfer
+            .download(
+                "/cargo-test/small.txt",
+                &downloaded,
+                &SmbTransferOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(fs::read_to_string(&downloaded).unwrap(), "Hello, World!\n");
+    }
+}
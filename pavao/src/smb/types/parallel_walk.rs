@@ -0,0 +1,553 @@
+//! # Parallel walk
+//!
+//! module which exposes the directory walker which spreads listings over several connections
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use super::walk::{WalkFilter, WalkMatcher};
+use super::{SmbCredentials, SmbOptions, SmbWalkEntry, SmbWalkErrorPolicy, Workers};
+use crate::{SmbClient, SmbError, SmbResult};
+
+/// Default capacity of the channel which delivers the entries
+const DEFAULT_QUEUE_SIZE: usize = 1024;
+/// Maximum amount of directories shared between the workers; beyond, workers keep the directories they find
+/// on a stack of their own, which isn't bounded
+const MAX_QUEUED_DIRS: usize = 1024;
+
+/// Recursive directory walker which lists directories in parallel.
+///
+/// Each worker thread opens its own connection with the provided credentials and options,
+/// so listings aren't serialized by the lock of a single context.
+/// Entries are delivered through a bounded channel, in no particular order; the root directory itself is not yielded.
+/// If a worker panics, the walk is aborted and the last item yielded is an error.
+///
+/// Only the entries waiting to be received and the directories shared between the workers are bounded:
+/// each directory is listed at once, and the directories found beyond the shared ones wait in memory
+/// until the worker which found them lists them, so memory usage still grows with the width of the tree.
+#[derive(Debug, Clone)]
+pub struct SmbParallelWalk {
+    workers: Workers,
+    queue_size: usize,
+    filter: WalkFilter,
+    error_policy: SmbWalkErrorPolicy,
+}
+
+impl SmbParallelWalk {
+    /// Initialize a new parallel walker which connects with the provided credentials and options
+    pub fn new(credentials: SmbCredentials, options: SmbOptions) -> Self {
+        Self {
+            workers: Workers::new(credentials, options),
+            queue_size: DEFAULT_QUEUE_SIZE,
+            filter: WalkFilter::default(),
+            error_policy: SmbWalkErrorPolicy::default(),
+        }
+    }
+
+    /// Set the amount of workers, each one with its own connection. Must be greater than 0
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers.set_amount(workers);
+        self
+    }
+
+    /// Set the maximum amount of entries waiting to be received. Must be greater than 0
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    /// Only yield entries at depth `depth` or deeper
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.filter.min_depth = depth.max(1);
+        self
+    }
+
+    /// Don't descend deeper than `depth`
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.filter.max_depth = depth;
+        self
+    }
+
+    /// Only yield entries matching the gitignore-style `pattern`. Can be called multiple times.
+    pub fn include<S>(mut self, pattern: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.filter.include.push(pattern.as_ref().to_string());
+        self
+    }
+
+    /// Skip entries matching the gitignore-style `pattern`; excluded directories are not walked.
+    /// Can be called multiple times.
+    pub fn exclude<S>(mut self, pattern: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.filter.exclude.push(pattern.as_ref().to_string());
+        self
+    }
+
+    /// Match include and exclude patterns case sensitively (default)
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.filter.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Skip entries with the hidden attribute; hidden directories are not walked
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.filter.skip_hidden = skip;
+        self
+    }
+
+    /// Skip entries with the system attribute; system directories are not walked
+    pub fn skip_system(mut self, skip: bool) -> Self {
+        self.filter.skip_system = skip;
+        self
+    }
+
+    /// Set what to do when a directory can't be read or a worker can't connect.
+    ///
+    /// With [`SmbWalkErrorPolicy::Abort`] the first error is the last item yielded.
+    pub fn error_policy(mut self, policy: SmbWalkErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Start walking the directory tree at `path`
+    pub fn walk<S>(self, path: S) -> SmbResult<SmbParallelWalkIter>
+    where
+        S: AsRef<str>,
+    {
+        let shared = Arc::new(Shared {
+            matcher: self.filter.compile()?,
+            error_policy: self.error_policy,
+            queue: Mutex::new(Queue {
+                dirs: vec![Dir {
+                    path: path.as_ref().to_string(),
+                    relative_path: String::default(),
+                    depth: 1,
+                }],
+                active: 0,
+                aborted: false,
+                panicked: false,
+            }),
+            cond: Condvar::new(),
+        });
+        let (tx, rx) = mpsc::sync_channel(self.queue_size);
+        debug!(
+            "walking {} with {} workers",
+            path.as_ref(),
+            self.workers.amount()
+        );
+        let workers = (0..self.workers.amount())
+            .map(|_| {
+                let shared = shared.clone();
+                let tx = tx.clone();
+                let workers = self.workers.clone();
+                thread::spawn(move || Self::worker(shared, workers, tx))
+            })
+            .collect();
+        Ok(SmbParallelWalkIter {
+            rx: Some(rx),
+            shared,
+            workers,
+            done: false,
+        })
+    }
+
+    /// Walk directories from the queue until the tree is complete
+    fn worker(shared: Arc<Shared>, workers: Workers, tx: SyncSender<SmbResult<SmbWalkEntry>>) {
+        let client = match workers.connect() {
+            Ok(client) => client,
+            Err(err) => {
+                error!("walker failed to connect: {}", err);
+                shared.on_error();
+                let _ = tx.send(Err(err));
+                return;
+            }
+        };
+        while let Some((dir, _listing)) = shared.next_dir() {
+            // directories which didn't fit in the shared queue; not bounded, since a worker can't wait for room
+            // in the queue while the other workers may be waiting as well
+            let mut pending = vec![dir];
+            while let Some(dir) = pending.pop() {
+                if shared.is_aborted() {
+                    break;
+                }
+                Self::list_dir(&shared, &client, &tx, dir, &mut pending);
+            }
+        }
+    }
+
+    /// List `dir`, sending its entries and queueing its subdirectories
+    fn list_dir(
+        shared: &Shared,
+        client: &SmbClient,
+        tx: &SyncSender<SmbResult<SmbWalkEntry>>,
+        dir: Dir,
+        pending: &mut Vec<Dir>,
+    ) {
+        match client.list_dirplus(&dir.path) {
+            Ok(entries) => {
+                for info in entries {
+                    let entry = SmbWalkEntry::new(&dir.path, &dir.relative_path, dir.depth, info);
+                    if shared.matcher.is_pruned(&entry) {
+                        continue;
+                    }
+                    if shared.matcher.descends(&entry) {
+                        let subdir = Dir {
+                            path: entry.path().to_string(),
+                            relative_path: entry.relative_path().to_string(),
+                            depth: dir.depth + 1,
+                        };
+                        if let Err(subdir) = shared.push_dir(subdir) {
+                            pending.push(subdir);
+                        }
+                    }
+                    if shared.matcher.is_yielded(&entry) && tx.send(Ok(entry)).is_err() {
+                        // receiver has been dropped
+                        shared.abort();
+                        break;
+                    }
+                }
+            }
+            Err(err) => {
+                error!("failed to read directory {}: {}", dir.path, err);
+                shared.on_error();
+                if tx.send(Err(err)).is_err() {
+                    shared.abort();
+                }
+            }
+        }
+    }
+}
+
+/// Directory waiting to be listed
+struct Dir {
+    path: String,
+    relative_path: String,
+    /// depth of the entries
+    depth: usize,
+}
+
+/// Directories waiting to be listed
+struct Queue {
+    dirs: Vec<Dir>,
+    /// directories being listed
+    active: usize,
+    aborted: bool,
+    /// whether a worker panicked
+    panicked: bool,
+}
+
+/// Directory taken from the queue by a worker; marks it as listed when dropped, even if the worker panics
+struct Listing<'a> {
+    shared: &'a Shared,
+}
+
+impl Drop for Listing<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            error!("walk worker panicked: aborting walk");
+            self.shared.queue().panicked = true;
+            self.shared.abort();
+        }
+        self.shared.done_dir();
+    }
+}
+
+/// State shared by the workers
+struct Shared {
+    matcher: WalkMatcher,
+    error_policy: SmbWalkErrorPolicy,
+    queue: Mutex<Queue>,
+    cond: Condvar,
+}
+
+impl Shared {
+    fn queue(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(Self::poisoned)
+    }
+
+    /// A worker panicked while holding the queue: abort the walk instead of propagating the panic
+    fn poisoned(err: PoisonError<MutexGuard<'_, Queue>>) -> MutexGuard<'_, Queue> {
+        error!("walk queue is poisoned: aborting walk");
+        let mut queue = err.into_inner();
+        queue.aborted = true;
+        queue
+    }
+
+    /// Wait for the next directory to list; returns `None` once the walk is complete or aborted
+    fn next_dir(&self) -> Option<(Dir, Listing<'_>)> {
+        let mut queue = self.queue();
+        loop {
+            if queue.aborted {
+                return None;
+            }
+            // depth-first, so the queue doesn't grow with the width of the tree
+            if let Some(dir) = queue.dirs.pop() {
+                queue.active += 1;
+                return Some((dir, Listing { shared: self }));
+            }
+            if queue.active == 0 {
+                return None;
+            }
+            queue = self.cond.wait(queue).unwrap_or_else(Self::poisoned);
+        }
+    }
+
+    /// Share `dir` with the other workers; it's given back if the queue is full
+    fn push_dir(&self, dir: Dir) -> Result<(), Dir> {
+        let mut queue = self.queue();
+        if queue.dirs.len() >= MAX_QUEUED_DIRS {
+            return Err(dir);
+        }
+        queue.dirs.push(dir);
+        self.cond.notify_one();
+        Ok(())
+    }
+
+    /// Mark a directory as listed
+    fn done_dir(&self) {
+        let mut queue = self.queue();
+        queue.active -= 1;
+        if queue.active == 0 && queue.dirs.is_empty() {
+            self.cond.notify_all();
+        }
+    }
+
+    fn on_error(&self) {
+        if self.error_policy == SmbWalkErrorPolicy::Abort {
+            self.abort();
+        }
+    }
+
+    fn abort(&self) {
+        self.queue().aborted = true;
+        self.cond.notify_all();
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.queue().aborted
+    }
+}
+
+/// Iterator over the entries found by [`SmbParallelWalk`].
+///
+/// Dropping the iterator stops the workers.
+pub struct SmbParallelWalkIter {
+    rx: Option<Receiver<SmbResult<SmbWalkEntry>>>,
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    done: bool,
+}
+
+impl Iterator for SmbParallelWalkIter {
+    type Item = SmbResult<SmbWalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        // ends when all the workers have quit
+        let item = self.rx.as_ref()?.recv().ok();
+        match &item {
+            None if self.shared.queue().panicked => {
+                self.done = true;
+                return Some(Err(SmbError::Io(io::Error::other("walk worker panicked"))));
+            }
+            None => self.done = true,
+            Some(Err(_)) if self.shared.error_policy == SmbWalkErrorPolicy::Abort => {
+                self.done = true
+            }
+            Some(_) => {}
+        }
+        item
+    }
+}
+
+impl Drop for SmbParallelWalkIter {
+    fn drop(&mut self) {
+        self.shared.abort();
+        // unblock workers waiting to send
+        self.rx.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test::TestCtx;
+    use crate::{mock, SmbError, SmbMode, SmbOpenOptions};
+
+    #[test]
+    #[serial]
+    fn should_walk_tree_in_parallel() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let mut entries: Vec<(String, usize)> =
+            SmbParallelWalk::new(ctx.credentials(), SmbOptions::default())
+                .workers(3)
+                .queue_size(2)
+                .walk("/cargo-test/tree")
+                .unwrap()
+                .map(|e| e.unwrap())
+                .map(|e| (e.relative_path().to_string(), e.depth()))
+                .collect();
+        entries.sort();
+        assert_eq!(entries.len(), 4 + 8 + 16);
+        assert_eq!(entries[0], (String::from("d0"), 1));
+        assert!(entries.contains(&(String::from("d1/d0/f1"), 3)));
+    }
+
+    #[test]
+    #[serial]
+    fn should_walk_tree_in_parallel_with_filters() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let mut entries: Vec<String> =
+            SmbParallelWalk::new(ctx.credentials(), SmbOptions::default())
+                .max_depth(2)
+                .min_depth(2)
+                .include("f*")
+                .exclude("/d1/")
+                .walk("/cargo-test/tree")
+                .unwrap()
+                .map(|e| e.unwrap().relative_path().to_string())
+                .collect();
+        entries.sort();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].as_str(), "d0/f0");
+    }
+
+    #[test]
+    #[serial]
+    fn should_stop_on_error() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let mut iter = SmbParallelWalk::new(ctx.credentials(), SmbOptions::default())
+            .walk("/cargo-test/nope")
+            .unwrap();
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn should_yield_errors() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let entries: Vec<SmbResult<SmbWalkEntry>> =
+            SmbParallelWalk::new(ctx.credentials().password("wrong"), SmbOptions::default())
+                .workers(2)
+                .error_policy(SmbWalkErrorPolicy::Yield)
+                .walk("/cargo-test")
+                .unwrap()
+                .collect();
+        assert!(!entries.is_empty());
+        assert!(entries.iter().all(|e| e.is_err()));
+    }
+
+    #[test]
+    fn should_fail_on_bad_pattern() {
+        assert_eq!(
+            SmbParallelWalk::new(SmbCredentials::default(), SmbOptions::default())
+                .include("a[b")
+                .walk("/")
+                .err()
+                .map(|e| matches!(e, SmbError::BadPattern(_))),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn should_abort_when_worker_panics() {
+        let shared = shared(2);
+        let result = thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _listing = shared.next_dir();
+                    panic!("worker panicked");
+                })
+                .join()
+        });
+        assert!(result.is_err());
+        // the directory is released and the walk aborted, so the other workers don't wait forever
+        assert!(shared.next_dir().is_none());
+        let queue = shared.queue();
+        assert_eq!(queue.active, 0);
+        assert!(queue.panicked);
+    }
+
+    #[test]
+    fn should_give_back_dirs_when_queue_is_full() {
+        let shared = shared(MAX_QUEUED_DIRS);
+        assert!(shared.push_dir(dir(0)).is_err());
+        let (_, listing) = shared.next_dir().unwrap();
+        assert!(shared.push_dir(dir(0)).is_ok());
+        drop(listing);
+        assert_eq!(shared.queue().active, 0);
+    }
+
+    fn shared(dirs: usize) -> Shared {
+        Shared {
+            matcher: WalkFilter::default().compile().unwrap(),
+            error_policy: SmbWalkErrorPolicy::Abort,
+            queue: Mutex::new(Queue {
+                dirs: (0..dirs).map(dir).collect(),
+                active: 0,
+                aborted: false,
+                panicked: false,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    fn dir(n: usize) -> Dir {
+        Dir {
+            path: format!("/cargo-test/d{}", n),
+            relative_path: format!("d{}", n),
+            depth: 1,
+        }
+    }
+
+    /// Make a tree with 2 dirs and 2 files in each directory, 3 levels deep
+    fn make_tree(client: &SmbClient) {
+        client
+            .mkdir("/cargo-test/tree", SmbMode::from(0o755))
+            .unwrap();
+        let mut parents = vec![String::from("/cargo-test/tree")];
+        for _ in 0..3 {
+            let mut dirs = Vec::new();
+            for parent in parents.iter() {
+                for i in 0..2 {
+                    let dir = format!("{}/d{}", parent, i);
+                    client.mkdir(&dir, SmbMode::from(0o755)).unwrap();
+                    dirs.push(dir);
+                    let mut reader = Cursor::new("Hello, World!\n".as_bytes());
+                    let mut writer = client
+                        .open_with(
+                            format!("{}/f{}", parent, i),
+                            SmbOpenOptions::default().create(true).write(true),
+                        )
+                        .unwrap();
+                    assert!(std::io::copy(&mut reader, &mut writer).is_ok());
+                }
+            }
+            parents = dirs;
+        }
+    }
+}
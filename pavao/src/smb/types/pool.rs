@@ -0,0 +1,439 @@
+//! # Pool
+//!
+//! module which exposes a pool of clients sharing the same credentials and options
+
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Deref;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use super::{SmbCredentials, SmbOptions};
+use crate::{SmbClient, SmbError, SmbResult};
+
+/// Default maximum amount of clients
+const DEFAULT_MAX_SIZE: usize = 8;
+/// Default time after which idle clients are evicted
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Pool of clients connected with the same credentials and options.
+///
+/// Each client has its own context, so operations on different leased clients run concurrently.
+/// Clients are created lazily, up to the maximum size of the pool; when all of them are in use,
+/// [`SmbClientPool::get`] waits for one to be returned.
+pub struct SmbClientPool {
+    credentials: SmbCredentials,
+    options: SmbOptions,
+    max_size: usize,
+    idle_timeout: Duration,
+    acquire_timeout: Option<Duration>,
+    health_check: bool,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+/// Idle client
+struct Idle {
+    client: SmbClient,
+    since: Instant,
+}
+
+#[derive(Default)]
+struct PoolState {
+    idle: VecDeque<Idle>,
+    /// clients leased or being created
+    in_use: usize,
+    /// leases waiting for a client to be returned
+    waiting: usize,
+    metrics: SmbPoolMetrics,
+}
+
+/// Metrics of a [`SmbClientPool`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmbPoolMetrics {
+    /// Amount of clients currently leased
+    pub in_use: usize,
+    /// Amount of idle clients
+    pub idle: usize,
+    /// Amount of clients created
+    pub created: u64,
+    /// Amount of idle clients evicted
+    pub evicted: u64,
+    /// Amount of clients discarded because they failed the health check
+    pub discarded: u64,
+    /// Amount of leases
+    pub leases: u64,
+    /// Amount of leases which had to wait for a client to be returned, including the ones which timed out
+    pub waits: u64,
+    /// Total time spent waiting for clients
+    pub wait_time: Duration,
+    /// Longest time spent waiting for a client
+    pub max_wait_time: Duration,
+}
+
+impl SmbClientPool {
+    /// Initialize a new pool of clients connecting with the provided credentials and options
+    pub fn new(credentials: SmbCredentials, options: SmbOptions) -> Self {
+        Self {
+            credentials,
+            options,
+            max_size: DEFAULT_MAX_SIZE,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            acquire_timeout: None,
+            health_check: true,
+            state: Mutex::new(PoolState::default()),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Set the maximum amount of clients. Must be greater than 0
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    /// Set the time after which idle clients are evicted
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Set how long [`SmbClientPool::get`] waits for a client before failing with `TimedOut`; waits forever if not set
+    pub fn acquire_timeout(mut self, acquire_timeout: Duration) -> Self {
+        self.acquire_timeout = Some(acquire_timeout);
+        self
+    }
+
+    /// Check whether clients still work when they are returned, discarding the broken ones
+    pub fn health_check(mut self, health_check: bool) -> Self {
+        self.health_check = health_check;
+        self
+    }
+
+    /// Lease a client, creating it if there's no idle client and the pool isn't full
+    pub fn get(&self) -> SmbResult<SmbPooledClient<'_>> {
+        let started = Instant::now();
+        let mut waited = false;
+        // declared before the state, so evicted clients are dropped once the lock is released
+        let mut evicted = Vec::new();
+        let mut state = self.state()?;
+        loop {
+            evicted.extend(self.evict(&mut state));
+            if let Some(idle) = state.idle.pop_back() {
+                self.lease(&mut state, started, waited);
+                return Ok(SmbPooledClient::new(self, idle.client));
+            }
+            if state.in_use < self.max_size {
+                self.lease(&mut state, started, waited);
+                drop(state);
+                return self.create();
+            }
+            waited = true;
+            state = match self.acquire_timeout {
+                None => self.wait(state, None)?,
+                Some(timeout) => {
+                    let Some(remaining) = timeout.checked_sub(started.elapsed()) else {
+                        Self::record_wait(&mut state, started);
+                        error!("timed out waiting for a pooled client");
+                        return Err(SmbError::Io(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "timed out waiting for a pooled client",
+                        )));
+                    };
+                    self.wait(state, Some(remaining))?
+                }
+            };
+        }
+    }
+
+    /// Evict the clients which have been idle for longer than the idle timeout
+    pub fn evict_idle(&self) -> SmbResult<()> {
+        let mut state = self.state()?;
+        let evicted = self.evict(&mut state);
+        drop(state);
+        drop(evicted);
+        Ok(())
+    }
+
+    /// Get the metrics of the pool
+    pub fn metrics(&self) -> SmbResult<SmbPoolMetrics> {
+        let state = self.state()?;
+        Ok(SmbPoolMetrics {
+            in_use: state.in_use,
+            idle: state.idle.len(),
+            ..state.metrics.clone()
+        })
+    }
+
+    fn state(&self) -> SmbResult<MutexGuard<'_, PoolState>> {
+        self.state.lock().map_err(|_| SmbError::Mutex)
+    }
+
+    /// Wait for a client to be returned, for at most `timeout` if set
+    fn wait<'a>(
+        &self,
+        mut state: MutexGuard<'a, PoolState>,
+        timeout: Option<Duration>,
+    ) -> SmbResult<MutexGuard<'a, PoolState>> {
+        state.waiting += 1;
+        let mut state = match timeout {
+            None => self.available.wait(state).map_err(|_| SmbError::Mutex)?,
+            Some(timeout) => {
+                self.available
+                    .wait_timeout(state, timeout)
+                    .map_err(|_| SmbError::Mutex)?
+                    .0
+            }
+        };
+        state.waiting -= 1;
+        Ok(state)
+    }
+
+    fn lease(&self, state: &mut PoolState, started: Instant, waited: bool) {
+        state.in_use += 1;
+        state.metrics.leases += 1;
+        if waited {
+            Self::record_wait(state, started);
+        }
+    }
+
+    fn record_wait(state: &mut PoolState, started: Instant) {
+        let wait_time = started.elapsed();
+        state.metrics.waits += 1;
+        state.metrics.wait_time += wait_time;
+        state.metrics.max_wait_time = state.metrics.max_wait_time.max(wait_time);
+    }
+
+    /// Create a new client for a lease which has already been accounted
+    fn create(&self) -> SmbResult<SmbPooledClient<'_>> {
+        trace!("creating a new pooled client");
+        match SmbClient::new(self.credentials.clone(), self.options.clone()) {
+            Ok(client) => {
+                if let Ok(mut state) = self.state() {
+                    state.metrics.created += 1;
+                }
+                Ok(SmbPooledClient::new(self, client))
+            }
+            Err(err) => {
+                error!("failed to create pooled client: {}", err);
+                self.release(None);
+                Err(err)
+            }
+        }
+    }
+
+    /// Remove the clients which have been idle for longer than the idle timeout from the pool and return them,
+    /// so they can be dropped, which closes their connections, once the lock is released
+    fn evict(&self, state: &mut PoolState) -> Vec<SmbClient> {
+        let mut evicted = Vec::new();
+        // idle clients are pushed at the back, so the oldest ones are at the front
+        while state
+            .idle
+            .front()
+            .map(|idle| idle.since.elapsed() >= self.idle_timeout)
+            .unwrap_or(false)
+        {
+            trace!("evicting idle client");
+            evicted.extend(state.idle.pop_front().map(|idle| idle.client));
+            state.metrics.evicted += 1;
+        }
+        evicted
+    }
+
+    /// Release a lease, putting `client` back among the idle clients if provided
+    fn release(&self, client: Option<SmbClient>) {
+        // the lease must be released even if the state is poisoned, or the slot would be leaked
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.in_use -= 1;
+        if let Some(client) = client {
+            state.idle.push_back(Idle {
+                client,
+                since: Instant::now(),
+            });
+        }
+        let evicted = self.evict(&mut state);
+        drop(state);
+        self.available.notify_one();
+        drop(evicted);
+    }
+
+    /// Release a lease, checking whether `client` still works
+    fn give_back(&self, client: SmbClient) {
+        if self.health_check && client.stat("/").is_err() {
+            debug!("pooled client failed the health check: discarding it");
+            if let Ok(mut state) = self.state() {
+                state.metrics.discarded += 1;
+            }
+            self.release(None);
+        } else {
+            self.release(Some(client));
+        }
+    }
+}
+
+/// Client leased from a [`SmbClientPool`], which is given back to the pool when dropped
+pub struct SmbPooledClient<'a> {
+    pool: &'a SmbClientPool,
+    client: Option<SmbClient>,
+}
+
+impl<'a> SmbPooledClient<'a> {
+    fn new(pool: &'a SmbClientPool, client: SmbClient) -> Self {
+        Self {
+            pool,
+            client: Some(client),
+        }
+    }
+
+    /// Drop the client instead of giving it back to the pool, e.g. because its connection is broken
+    pub fn discard(mut self) {
+        drop(self.client.take());
+        self.pool.release(None);
+    }
+}
+
+impl Deref for SmbPooledClient<'_> {
+    type Target = SmbClient;
+
+    fn deref(&self) -> &Self::Target {
+        self.client.as_ref().expect("client already given back")
+    }
+}
+
+impl Drop for SmbPooledClient<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.pool.give_back(client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    #[test]
+    fn should_build_pool() {
+        let pool = SmbClientPool::new(SmbCredentials::default(), SmbOptions::default());
+        assert_eq!(pool.max_size, DEFAULT_MAX_SIZE);
+        assert_eq!(pool.idle_timeout, DEFAULT_IDLE_TIMEOUT);
+        assert_eq!(pool.acquire_timeout, None);
+        assert_eq!(pool.health_check, true);
+        let pool = pool
+            .max_size(0)
+            .idle_timeout(Duration::from_secs(1))
+            .acquire_timeout(Duration::from_secs(2))
+            .health_check(false);
+        assert_eq!(pool.max_size, 1);
+        assert_eq!(pool.idle_timeout, Duration::from_secs(1));
+        assert_eq!(pool.acquire_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(pool.health_check, false);
+        assert_eq!(pool.metrics().unwrap(), SmbPoolMetrics::default());
+    }
+
+    #[test]
+    #[serial]
+    fn should_lease_and_reuse_clients() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let pool = SmbClientPool::new(ctx.credentials(), SmbOptions::default()).max_size(2);
+        let a = pool.get().unwrap();
+        let b = pool.get().unwrap();
+        assert!(a.stat("/cargo-test").is_ok());
+        let metrics = pool.metrics().unwrap();
+        assert_eq!(metrics.in_use, 2);
+        assert_eq!(metrics.created, 2);
+        drop(a);
+        drop(b);
+        let metrics = pool.metrics().unwrap();
+        assert_eq!(metrics.in_use, 0);
+        assert_eq!(metrics.idle, 2);
+        // idle clients are reused
+        let c = pool.get().unwrap();
+        c.discard();
+        let metrics = pool.metrics().unwrap();
+        assert_eq!(metrics.created, 2);
+        assert_eq!(metrics.leases, 3);
+        assert_eq!(metrics.idle, 1);
+    }
+
+    #[test]
+    #[serial]
+    fn should_wait_for_returned_client() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        // leases time out while the pool is full
+        let pool = SmbClientPool::new(ctx.credentials(), SmbOptions::default())
+            .max_size(1)
+            .acquire_timeout(Duration::from_millis(100));
+        let client = pool.get().unwrap();
+        assert!(pool.get().is_err());
+        drop(client);
+        let metrics = pool.metrics().unwrap();
+        assert_eq!(metrics.waits, 1);
+        assert!(metrics.wait_time >= Duration::from_millis(100));
+        // leases get the client returned while they wait
+        let pool =
+            Arc::new(SmbClientPool::new(ctx.credentials(), SmbOptions::default()).max_size(1));
+        let client = pool.get().unwrap();
+        let handle = {
+            let pool = pool.clone();
+            std::thread::spawn(move || pool.get().map(|c| c.stat("/cargo-test").is_ok()))
+        };
+        while pool.state().unwrap().waiting == 0 {
+            std::thread::yield_now();
+        }
+        drop(client);
+        assert_eq!(handle.join().unwrap().unwrap(), true);
+        let metrics = pool.metrics().unwrap();
+        assert_eq!(metrics.created, 1);
+        assert_eq!(metrics.waits, 1);
+    }
+
+    #[test]
+    fn should_release_lease_when_poisoned() {
+        let pool = Arc::new(SmbClientPool::new(
+            SmbCredentials::default(),
+            SmbOptions::default(),
+        ));
+        pool.state().unwrap().in_use = 1;
+        let poisoner = pool.clone();
+        assert!(std::thread::spawn(move || {
+            let _state = poisoner.state().unwrap();
+            panic!("poisoning the pool state");
+        })
+        .join()
+        .is_err());
+        pool.release(None);
+        assert_eq!(
+            pool.state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .in_use,
+            0
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_evict_idle_clients() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let pool = SmbClientPool::new(ctx.credentials(), SmbOptions::default())
+            .idle_timeout(Duration::ZERO)
+            .health_check(false);
+        drop(pool.get().unwrap());
+        pool.evict_idle().unwrap();
+        let metrics = pool.metrics().unwrap();
+        assert_eq!(metrics.idle, 0);
+        assert_eq!(metrics.evicted, 1);
+    }
+}
@@ -0,0 +1,191 @@
+//! # Share
+//!
+//! handle to a share which reuses the context of a client
+
+use super::{SmbDirent, SmbDirentInfo, SmbFile, SmbMode, SmbOpenOptions, SmbStat, SmbStatVfs};
+use crate::{SmbClient, SmbResult};
+
+/// Handle to a share, obtained with [`SmbClient::share`].
+///
+/// Paths are relative to the share; operations run on the context of the client which created the handle.
+pub struct SmbShare<'a> {
+    client: &'a SmbClient,
+    uri: String,
+}
+
+impl<'a> SmbShare<'a> {
+    pub(crate) fn new(client: &'a SmbClient, uri: String) -> Self {
+        Self { client, uri }
+    }
+
+    /// Get share uri
+    pub fn uri(&self) -> &str {
+        self.uri.as_str()
+    }
+
+    /// Get the client the share belongs to
+    pub fn client(&self) -> &'a SmbClient {
+        self.client
+    }
+
+    /// Unlink file at `path`
+    pub fn unlink<S>(&self, path: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        self.client.unlink(self.path_uri(path))
+    }
+
+    /// Rename file at `orig_url` to `new_url`
+    pub fn rename<S>(&self, orig_url: S, new_url: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        self.client
+            .rename(self.path_uri(orig_url), self.path_uri(new_url))
+    }
+
+    /// Rename file at `orig_url` to `new_url` on the `dest` share
+    pub fn rename_to<S>(&self, orig_url: S, dest: &SmbShare<'_>, new_url: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        self.client
+            .rename_to(self.path_uri(orig_url), dest.client, dest.path_uri(new_url))
+    }
+
+    /// List content of directory at `path`
+    pub fn list_dir<S>(&self, path: S) -> SmbResult<Vec<SmbDirent>>
+    where
+        S: AsRef<str>,
+    {
+        self.client.list_dir(self.path_uri(path))
+    }
+
+    /// List content of directory with metadata at `path`
+    pub fn list_dirplus<S>(&self, path: S) -> SmbResult<Vec<SmbDirentInfo>>
+    where
+        S: AsRef<str>,
+    {
+        self.client.list_dirplus(self.path_uri(path))
+    }
+
+    /// Make directory at `p` with provided `mode`
+    pub fn mkdir<S>(&self, p: S, mode: SmbMode) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        self.client.mkdir(self.path_uri(p), mode)
+    }
+
+    /// Remove directory at `p`
+    pub fn rmdir<S>(&self, p: S) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        self.client.rmdir(self.path_uri(p))
+    }
+
+    /// Stat filesystem at `p` and return its metadata
+    pub fn statvfs<S>(&self, p: S) -> SmbResult<SmbStatVfs>
+    where
+        S: AsRef<str>,
+    {
+        self.client.statvfs(self.path_uri(p))
+    }
+
+    /// Stat file at `p` and return its metadata
+    pub fn stat<S>(&self, p: S) -> SmbResult<SmbStat>
+    where
+        S: AsRef<str>,
+    {
+        self.client.stat(self.path_uri(p))
+    }
+
+    /// Change file mode for file at `p`
+    pub fn chmod<S>(&self, p: S, mode: SmbMode) -> SmbResult<()>
+    where
+        S: AsRef<str>,
+    {
+        self.client.chmod(self.path_uri(p), mode)
+    }
+
+    /// Open a file at `path` with provided options
+    pub fn open_with<P: AsRef<str>>(
+        &self,
+        path: P,
+        options: SmbOpenOptions,
+    ) -> SmbResult<SmbFile<'a>> {
+        self.client.open_with(self.path_uri(path), options)
+    }
+
+    /// Get absolute uri for `p` on this share
+    fn path_uri<S>(&self, p: S) -> String
+    where
+        S: AsRef<str>,
+    {
+        match p.as_ref().starts_with("smb://") {
+            true => p.as_ref().to_string(),
+            false => format!("{}{}", self.uri, p.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::{Cursor, Read};
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mock;
+    use crate::test::TestCtx;
+
+    #[test]
+    #[serial]
+    fn should_get_share_handle() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let share = ctx.client.share("temp");
+        assert!(share.uri().ends_with("/temp"));
+        assert_eq!(
+            share.path_uri("/cargo-test").as_str(),
+            ctx.client.uri("/cargo-test").as_str()
+        );
+        assert!(share.list_dir("/cargo-test").is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn should_read_and_write_through_share() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let share = ctx.client.share("/temp");
+        let mut writer = share
+            .open_with(
+                "/cargo-test/test",
+                SmbOpenOptions::default().create(true).write(true),
+            )
+            .unwrap();
+        let mut reader = Cursor::new("Hello, World!\n".as_bytes());
+        assert!(std::io::copy(&mut reader, &mut writer).is_ok());
+        drop(writer);
+        assert_eq!(share.stat("/cargo-test/test").unwrap().size, 14);
+        let mut reader = share
+            .open_with("/cargo-test/test", SmbOpenOptions::default().read(true))
+            .unwrap();
+        let mut output = String::default();
+        assert!(reader.read_to_string(&mut output).is_ok());
+        assert_eq!(output.as_str(), "Hello, World!\n");
+    }
+
+    #[test]
+    #[serial]
+    fn should_fail_on_unknown_share() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        assert!(ctx.client.share("foobar").list_dir("/").is_err());
+    }
+}
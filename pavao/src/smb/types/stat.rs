@@ -12,6 +12,10 @@ use super::SmbMode;
 use crate::utils::char_ptr_to_string;
 use crate::{SmbDirentType, SmbError};
 
+/// DOS Attribute mask for HIDDEN
+const FILE_ATTRIBUTE_HIDDEN: u16 = 0x0002;
+/// DOS Attribute mask for SYSTEM
+const FILE_ATTRIBUTE_SYSTEM: u16 = 0x0004;
 /// DOS Attribute mask for DIRECTORY
 const FILE_ATTRIBUTE_DIRECTORY: u16 = 0x0010;
 
@@ -281,6 +285,16 @@ impl SmbDirentInfo {
     pub fn short_name(&self) -> &str {
         self.short_name.as_str()
     }
+
+    /// Returns whether the entity has the hidden attribute
+    pub fn is_hidden(&self) -> bool {
+        self.attrs & FILE_ATTRIBUTE_HIDDEN != 0
+    }
+
+    /// Returns whether the entity has the system attribute
+    pub fn is_system(&self) -> bool {
+        self.attrs & FILE_ATTRIBUTE_SYSTEM != 0
+    }
 }
 
 impl TryFrom<libsmb_file_info> for SmbDirentInfo {
@@ -314,7 +328,7 @@ fn time_t_to_system_time(t: time_t) -> SystemTime {
 #[cfg(test)]
 mod test {
 
-    use pretty_assertions::assert_ne;
+    use pretty_assertions::{assert_eq, assert_ne};
 
     use super::*;
 
@@ -322,4 +336,27 @@ mod test {
     fn should_convert_time_t_into_system_time() {
         assert_ne!(time_t_to_system_time(1000), UNIX_EPOCH);
     }
+
+    #[test]
+    fn should_get_dirent_info_attributes() {
+        let mut info = SmbDirentInfo {
+            name: String::from("desktop.ini"),
+            short_name: String::from("DESKTOP.INI"),
+            size: 0,
+            attrs: FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM,
+            ctime: UNIX_EPOCH,
+            btime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            atime: UNIX_EPOCH,
+            uid: 0,
+            gid: 0,
+        };
+        assert_eq!(info.get_type(), SmbDirentType::File);
+        assert_eq!(info.is_hidden(), true);
+        assert_eq!(info.is_system(), true);
+        info.attrs = FILE_ATTRIBUTE_DIRECTORY;
+        assert_eq!(info.get_type(), SmbDirentType::Dir);
+        assert_eq!(info.is_hidden(), false);
+        assert_eq!(info.is_system(), false);
+    }
 }
@@ -0,0 +1,320 @@
+//! # Sync
+//!
+//! module which exposes the options, the plan and the report of directory synchronization
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::SmbTransferOptions;
+
+/// Default tolerance when comparing modification times
+const DEFAULT_MTIME_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Direction of a synchronization
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SmbSyncDirection {
+    /// Make the remote tree a mirror of the local tree
+    Upload,
+    /// Make the local tree a mirror of the remote tree
+    Download,
+}
+
+/// Which entries are deleted from the destination
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SmbDeletePolicy {
+    /// Never delete entries from the destination
+    #[default]
+    Keep,
+    /// Delete the entries which don't exist in the source
+    Extraneous,
+    /// Delete the entries which don't exist in the source, and the excluded entries too
+    ExtraneousAndExcluded,
+}
+
+/// What to do when a destination file has been modified more recently than the source
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SmbConflictPolicy {
+    /// Replace the destination file with the source
+    #[default]
+    Overwrite,
+    /// Keep the destination file
+    Skip,
+    /// Fail planning the synchronization
+    Fail,
+}
+
+/// Options for synchronizing directory trees
+#[derive(Debug, Clone)]
+pub struct SmbSyncOptions {
+    pub(crate) checksum: bool,
+    pub(crate) delete: SmbDeletePolicy,
+    pub(crate) conflict: SmbConflictPolicy,
+    pub(crate) exclude: Vec<String>,
+    pub(crate) mtime_tolerance: Duration,
+    pub(crate) transfer: SmbTransferOptions,
+}
+
+impl Default for SmbSyncOptions {
+    fn default() -> Self {
+        Self {
+            checksum: false,
+            delete: SmbDeletePolicy::default(),
+            conflict: SmbConflictPolicy::default(),
+            exclude: Vec::default(),
+            mtime_tolerance: DEFAULT_MTIME_TOLERANCE,
+            transfer: SmbTransferOptions::default(),
+        }
+    }
+}
+
+impl SmbSyncOptions {
+    /// Compare the checksums of files with the same size, instead of their modification times
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Set which entries are deleted from the destination
+    pub fn delete(mut self, delete: SmbDeletePolicy) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Set what to do when a destination file is newer than the source
+    pub fn conflict(mut self, conflict: SmbConflictPolicy) -> Self {
+        self.conflict = conflict;
+        self
+    }
+
+    /// Don't synchronize entries matching the gitignore-style `pattern`. Can be called multiple times.
+    pub fn exclude<S>(mut self, pattern: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.exclude.push(pattern.as_ref().to_string());
+        self
+    }
+
+    /// Set the tolerance when comparing modification times, as servers may round them
+    pub fn mtime_tolerance(mut self, tolerance: Duration) -> Self {
+        self.mtime_tolerance = tolerance;
+        self
+    }
+
+    /// Set the options used to transfer files. The overwrite policy is ignored, as the plan decides what to replace
+    pub fn transfer_options(mut self, transfer: SmbTransferOptions) -> Self {
+        self.transfer = transfer;
+        self
+    }
+}
+
+/// Kind of a synchronization action
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SmbSyncActionKind {
+    Create,
+    Update,
+    Delete,
+    Skip,
+}
+
+impl fmt::Display for SmbSyncActionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Skip => "skip",
+        })
+    }
+}
+
+/// Why an action has been planned
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SmbSyncReason {
+    /// Entry doesn't exist at destination
+    Missing,
+    /// Entry is a file at source and a directory at destination, or the other way round
+    TypeChanged,
+    /// Files have a different size
+    Size,
+    /// Files have a different modification time
+    Modified,
+    /// Files have a different checksum
+    Checksum,
+    /// Files are the same
+    Unchanged,
+    /// Destination file is newer than the source
+    Conflict,
+    /// Entry doesn't exist at source
+    Extraneous,
+    /// Entry is excluded
+    Excluded,
+}
+
+impl fmt::Display for SmbSyncReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Missing => "missing",
+            Self::TypeChanged => "type changed",
+            Self::Size => "size",
+            Self::Modified => "modified",
+            Self::Checksum => "checksum",
+            Self::Unchanged => "unchanged",
+            Self::Conflict => "conflict",
+            Self::Extraneous => "extraneous",
+            Self::Excluded => "excluded",
+        })
+    }
+}
+
+/// Action planned for an entry
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SmbSyncAction {
+    /// What to do with the entry
+    pub kind: SmbSyncActionKind,
+    /// Why
+    pub reason: SmbSyncReason,
+    /// Path of the entry relative to the synchronized directories, without leading `/`
+    pub path: String,
+    /// Whether the entry is a directory (at source, or at destination for deletions)
+    pub is_dir: bool,
+    /// Size of the source file
+    pub size: u64,
+}
+
+/// Plan of a synchronization, obtained with [`crate::SmbClient::sync_plan`].
+///
+/// Its `Display` implementation lists the actions, so it can be shown as a dry run.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SmbSyncPlan {
+    pub(crate) direction: SmbSyncDirection,
+    pub(crate) local: PathBuf,
+    pub(crate) remote: String,
+    pub(crate) actions: Vec<SmbSyncAction>,
+}
+
+impl SmbSyncPlan {
+    /// Get the direction of the synchronization
+    pub fn direction(&self) -> SmbSyncDirection {
+        self.direction
+    }
+
+    /// Get planned actions, sorted by path
+    pub fn actions(&self) -> &[SmbSyncAction] {
+        self.actions.as_slice()
+    }
+
+    /// Returns whether the plan has nothing to do
+    pub fn is_empty(&self) -> bool {
+        self.actions
+            .iter()
+            .all(|a| a.kind == SmbSyncActionKind::Skip)
+    }
+
+    /// Count the actions of `kind`
+    pub fn count(&self, kind: SmbSyncActionKind) -> usize {
+        self.actions.iter().filter(|a| a.kind == kind).count()
+    }
+}
+
+impl fmt::Display for SmbSyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in self.actions.iter() {
+            writeln!(
+                f,
+                "{:<6} {}{} ({})",
+                action.kind,
+                action.path,
+                if action.is_dir { "/" } else { "" },
+                action.reason
+            )?;
+        }
+        write!(
+            f,
+            "{} to create, {} to update, {} to delete, {} to skip",
+            self.count(SmbSyncActionKind::Create),
+            self.count(SmbSyncActionKind::Update),
+            self.count(SmbSyncActionKind::Delete),
+            self.count(SmbSyncActionKind::Skip)
+        )
+    }
+}
+
+/// Report of a synchronization
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmbSyncReport {
+    /// Amount of entries created
+    pub created: u64,
+    /// Amount of entries updated
+    pub updated: u64,
+    /// Amount of entries deleted
+    pub deleted: u64,
+    /// Amount of entries skipped
+    pub skipped: u64,
+    /// Amount of bytes transferred
+    pub bytes: u64,
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn should_build_sync_options() {
+        let options = SmbSyncOptions::default();
+        assert_eq!(options.checksum, false);
+        assert_eq!(options.delete, SmbDeletePolicy::Keep);
+        assert_eq!(options.conflict, SmbConflictPolicy::Overwrite);
+        assert!(options.exclude.is_empty());
+        assert_eq!(options.mtime_tolerance, DEFAULT_MTIME_TOLERANCE);
+        let options = options
+            .checksum(true)
+            .delete(SmbDeletePolicy::Extraneous)
+            .conflict(SmbConflictPolicy::Skip)
+            .exclude("*.tmp")
+            .mtime_tolerance(Duration::ZERO)
+            .transfer_options(SmbTransferOptions::default().buffer_size(4));
+        assert_eq!(options.checksum, true);
+        assert_eq!(options.delete, SmbDeletePolicy::Extraneous);
+        assert_eq!(options.conflict, SmbConflictPolicy::Skip);
+        assert_eq!(options.exclude, vec![String::from("*.tmp")]);
+        assert_eq!(options.mtime_tolerance, Duration::ZERO);
+        assert_eq!(options.transfer.buffer_size, 4);
+    }
+
+    #[test]
+    fn should_display_plan() {
+        let plan = SmbSyncPlan {
+            direction: SmbSyncDirection::Upload,
+            local: PathBuf::from("/tmp"),
+            remote: String::from("/backup"),
+            actions: vec![
+                SmbSyncAction {
+                    kind: SmbSyncActionKind::Create,
+                    reason: SmbSyncReason::Missing,
+                    path: String::from("a"),
+                    is_dir: true,
+                    size: 0,
+                },
+                SmbSyncAction {
+                    kind: SmbSyncActionKind::Skip,
+                    reason: SmbSyncReason::Unchanged,
+                    path: String::from("b.txt"),
+                    is_dir: false,
+                    size: 14,
+                },
+            ],
+        };
+        assert_eq!(plan.direction(), SmbSyncDirection::Upload);
+        assert_eq!(plan.is_empty(), false);
+        assert_eq!(plan.count(SmbSyncActionKind::Skip), 1);
+        assert_eq!(
+            plan.to_string().as_str(),
+            "create a/ (missing)\nskip   b.txt (unchanged)\n1 to create, 0 to update, 0 to delete, 1 to skip"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! # Transfer
+//!
+//! module which exposes the options and the report of uploads and downloads
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Default size of the buffer used to transfer files
+const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Callback invoked while a file is being transferred
+type ProgressFn = Arc<dyn Fn(&SmbTransferProgress<'_>) + Send + Sync>;
+
+/// What to do when the destination file already exists
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SmbOverwritePolicy {
+    /// Fail with an `AlreadyExists` error
+    #[default]
+    Fail,
+    /// Keep the existing file
+    Skip,
+    /// Overwrite the existing file
+    Always,
+    /// Overwrite the existing file only if the source has been modified more recently
+    IfNewer,
+}
+
+/// Options for uploading and downloading files
+#[derive(Clone)]
+pub struct SmbTransferOptions {
+    pub(crate) buffer_size: usize,
+    pub(crate) overwrite: SmbOverwritePolicy,
+    pub(crate) preserve_mtime: bool,
+    pub(crate) preserve_permissions: bool,
+    pub(crate) create_parents: bool,
+    pub(crate) progress: Option<ProgressFn>,
+    pub(crate) verify_checksum: bool,
+}
+
+impl Default for SmbTransferOptions {
+    fn default() -> Self {
+        Self {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            overwrite: SmbOverwritePolicy::default(),
+            preserve_mtime: true,
+            preserve_permissions: false,
+            create_parents: false,
+            progress: None,
+            verify_checksum: false,
+        }
+    }
+}
+
+impl fmt::Debug for SmbTransferOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmbTransferOptions")
+            .field("buffer_size", &self.buffer_size)
+            .field("overwrite", &self.overwrite)
+            .field("preserve_mtime", &self.preserve_mtime)
+            .field("preserve_permissions", &self.preserve_permissions)
+            .field("create_parents", &self.create_parents)
+            .field("progress", &self.progress.is_some())
+            .field("verify_checksum", &self.verify_checksum)
+            .finish()
+    }
+}
+
+impl SmbTransferOptions {
+    /// Set the size of the buffer used to stream files. Must be greater than 0
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Set what to do when the destination file already exists
+    pub fn overwrite(mut self, overwrite: SmbOverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Copy the modification time of the source to the destination
+    pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+        self
+    }
+
+    /// Copy the permissions of the source to the destination
+    pub fn preserve_permissions(mut self, preserve_permissions: bool) -> Self {
+        self.preserve_permissions = preserve_permissions;
+        self
+    }
+
+    /// Create the missing parent directories of the destination
+    pub fn create_parents(mut self, create_parents: bool) -> Self {
+        self.create_parents = create_parents;
+        self
+    }
+
+    /// Set a callback invoked each time a chunk of a file has been transferred
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(&SmbTransferProgress<'_>) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Compare the CRC-32 checksums of source and destination once a file has been transferred
+    pub fn verify_checksum(mut self, verify_checksum: bool) -> Self {
+        self.verify_checksum = verify_checksum;
+        self
+    }
+
+    /// Report progress to the callback, if any
+    pub(crate) fn report_progress(&self, path: &str, transferred: u64, total: u64) {
+        if let Some(progress) = self.progress.as_ref() {
+            progress(&SmbTransferProgress {
+                path,
+                transferred,
+                total,
+            });
+        }
+    }
+}
+
+/// Progress of a file transfer
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SmbTransferProgress<'a> {
+    /// Path of the source file
+    pub path: &'a str,
+    /// Bytes transferred so far
+    pub transferred: u64,
+    /// Size of the file
+    pub total: u64,
+}
+
+/// Report of an upload or a download
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmbTransferReport {
+    /// Amount of files transferred
+    pub files: u64,
+    /// Amount of directories created
+    pub directories: u64,
+    /// Amount of bytes transferred
+    pub bytes: u64,
+    /// Amount of files skipped, because they already exist at destination
+    pub skipped: u64,
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn should_create_default_transfer_options() {
+        let options = SmbTransferOptions::default();
+        assert_eq!(options.buffer_size, DEFAULT_BUFFER_SIZE);
+        assert_eq!(options.overwrite, SmbOverwritePolicy::Fail);
+        assert_eq!(options.preserve_mtime, true);
+        assert_eq!(options.preserve_permissions, false);
+        assert_eq!(options.create_parents, false);
+        assert!(options.progress.is_none());
+        assert_eq!(options.verify_checksum, false);
+    }
+
+    #[test]
+    fn should_build_transfer_options() {
+        let transferred = Arc::new(AtomicU64::new(0));
+        let counter = transferred.clone();
+        let options = SmbTransferOptions::default()
+            .buffer_size(0)
+            .overwrite(SmbOverwritePolicy::IfNewer)
+            .preserve_mtime(false)
+            .preserve_permissions(true)
+            .create_parents(true)
+            .progress(move |p| counter.store(p.transferred, Ordering::SeqCst))
+            .verify_checksum(true);
+        assert_eq!(options.buffer_size, 1);
+        assert_eq!(options.overwrite, SmbOverwritePolicy::IfNewer);
+        assert_eq!(options.preserve_mtime, false);
+        assert_eq!(options.preserve_permissions, true);
+        assert_eq!(options.create_parents, true);
+        assert_eq!(options.verify_checksum, true);
+        options.report_progress("/test", 128, 256);
+        assert_eq!(transferred.load(Ordering::SeqCst), 128);
+        assert!(format!("{:?}", options).contains("progress: true"));
+    }
+}
@@ -0,0 +1,592 @@
+//! # Walk
+//!
+//! module which exposes the recursive directory walker
+
+use std::cmp::Ordering;
+use std::vec::IntoIter;
+
+use super::{SmbDirentInfo, SmbDirentType};
+use crate::smb::pattern::PatternSet;
+use crate::{utils, SmbClient, SmbError, SmbResult};
+
+/// Sorting function for the entries of a directory
+type SortFn<'a> = Box<dyn FnMut(&SmbDirentInfo, &SmbDirentInfo) -> Ordering + 'a>;
+
+/// What to do when a directory can't be read while walking
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SmbWalkErrorPolicy {
+    /// Yield the error and stop walking
+    #[default]
+    Abort,
+    /// Yield the error and keep walking the other directories
+    Yield,
+}
+
+/// Recursive directory walker, obtained with [`SmbClient::walk`].
+///
+/// The root directory itself is not yielded: its entries have depth 1.
+/// By default the tree is walked in pre-order, without depth limits and without sorting.
+pub struct SmbWalkDir<'a> {
+    client: &'a SmbClient,
+    root: String,
+    contents_first: bool,
+    sort_by: Option<SortFn<'a>>,
+    filter: WalkFilter,
+    error_policy: SmbWalkErrorPolicy,
+}
+
+impl<'a> SmbWalkDir<'a> {
+    pub(crate) fn new(client: &'a SmbClient, root: String) -> Self {
+        Self {
+            client,
+            root,
+            contents_first: false,
+            sort_by: None,
+            filter: WalkFilter::default(),
+            error_policy: SmbWalkErrorPolicy::default(),
+        }
+    }
+
+    /// Only yield entries at depth `depth` or deeper
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.filter.min_depth = depth.max(1);
+        self
+    }
+
+    /// Don't descend deeper than `depth`
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.filter.max_depth = depth;
+        self
+    }
+
+    /// Yield the content of directories before the directories themselves (post-order)
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.contents_first = contents_first;
+        self
+    }
+
+    /// Sort the entries of each directory by name
+    pub fn sort_by_name(self) -> Self {
+        self.sort_by(|a, b| a.name().cmp(b.name()))
+    }
+
+    /// Sort the entries of each directory with the provided compare function
+    pub fn sort_by<F>(mut self, cmp: F) -> Self
+    where
+        F: FnMut(&SmbDirentInfo, &SmbDirentInfo) -> Ordering + 'a,
+    {
+        self.sort_by = Some(Box::new(cmp));
+        self
+    }
+
+    /// Only yield entries matching the gitignore-style `pattern`. Can be called multiple times.
+    ///
+    /// Directories are walked anyway, so files matching the patterns are found at any depth.
+    pub fn include<S>(mut self, pattern: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.filter.include.push(pattern.as_ref().to_string());
+        self
+    }
+
+    /// Skip entries matching the gitignore-style `pattern`; excluded directories are not walked.
+    /// Can be called multiple times.
+    pub fn exclude<S>(mut self, pattern: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.filter.exclude.push(pattern.as_ref().to_string());
+        self
+    }
+
+    /// Match include and exclude patterns case sensitively (default)
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.filter.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Skip entries with the hidden attribute; hidden directories are not walked
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.filter.skip_hidden = skip;
+        self
+    }
+
+    /// Skip entries with the system attribute; system directories are not walked
+    pub fn skip_system(mut self, skip: bool) -> Self {
+        self.filter.skip_system = skip;
+        self
+    }
+
+    /// Set what to do when a directory can't be read
+    pub fn error_policy(mut self, policy: SmbWalkErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+}
+
+impl<'a> IntoIterator for SmbWalkDir<'a> {
+    type Item = SmbResult<SmbWalkEntry>;
+    type IntoIter = SmbWalkIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let (matcher, pending) = match self.filter.compile() {
+            Ok(matcher) => (Some(matcher), None),
+            Err(err) => (None, Some(Err(err))),
+        };
+        SmbWalkIter {
+            walk: self,
+            matcher,
+            stack: Vec::default(),
+            started: false,
+            pending,
+        }
+    }
+}
+
+/// Entry yielded by [`SmbWalkIter`]
+#[derive(Debug, Clone)]
+pub struct SmbWalkEntry {
+    path: String,
+    relative_path: String,
+    depth: usize,
+    info: SmbDirentInfo,
+}
+
+impl SmbWalkEntry {
+    /// Make the entry for `info`, found in the directory at `path`
+    pub(crate) fn new(path: &str, relative_path: &str, depth: usize, info: SmbDirentInfo) -> Self {
+        Self {
+            path: utils::join_path(path, info.name()),
+            relative_path: match relative_path.is_empty() {
+                true => info.name().to_string(),
+                false => format!("{}/{}", relative_path, info.name()),
+            },
+            depth,
+            info,
+        }
+    }
+
+    /// Get the path of the entry, which can be passed to the client
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    /// Get the path of the entry relative to the walked directory, without leading `/`
+    pub fn relative_path(&self) -> &str {
+        self.relative_path.as_str()
+    }
+
+    /// Get the depth of the entry; entries of the walked directory have depth 1
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Get name
+    pub fn name(&self) -> &str {
+        self.info.name()
+    }
+
+    /// Get entry type
+    pub fn get_type(&self) -> SmbDirentType {
+        self.info.get_type()
+    }
+
+    /// Returns whether the entry is a directory
+    pub fn is_dir(&self) -> bool {
+        self.get_type() == SmbDirentType::Dir
+    }
+
+    /// Get entry metadata
+    pub fn info(&self) -> &SmbDirentInfo {
+        &self.info
+    }
+
+    /// Get entry metadata, consuming the entry
+    pub fn into_info(self) -> SmbDirentInfo {
+        self.info
+    }
+}
+
+/// Filters shared by the walkers
+#[derive(Debug, Clone)]
+pub(crate) struct WalkFilter {
+    pub min_depth: usize,
+    pub max_depth: usize,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub case_sensitive: bool,
+    pub skip_hidden: bool,
+    pub skip_system: bool,
+}
+
+impl Default for WalkFilter {
+    fn default() -> Self {
+        Self {
+            min_depth: 1,
+            max_depth: usize::MAX,
+            include: Vec::default(),
+            exclude: Vec::default(),
+            case_sensitive: true,
+            skip_hidden: false,
+            skip_system: false,
+        }
+    }
+}
+
+impl WalkFilter {
+    /// Compile the patterns of the filter
+    pub fn compile(&self) -> SmbResult<WalkMatcher> {
+        Ok(WalkMatcher {
+            min_depth: self.min_depth,
+            max_depth: self.max_depth,
+            include: PatternSet::new(&self.include, self.case_sensitive)?,
+            exclude: PatternSet::new(&self.exclude, self.case_sensitive)?,
+            skip_hidden: self.skip_hidden,
+            skip_system: self.skip_system,
+        })
+    }
+}
+
+/// Compiled [`WalkFilter`]
+#[derive(Debug, Clone)]
+pub(crate) struct WalkMatcher {
+    min_depth: usize,
+    max_depth: usize,
+    include: PatternSet,
+    exclude: PatternSet,
+    skip_hidden: bool,
+    skip_system: bool,
+}
+
+impl WalkMatcher {
+    /// Returns whether `entry` and its content must be skipped
+    pub fn is_pruned(&self, entry: &SmbWalkEntry) -> bool {
+        (self.skip_hidden && entry.info.is_hidden())
+            || (self.skip_system && entry.info.is_system())
+            || self.exclude.is_match(&entry.relative_path, entry.is_dir())
+    }
+
+    /// Returns whether `entry` must be yielded
+    pub fn is_yielded(&self, entry: &SmbWalkEntry) -> bool {
+        entry.depth >= self.min_depth
+            && (self.include.is_empty()
+                || self.include.is_match(&entry.relative_path, entry.is_dir()))
+    }
+
+    /// Returns whether the content of `entry` must be walked
+    pub fn descends(&self, entry: &SmbWalkEntry) -> bool {
+        entry.is_dir() && entry.depth < self.max_depth
+    }
+}
+
+/// Directory being walked
+struct Frame {
+    path: String,
+    relative_path: String,
+    /// depth of the entries
+    depth: usize,
+    entries: IntoIter<SmbDirentInfo>,
+    /// directory to yield once its content has been walked, with `contents_first`
+    dir: Option<SmbWalkEntry>,
+}
+
+/// Iterator over the entries of a directory tree
+pub struct SmbWalkIter<'a> {
+    walk: SmbWalkDir<'a>,
+    matcher: Option<WalkMatcher>,
+    stack: Vec<Frame>,
+    started: bool,
+    /// item to yield before walking further
+    pending: Option<SmbResult<SmbWalkEntry>>,
+}
+
+impl SmbWalkIter<'_> {
+    /// List directory at `path` and push it on the stack
+    fn push_dir(
+        &mut self,
+        path: String,
+        relative_path: String,
+        depth: usize,
+        dir: Option<SmbWalkEntry>,
+    ) -> SmbResult<()> {
+        let mut entries = self.walk.client.list_dirplus(&path).map_err(|err| {
+            error!("failed to read directory {}: {}", path, err);
+            err
+        })?;
+        if let Some(sort_by) = self.walk.sort_by.as_mut() {
+            entries.sort_by(|a, b| sort_by(a, b));
+        }
+        self.stack.push(Frame {
+            path,
+            relative_path,
+            depth,
+            entries: entries.into_iter(),
+            dir,
+        });
+        Ok(())
+    }
+
+    /// Returns whether `entry` must be yielded
+    fn is_yielded(&self, entry: &SmbWalkEntry) -> bool {
+        self.matcher
+            .as_ref()
+            .map(|m| m.is_yielded(entry))
+            .unwrap_or(false)
+    }
+
+    /// Handle a directory which couldn't be read according to the error policy
+    fn on_error(&mut self, err: SmbError) -> SmbResult<SmbWalkEntry> {
+        if self.walk.error_policy == SmbWalkErrorPolicy::Abort {
+            self.stack.clear();
+        }
+        Err(err)
+    }
+}
+
+impl Iterator for SmbWalkIter<'_> {
+    type Item = SmbResult<SmbWalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.pending.take() {
+            return Some(item);
+        }
+        if !self.started {
+            self.started = true;
+            // patterns failed to compile
+            self.matcher.as_ref()?;
+            let root = self.walk.root.clone();
+            if let Err(err) = self.push_dir(root, String::default(), 1, None) {
+                return Some(self.on_error(err));
+            }
+        }
+        loop {
+            let frame = self.stack.last_mut()?;
+            let info = match frame.entries.next() {
+                Some(info) => info,
+                None => {
+                    if let Some(dir) = self.stack.pop().and_then(|frame| frame.dir) {
+                        if self.is_yielded(&dir) {
+                            return Some(Ok(dir));
+                        }
+                    }
+                    continue;
+                }
+            };
+            let entry = SmbWalkEntry::new(&frame.path, &frame.relative_path, frame.depth, info);
+            let matcher = self.matcher.as_ref()?;
+            if matcher.is_pruned(&entry) {
+                trace!("skipping {}", entry.path);
+                continue;
+            }
+            if matcher.descends(&entry) {
+                let (path, relative_path, depth) =
+                    (entry.path.clone(), entry.relative_path.clone(), entry.depth);
+                let dir = match self.walk.contents_first {
+                    true => Some(entry.clone()),
+                    false => None,
+                };
+                match self.push_dir(path, relative_path, depth + 1, dir) {
+                    Ok(()) if self.walk.contents_first => continue,
+                    Ok(()) => {}
+                    Err(err) => {
+                        let err = self.on_error(err);
+                        if !self.is_yielded(&entry) {
+                            return Some(err);
+                        }
+                        // the directory is yielded anyway, before or after its error
+                        return match self.walk.contents_first {
+                            true if self.walk.error_policy == SmbWalkErrorPolicy::Yield => {
+                                self.pending = Some(Ok(entry));
+                                Some(err)
+                            }
+                            true => Some(err),
+                            false => {
+                                self.pending = Some(err);
+                                Some(Ok(entry))
+                            }
+                        };
+                    }
+                }
+            }
+            if self.is_yielded(&entry) {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test::TestCtx;
+    use crate::{mock, SmbMode, SmbOpenOptions};
+
+    #[test]
+    #[serial]
+    fn should_walk_tree_in_pre_order() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let entries: Vec<(String, usize)> = ctx
+            .client
+            .walk("/cargo-test/tree")
+            .sort_by_name()
+            .into_iter()
+            .map(|e| e.unwrap())
+            .map(|e| (e.relative_path().to_string(), e.depth()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (String::from("a.txt"), 1),
+                (String::from("b.md"), 1),
+                (String::from("sub"), 1),
+                (String::from("sub/c.txt"), 2),
+                (String::from("sub/deep"), 2),
+                (String::from("sub/deep/d.txt"), 3),
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_walk_tree_contents_first() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let entries: Vec<String> = ctx
+            .client
+            .walk("/cargo-test/tree")
+            .sort_by_name()
+            .contents_first(true)
+            .into_iter()
+            .map(|e| e.unwrap().path().to_string())
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                String::from("/cargo-test/tree/a.txt"),
+                String::from("/cargo-test/tree/b.md"),
+                String::from("/cargo-test/tree/sub/c.txt"),
+                String::from("/cargo-test/tree/sub/deep/d.txt"),
+                String::from("/cargo-test/tree/sub/deep"),
+                String::from("/cargo-test/tree/sub"),
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_walk_tree_with_depth_limits() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let entries: Vec<String> = ctx
+            .client
+            .walk("/cargo-test/tree")
+            .sort_by_name()
+            .min_depth(2)
+            .max_depth(2)
+            .into_iter()
+            .map(|e| e.unwrap().relative_path().to_string())
+            .collect();
+        assert_eq!(
+            entries,
+            vec![String::from("sub/c.txt"), String::from("sub/deep")]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_walk_tree_with_patterns() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        let entries: Vec<String> = ctx
+            .client
+            .walk("/cargo-test/tree")
+            .sort_by_name()
+            .include("*.txt")
+            .exclude("deep/")
+            .into_iter()
+            .map(|e| e.unwrap().relative_path().to_string())
+            .collect();
+        assert_eq!(
+            entries,
+            vec![String::from("a.txt"), String::from("sub/c.txt")]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn should_skip_hidden_entries() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        make_tree(&ctx.client);
+        // 0x02: hidden
+        if ctx
+            .client
+            .set_dos_attributes("/cargo-test/tree/sub", 0x12)
+            .is_err()
+        {
+            return;
+        }
+        let entries: Vec<String> = ctx
+            .client
+            .walk("/cargo-test/tree")
+            .sort_by_name()
+            .skip_hidden(true)
+            .into_iter()
+            .map(|e| e.unwrap().relative_path().to_string())
+            .collect();
+        assert_eq!(entries, vec![String::from("a.txt"), String::from("b.md")]);
+    }
+
+    #[test]
+    #[serial]
+    fn should_abort_or_yield_errors() {
+        mock::logger();
+        let ctx = TestCtx::default();
+        let mut iter = ctx.client.walk("/cargo-test/nope").into_iter();
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+
+        let mut iter = ctx
+            .client
+            .walk("/cargo-test")
+            .include("a[b")
+            .error_policy(SmbWalkErrorPolicy::Yield)
+            .into_iter();
+        assert!(matches!(iter.next(), Some(Err(SmbError::BadPattern(_)))));
+        assert!(iter.next().is_none());
+    }
+
+    fn make_tree(client: &SmbClient) {
+        for dir in [
+            "/cargo-test/tree",
+            "/cargo-test/tree/sub",
+            "/cargo-test/tree/sub/deep",
+        ] {
+            client.mkdir(dir, SmbMode::from(0o755)).unwrap();
+        }
+        for file in [
+            "/cargo-test/tree/a.txt",
+            "/cargo-test/tree/b.md",
+            "/cargo-test/tree/sub/c.txt",
+            "/cargo-test/tree/sub/deep/d.txt",
+        ] {
+            let mut reader = Cursor::new("Hello, World!\n".as_bytes());
+            let mut writer = client
+                .open_with(file, SmbOpenOptions::default().create(true).write(true))
+                .unwrap();
+            assert!(std::io::copy(&mut reader, &mut writer).is_ok());
+        }
+    }
+}
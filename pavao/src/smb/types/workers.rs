@@ -0,0 +1,64 @@
+//! # Workers
+//!
+//! module which exposes the connection settings shared by the workers of the parallel operations
+
+use super::{SmbCredentials, SmbOptions};
+use crate::{SmbClient, SmbResult};
+
+/// Default amount of workers
+pub(crate) const DEFAULT_WORKERS: usize = 4;
+
+/// Workers of a parallel operation.
+///
+/// Each worker thread opens its own connection with the provided credentials and options,
+/// so the operations of the workers aren't serialized by the lock of a single context.
+#[derive(Debug, Clone)]
+pub(crate) struct Workers {
+    credentials: SmbCredentials,
+    options: SmbOptions,
+    amount: usize,
+}
+
+impl Workers {
+    /// Initialize workers which connect with the provided credentials and options
+    pub fn new(credentials: SmbCredentials, options: SmbOptions) -> Self {
+        Self {
+            credentials,
+            options,
+            amount: DEFAULT_WORKERS,
+        }
+    }
+
+    /// Get the amount of workers
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+
+    /// Set the amount of workers; at least one is kept
+    pub fn set_amount(&mut self, amount: usize) {
+        self.amount = amount.max(1);
+    }
+
+    /// Open a new connection for a worker
+    pub fn connect(&self) -> SmbResult<SmbClient> {
+        SmbClient::new(self.credentials.clone(), self.options.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn should_set_amount_of_workers() {
+        let mut workers = Workers::new(SmbCredentials::default(), SmbOptions::default());
+        assert_eq!(workers.amount(), DEFAULT_WORKERS);
+        workers.set_amount(8);
+        assert_eq!(workers.amount(), 8);
+        workers.set_amount(0);
+        assert_eq!(workers.amount(), 1);
+    }
+}
@@ -6,9 +6,22 @@ use crate::{SmbClient, SmbCredentials, SmbMode, SmbOptions};
 
 pub struct TestCtx {
     pub client: SmbClient,
+    url: String,
     _container: SambaContainer,
 }
 
+impl TestCtx {
+    /// Credentials to connect to the test server
+    pub fn credentials(&self) -> SmbCredentials {
+        SmbCredentials::default()
+            .server(&self.url)
+            .share("/temp")
+            .username("test")
+            .password("test")
+            .workgroup("pavao")
+    }
+}
+
 impl Default for TestCtx {
     fn default() -> Self {
         let _ = env_logger::builder()
@@ -41,6 +54,7 @@ impl Default for TestCtx {
 
         TestCtx {
             client,
+            url,
             _container: container,
         }
     }
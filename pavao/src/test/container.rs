@@ -27,6 +27,8 @@ impl Image for SambaImage {
             "-p",
             "-s",
             "temp;/mnt/tmp;yes;no;yes;test;test",
+            "-s",
+            "other;/mnt/other;yes;no;yes;test;test",
             "-w",
             "pavao",
         ]
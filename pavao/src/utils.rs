@@ -4,10 +4,11 @@
 
 use std::borrow::Cow;
 use std::ffi::{CStr, CString};
-use std::io::{self, Write};
+use std::io;
 use std::slice;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use libc::{c_char, c_int};
+use libc::{c_char, c_int, suseconds_t, time_t, timeval};
 
 use super::SmbResult;
 use crate::SmbError;
@@ -23,21 +24,24 @@ pub fn result_from_ptr_mut<T>(ptr: *mut T) -> io::Result<*mut T> {
 }
 
 pub unsafe fn cstr<'a, T>(p: *const T) -> Cow<'a, str> {
+    if p.is_null() {
+        return Cow::Borrowed("");
+    }
     CStr::from_ptr(p as *const c_char).to_string_lossy()
 }
 
 pub unsafe fn write_to_cstr(dest: *mut u8, len: usize, src: &str) {
+    if dest.is_null() || len == 0 {
+        return;
+    }
     // just to ensure that it can be interpreted as c string
     *dest.add(len - 1) = 0u8;
     trace!("orig: {:?}", cstr(dest));
 
-    let mut buf = slice::from_raw_parts_mut(dest, len);
-    let mut idx = buf.write(src.as_bytes()).unwrap();
-
-    if idx == len {
-        idx -= 1;
-    }
-    buf = slice::from_raw_parts_mut(dest, len);
+    let buf = slice::from_raw_parts_mut(dest, len);
+    // leave room for the terminator
+    let idx = src.len().min(len - 1);
+    buf[..idx].copy_from_slice(&src.as_bytes()[..idx]);
     buf[idx] = 0u8;
 
     trace!(
@@ -107,6 +111,24 @@ pub fn char_ptr_to_string(ptr: *const c_char) -> SmbResult<String> {
         .map_err(|_| SmbError::BadValue)
 }
 
+/// Join `name` to the remote `path`
+pub fn join_path(path: &str, name: &str) -> String {
+    format!(
+        "{}/{}",
+        path.trim_end_matches('/'),
+        name.trim_start_matches('/')
+    )
+}
+
+/// Convert system time to timeval
+pub fn system_time_to_timeval(t: SystemTime) -> timeval {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    timeval {
+        tv_sec: d.as_secs() as time_t,
+        tv_usec: d.subsec_micros() as suseconds_t,
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -128,4 +150,33 @@ mod test {
             "Hello, World!"
         );
     }
+
+    #[test]
+    fn should_write_to_cstr() {
+        let mut buf = [0xffu8; 6];
+        unsafe { write_to_cstr(buf.as_mut_ptr(), buf.len(), "foo") };
+        assert_eq!(&buf[..4], b"foo\0");
+        unsafe { write_to_cstr(buf.as_mut_ptr(), buf.len(), "foobarbaz") };
+        assert_eq!(&buf, b"fooba\0");
+        // nothing to write to
+        unsafe { write_to_cstr(buf.as_mut_ptr(), 0, "foo") };
+        unsafe { write_to_cstr(std::ptr::null_mut(), 6, "foo") };
+        assert_eq!(unsafe { cstr(std::ptr::null::<c_char>()) }, "");
+    }
+
+    #[test]
+    fn should_join_path() {
+        assert_eq!(join_path("/", "test").as_str(), "/test");
+        assert_eq!(join_path("/foo", "bar").as_str(), "/foo/bar");
+        assert_eq!(join_path("/foo/", "/bar").as_str(), "/foo/bar");
+        assert_eq!(join_path("", "bar").as_str(), "/bar");
+    }
+
+    #[test]
+    fn should_convert_system_time_to_timeval() {
+        let t = UNIX_EPOCH + std::time::Duration::from_micros(1_500_000);
+        let tv = system_time_to_timeval(t);
+        assert_eq!(tv.tv_sec, 1);
+        assert_eq!(tv.tv_usec, 500_000);
+    }
 }